@@ -1,33 +1,41 @@
-use crate::tweaks::Tweak;
+use crate::catalog;
+use crate::brew::{self, Package, PackageKind};
+use crate::history::{History, HistoryAction, HistoryEntry, OutcomeFilter};
+use crate::batch::{self, BatchReport, BatchRun, BatchStep};
+use crate::drift::{self, DriftState};
+use crate::profile::{Profile, ProfileTweak};
+use crate::snapshot::{self, Snapshot, SnapshotStore};
+use crate::param;
+use crate::probe::{self, ProbeState};
+use crate::search::{self, SearchHit};
+use crate::runner::{self, CommandOutput, CommandRequest, CommandRunner, RunningCommand, ScriptedRunner, StreamEvent};
+use crate::tweaks::{self, BuiltinKind, ListSelectAction, ListSource, NodeId, Tweak, TweakAction, TweakKind, TweakTree};
 use crate::utils;
-use crate::utils::execute_command;
 use crate::config::Config;
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
 use ratatui::backend::Backend;
 use ratatui::Terminal;
-use serde::{Deserialize, Serialize};
-use ratatui::widgets::ListState;
-use std::collections::VecDeque;
+use ratatui::widgets::{ListState, TableState};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::PathBuf;
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 pub fn get_app_version() -> &'static str {
     env!("CARGO_PKG_VERSION")
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct TopLevelCategory {
-    pub name: String,
-    pub description: String,
-    pub tweaks: Vec<Tweak>,
+pub fn version_message() -> String {
+    format!("macOS Tweaks v{}", get_app_version())
 }
 
-impl TopLevelCategory {
-    fn new(name: &str, description: &str, tweaks: Vec<Tweak>) -> Self {
-        Self {
-            name: name.to_string(),
-            description: description.to_string(),
-            tweaks,
-        }
+pub fn check_brew_message(runner: &dyn CommandRunner) -> &'static str {
+    if utils::check_command_exists(runner, "brew") {
+        "Homebrew is installed and available in your PATH."
+    } else {
+        "Homebrew is not installed or not in your PATH."
     }
 }
 
@@ -53,7 +61,7 @@ impl SokobanGame {
     pub fn new() -> Self {
         // A new level with a bit more space
    // The level from the image
-   let level_layout = vec![
+   let level_layout = [
     "  ########",
     "  #..    #",
     "  #@$    #",
@@ -149,569 +157,1635 @@ impl SokobanGame {
     }
 }
 
-#[derive(Debug, Clone)]
+/// What the text prompt is asking for when it is not a tweak's value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProfilePrompt {
+    Save,
+    Compare,
+}
+
+/// State of the TUI history view.
+#[derive(Debug)]
+pub struct HistoryView {
+    pub entries: Vec<HistoryEntry>, // Newest first
+    pub categories: Vec<String>, // Category ids that appear in `entries`
+    pub category: Option<usize>, // Index into `categories`; `None` shows all
+    pub outcome: OutcomeFilter,
+    pub list_state: ListState,
+}
+
+impl HistoryView {
+    pub fn new(mut entries: Vec<HistoryEntry>) -> Self {
+        entries.reverse();
+        let mut categories: Vec<String> = Vec::new();
+        for entry in &entries {
+            if !categories.iter().any(|c| c == entry.category()) {
+                categories.push(entry.category().to_string());
+            }
+        }
+        let mut list_state = ListState::default();
+        list_state.select(Some(0));
+        HistoryView {
+            entries,
+            categories,
+            category: None,
+            outcome: OutcomeFilter::All,
+            list_state,
+        }
+    }
+
+    /// Entries that pass the current filters.
+    pub fn visible(&self) -> Vec<&HistoryEntry> {
+        let category = self.category.map(|index| self.categories[index].as_str());
+        self.entries
+            .iter()
+            .filter(|entry| category.is_none_or(|c| entry.category() == c))
+            .filter(|entry| self.outcome.matches(entry))
+            .collect()
+    }
+
+    pub fn selected(&self) -> Option<&HistoryEntry> {
+        self.visible().get(self.list_state.selected()?).copied()
+    }
+
+    pub fn next_category(&mut self) {
+        self.category = match self.category {
+            None if !self.categories.is_empty() => Some(0),
+            Some(index) if index + 1 < self.categories.len() => Some(index + 1),
+            _ => None,
+        };
+        self.list_state.select(Some(0));
+    }
+
+    pub fn next_outcome(&mut self) {
+        self.outcome = self.outcome.next();
+        self.list_state.select(Some(0));
+    }
+
+    pub fn move_selection(&mut self, forward: bool) {
+        let count = self.visible().len();
+        if count == 0 {
+            return;
+        }
+        let index = self.list_state.selected().unwrap_or(0);
+        let index = if forward { (index + 1) % count } else { (index + count - 1) % count };
+        self.list_state.select(Some(index));
+    }
+}
+
+/// State of the TUI search mode.
+#[derive(Debug, Default)]
+pub struct SearchView {
+    pub query: String,
+    pub hits: Vec<SearchHit>,
+    pub list_state: ListState,
+}
+
+impl SearchView {
+    /// Re-runs the search after the query changed.
+    pub fn update(&mut self, tree: &TweakTree) {
+        self.hits = search::search(tree, &self.query);
+        self.list_state.select((!self.hits.is_empty()).then_some(0));
+    }
+
+    pub fn selected(&self) -> Option<NodeId> {
+        self.hits.get(self.list_state.selected()?).map(|hit| hit.node)
+    }
+
+    pub fn move_selection(&mut self, forward: bool) {
+        let count = self.hits.len();
+        if count == 0 {
+            return;
+        }
+        let index = self.list_state.selected().unwrap_or(0);
+        let index = if forward { (index + 1) % count } else { (index + count - 1) % count };
+        self.list_state.select(Some(index));
+    }
+}
+
+/// How the commands in an [`OutputView`] ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutputOutcome {
+    Succeeded,
+    Failed(String),
+    Canceled,
+    /// Killed for running past the timeout.
+    TimedOut(Duration),
+}
+
+/// A tweak's commands running in the background, with everything they
+/// have printed so far. Kept once they finish so the output can be read.
+#[derive(Debug)]
+pub struct OutputView {
+    pub tweak: Tweak, // The tweak the running command is for
+    pub action: HistoryAction,
+    pub label: String, // Tweak name, with the value it is set to
+    pub command: String, // The command running, or the last one that ran
+    pub lines: Vec<(String, bool)>, // Output lines, flagged when from stderr
+    pub scroll_back: usize, // Lines scrolled up from the end; 0 follows new output
+    pub started: Instant,
+    pub finished: Option<(OutputOutcome, Duration)>,
+    canceled: bool,
+    running: Option<(CommandRequest, RunningCommand)>,
+    pending: VecDeque<CommandRequest>,
+    snapshot: Result<Option<Snapshot>>,
+    batch: Option<BatchRun>, // The batch the commands belong to, which picks the next one
+    package_operation: bool, // An upgrade, install or uninstall started from a package list
+}
+
+impl OutputView {
+    fn new(tweak: &Tweak, label: String, pending: VecDeque<CommandRequest>) -> Self {
+        OutputView {
+            tweak: tweak.clone(),
+            action: HistoryAction::Apply,
+            label,
+            command: String::new(),
+            lines: Vec::new(),
+            scroll_back: 0,
+            started: Instant::now(),
+            finished: None,
+            canceled: false,
+            running: None,
+            pending,
+            snapshot: Ok(None),
+            batch: None,
+            package_operation: false,
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.finished.is_none()
+    }
+
+    /// Time spent so far, or in total once finished.
+    pub fn elapsed(&self) -> Duration {
+        self.finished.as_ref().map_or_else(|| self.started.elapsed(), |(_, elapsed)| *elapsed)
+    }
+
+    pub fn scroll(&mut self, lines: isize) {
+        let max = self.lines.len().saturating_sub(1);
+        self.scroll_back = self.scroll_back.saturating_add_signed(lines).min(max);
+    }
+
+    /// Kills the running command and skips the ones after it.
+    pub fn cancel(&mut self) {
+        if let Some((_, command)) = &mut self.running {
+            self.canceled = true;
+            command.cancel();
+        }
+    }
+
+    fn start(&mut self, runner: &dyn CommandRunner, request: CommandRequest) {
+        self.command = request.display();
+        self.running = Some((request.clone(), runner.stream(&request)));
+    }
+
+    /// Collects new output, returning the command that ran and its result
+    /// once it has finished.
+    fn poll(&mut self) -> Option<(CommandRequest, Result<CommandOutput>)> {
+        let (_, command) = self.running.as_ref()?;
+        let mut result = None;
+        for event in command.poll() {
+            match event {
+                StreamEvent::Line { text, stderr } => self.lines.push((text, stderr)),
+                StreamEvent::Finished(finished) => result = Some(finished),
+            }
+        }
+        let result = result?;
+        let (request, _) = self.running.take()?;
+        Some((request, result))
+    }
+}
+
+/// What can be done to packages in a [`PackageList`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PackageOperation {
+    Upgrade,
+    Install,
+    Uninstall,
+}
+
+impl PackageOperation {
+    /// The `brew` subcommand, which is also the verb shown to the user.
+    pub fn verb(&self) -> &'static str {
+        match self {
+            PackageOperation::Upgrade => "upgrade",
+            PackageOperation::Install => "install",
+            PackageOperation::Uninstall => "uninstall",
+        }
+    }
+
+    fn running_label(&self) -> &'static str {
+        match self {
+            PackageOperation::Upgrade => "upgrading",
+            PackageOperation::Install => "installing",
+            PackageOperation::Uninstall => "uninstalling",
+        }
+    }
+
+    fn done_label(&self) -> &'static str {
+        match self {
+            PackageOperation::Upgrade => "upgraded",
+            PackageOperation::Install => "installed",
+            PackageOperation::Uninstall => "uninstalled",
+        }
+    }
+
+    /// Whether `package` is something the operation can be run on.
+    pub fn applies_to(&self, package: &Package) -> bool {
+        match self {
+            PackageOperation::Upgrade => !package.pinned,
+            PackageOperation::Install => package.installed.is_empty(),
+            PackageOperation::Uninstall => !package.installed.is_empty(),
+        }
+    }
+
+    /// The `brew` commands for `packages`. Upgrades run as one command, as
+    /// brew works out formulae and casks itself; installs and uninstalls
+    /// run once for formulae and once for casks.
+    fn requests(&self, packages: &[Package]) -> Vec<CommandRequest> {
+        let names = |kind: Option<PackageKind>| -> Vec<String> {
+            packages
+                .iter()
+                .filter(|p| kind.is_none_or(|kind| p.kind == kind))
+                .map(|p| p.full_name.clone())
+                .collect()
+        };
+        if *self == PackageOperation::Upgrade {
+            return vec![CommandRequest::exec("brew", [self.verb().to_string()].into_iter().chain(names(None)))];
+        }
+        [(PackageKind::Formula, "--formula"), (PackageKind::Cask, "--cask")]
+            .into_iter()
+            .map(|(kind, flag)| (flag, names(Some(kind))))
+            .filter(|(_, names)| !names.is_empty())
+            .map(|(flag, names)| CommandRequest::exec("brew", [self.verb().to_string(), flag.to_string()].into_iter().chain(names)))
+            .collect()
+    }
+}
+
+/// Where a package is in an upgrade, install or uninstall.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PackageState {
+    Waiting,
+    Running,
+    Done,
+    Failed,
+}
+
+impl PackageState {
+    pub fn label(&self, operation: PackageOperation) -> String {
+        match self {
+            PackageState::Waiting => "waiting".to_string(),
+            PackageState::Running => format!("{}…", operation.running_label()),
+            PackageState::Done => format!("✓ {}", operation.done_label()),
+            PackageState::Failed => "✗ failed".to_string(),
+        }
+    }
+}
+
+/// Homebrew packages listed by a list tweak, shown full screen. Outdated
+/// packages can be marked and upgraded together; searched ones can be
+/// installed or uninstalled.
+#[derive(Debug)]
+pub struct PackageList {
+    pub tweak: Tweak, // The list tweak, which commands run from the list are recorded under
+    pub source: ListSource,
+    pub on_select: ListSelectAction,
+    pub packages: Vec<Package>,
+    pub table_state: TableState,
+    pub query: Option<String>, // Search terms, for lists filled by searching
+    pub editing_query: bool, // Keys go to the query rather than the list
+    pub marked: HashSet<String>, // Full names of the marked packages
+    pub confirming: Option<(PackageOperation, Vec<Package>)>, // Operation waiting to be confirmed
+    pub operation: Option<PackageOperation>, // The operation running, or the last one that ran
+    pub progress: Vec<(String, PackageState)>, // Full name -> progress of `operation`
+    pub search: Option<PackageSearch>, // The search running for `query`
+}
+
+impl PackageList {
+    fn new(tweak: &Tweak, source: ListSource, on_select: ListSelectAction, packages: Vec<Package>) -> Self {
+        let mut table_state = TableState::default();
+        table_state.select((!packages.is_empty()).then_some(0));
+        let searches = source.searches();
+        PackageList {
+            tweak: tweak.clone(),
+            source,
+            on_select,
+            packages,
+            table_state,
+            query: searches.then(String::new),
+            editing_query: searches,
+            marked: HashSet::new(),
+            confirming: None,
+            operation: None,
+            progress: Vec::new(),
+            search: None,
+        }
+    }
+
+    /// Whether packages can be marked, which only makes sense for lists
+    /// that upgrade or install them.
+    pub fn multi_select(&self) -> bool {
+        matches!(self.on_select, ListSelectAction::BrewUpgrade | ListSelectAction::BrewInstall)
+    }
+
+    /// Pinned formulae are never upgraded, so they cannot be marked in an
+    /// upgrade list.
+    pub fn markable(&self, package: &Package) -> bool {
+        self.on_select != ListSelectAction::BrewUpgrade || !package.pinned
+    }
+
+    pub fn selected(&self) -> Option<&Package> {
+        self.table_state.selected().and_then(|index| self.packages.get(index))
+    }
+
+    pub fn move_selection(&mut self, forward: bool) {
+        let count = self.packages.len();
+        if count == 0 {
+            return;
+        }
+        let index = self.table_state.selected().unwrap_or(0);
+        let index = if forward { (index + 1) % count } else { (index + count - 1) % count };
+        self.table_state.select(Some(index));
+    }
+
+    /// Marks the highlighted package, or unmarks it.
+    pub fn toggle_mark(&mut self) {
+        let Some(package) = self.selected().filter(|package| self.markable(package)) else { return };
+        let name = package.full_name.clone();
+        if !self.marked.remove(&name) {
+            self.marked.insert(name);
+        }
+    }
+
+    pub fn mark_all(&mut self) {
+        self.marked = self.packages.iter().filter(|p| self.markable(p)).map(|p| p.full_name.clone()).collect();
+    }
+
+    pub fn invert_marks(&mut self) {
+        self.marked = self
+            .packages
+            .iter()
+            .filter(|p| self.markable(p) && !self.marked.contains(&p.full_name))
+            .map(|p| p.full_name.clone())
+            .collect();
+    }
+
+    /// The marked packages in list order, or the highlighted one when
+    /// nothing is marked, leaving out any `operation` does not apply to.
+    pub fn targets(&self, operation: PackageOperation) -> Vec<Package> {
+        let chosen: Vec<&Package> = if self.marked.is_empty() {
+            self.selected().into_iter().collect()
+        } else {
+            self.packages.iter().filter(|p| self.marked.contains(&p.full_name)).collect()
+        };
+        chosen.into_iter().filter(|p| operation.applies_to(p)).cloned().collect()
+    }
+
+    pub fn package_state(&self, package: &Package) -> Option<PackageState> {
+        self.progress.iter().find(|(name, _)| name == &package.full_name).map(|(_, state)| *state)
+    }
+
+    /// Follows brew's output: a `==> Upgrading <name>` line (or
+    /// `Installing`, `Uninstalling`, with `Cask` before casks) starts a
+    /// package and finishes the one before it, and an `Error:` line naming
+    /// a package means it failed.
+    fn track_progress(&mut self, lines: &[(String, bool)]) {
+        for (line, _) in lines {
+            let started = ["==> Upgrading ", "==> Installing ", "==> Uninstalling "]
+                .iter()
+                .find_map(|prefix| line.strip_prefix(prefix))
+                .map(|rest| rest.trim_start_matches("Cask ").split_whitespace().next().unwrap_or_default());
+            if let Some(name) = started {
+                let Some(index) = self.progress_index(name) else { continue };
+                for (_, state) in &mut self.progress {
+                    if *state == PackageState::Running {
+                        *state = PackageState::Done;
+                    }
+                }
+                self.progress[index].1 = PackageState::Running;
+            } else if line.starts_with("Error:") {
+                let words: Vec<&str> = line.split(|c: char| c.is_whitespace() || c == ':' || c == ',').collect();
+                for (name, state) in &mut self.progress {
+                    let short = name.rsplit('/').next().unwrap_or(name);
+                    if words.iter().any(|word| *word == name || *word == short) {
+                        *state = PackageState::Failed;
+                    }
+                }
+            }
+        }
+    }
+
+    fn progress_index(&self, name: &str) -> Option<usize> {
+        self.progress
+            .iter()
+            .position(|(full_name, _)| full_name == name || full_name.rsplit('/').next() == Some(name))
+    }
+
+    /// Settles every package once the commands have exited. `refreshed`
+    /// holds the packages as brew reports them now, when they could be read
+    /// again; whether each one reached the state the operation aims for
+    /// decides what failed. Otherwise anything not seen to finish failed
+    /// unless the commands succeeded. Failed packages stay marked so the
+    /// operation can be retried.
+    fn finish_operation(&mut self, succeeded: bool, refreshed: Option<Vec<Package>>) {
+        let Some(operation) = self.operation else { return };
+        for (name, state) in &mut self.progress {
+            *state = match &refreshed {
+                Some(refreshed) => {
+                    let now = refreshed.iter().find(|p| &p.full_name == name);
+                    let reached = match operation {
+                        PackageOperation::Upgrade => now.is_none_or(|p| !p.outdated),
+                        PackageOperation::Install => now.is_some_and(|p| !p.installed.is_empty()),
+                        PackageOperation::Uninstall => now.is_none_or(|p| p.installed.is_empty()),
+                    };
+                    if reached { PackageState::Done } else { PackageState::Failed }
+                }
+                None if succeeded && *state != PackageState::Failed => PackageState::Done,
+                None if *state == PackageState::Done => PackageState::Done,
+                None => PackageState::Failed,
+            };
+        }
+        if self.multi_select() {
+            self.marked = self
+                .progress
+                .iter()
+                .filter(|(_, state)| *state == PackageState::Failed)
+                .map(|(name, _)| name.clone())
+                .collect();
+        }
+        let Some(refreshed) = refreshed else { return };
+        // Keep the packages in view with their new state
+        for package in &mut self.packages {
+            match refreshed.iter().find(|p| p.full_name == package.full_name) {
+                Some(now) if operation != PackageOperation::Upgrade => *package = now.clone(),
+                Some(_) => {}
+                None if operation == PackageOperation::Upgrade => {
+                    package.outdated = false;
+                    if let Some(latest) = &package.latest {
+                        package.installed = vec![latest.clone()];
+                    }
+                }
+                None => {}
+            }
+        }
+    }
+}
+
+/// One command of a [`PackageSearch`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SearchStep {
+    Search(PackageKind),
+    Info(PackageKind),
+}
+
+impl SearchStep {
+    /// In the order they run, starting with the formula search.
+    const ALL: [SearchStep; 4] = [
+        SearchStep::Search(PackageKind::Formula),
+        SearchStep::Search(PackageKind::Cask),
+        SearchStep::Info(PackageKind::Formula),
+        SearchStep::Info(PackageKind::Cask),
+    ];
+}
+
+/// A search for a [`PackageList`] running in the background: `brew search`
+/// for formulae, then for casks, then `brew info` on what they found.
+#[derive(Debug)]
+pub struct PackageSearch {
+    pub query: String,
+    pub started: Instant,
+    step: SearchStep,
+    running: RunningCommand,
+    canceled: bool,
+    matches: Vec<Package>, // Found by brew search, without their details
+    found: Vec<Package>, // The matches with their details from brew info
+}
+
+impl PackageSearch {
+    /// Stops the search; the list keeps what it showed before.
+    pub fn cancel(&mut self) {
+        self.canceled = true;
+        self.running.cancel();
+    }
+
+    /// The `brew` arguments for `step`, or `None` when there is nothing to
+    /// look up.
+    fn args(&self, step: SearchStep) -> Option<Vec<String>> {
+        let flag = |kind| if kind == PackageKind::Formula { "--formula" } else { "--cask" };
+        match step {
+            SearchStep::Search(kind) => Some(["search", flag(kind), &self.query].map(String::from).to_vec()),
+            SearchStep::Info(kind) => {
+                let names: Vec<String> =
+                    self.matches.iter().filter(|p| p.kind == kind).map(|p| p.full_name.clone()).collect();
+                (!names.is_empty()).then(|| ["info", "--json=v2", flag(kind)].map(String::from).into_iter().chain(names).collect())
+            }
+        }
+    }
+}
+
+/// The names `brew search` printed. brew exits with an error when nothing
+/// matches, which is an empty result here rather than a failure.
+fn search_results(result: Result<CommandOutput>) -> Result<Vec<String>> {
+    let output = result?;
+    if output.status.is_some_and(|code| code != 0) && output.stderr.contains("No formulae or casks found") {
+        return Ok(Vec::new());
+    }
+    let stdout = output.into_result()?;
+    Ok(stdout
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with("==>"))
+        .map(String::from)
+        .collect())
+}
+
+#[derive(Debug)]
 pub struct App {
-    pub view_level: u8, // 0: Top-level Categories, 1: Sub-categories/Tweaks
-    pub selected_indices: [usize; 2], // [top_level_index, tweak_index]
-    pub category_list_state: ListState,
-    pub tweak_list_state: ListState,
-    pub viewing_sub_category: Option<String>,
+    pub current_parent: Option<NodeId>, // None: top-level categories
+    pub selection_stack: Vec<usize>, // Selected index at each ancestor level
+    pub list_state: ListState,
     pub should_quit: bool,
-    pub categories: Vec<TopLevelCategory>,
-    pub applied_tweaks: Vec<String>,
+    pub tree: TweakTree,
+    pub applied_tweaks: Vec<String>, // Tweak ids
+    pub last_applied: HashMap<String, DateTime<Utc>>, // Tweak id -> last successful apply
+    pub marked_tweaks: Vec<(String, Option<String>)>, // Tweak ids selected for a batch, in order, with their values
+    pub history: History,
+    pub history_view: Option<HistoryView>,
+    pub search: Option<SearchView>,
+    pub output_view: Option<OutputView>, // Commands running in the background
+    pub snapshots: SnapshotStore, // Prior defaults values of applied tweaks
+    pub probe_states: HashMap<String, ProbeState>, // Tweak id -> live state
+    pub probe_receiver: Option<Receiver<(String, ProbeState)>>,
+    pub runner: Arc<dyn CommandRunner>,
+    pub dry_run: bool,
+    pub allow_sudo: bool, // Tweaks that need sudo may run
+    pub assume_yes: bool, // The CLI applies destructive tweaks without asking
+    pub dry_run_log: Arc<ScriptedRunner>, // Records what a dry run would have executed
     pub status_message: Option<String>,
     pub status_timer: u32,
-    pub pending_destructive_command: Option<(String, String)>, // (tweak_name, command)
+    pub pending_destructive_command: Option<(Tweak, String)>,
     pub confirmation_message: Option<String>,
     pub text_input_prompt: Option<String>,
-    pub text_input_command_template: Option<String>,
+    pub text_input_tweak: Option<Tweak>, // Tweak whose value is being entered
+    pub text_input_error: Option<String>,
+    pub text_input_marks: bool, // The value entered is for the batch, not applied right away
+    pub profile_prompt: Option<ProfilePrompt>, // The text prompt asks for a profile name
+    pub drift_profile: Option<(String, Vec<BatchStep>)>, // Profile the tweaks are compared with
     pub input_buffer: String,
     pub fullscreen_output: Option<String>,
     pub fullscreen_output_scroll: u16,
     pub config: Config,
-    pub fullscreen_list: Option<Vec<String>>,
-    pub fullscreen_list_state: ListState,
-    pub fullscreen_list_title: String,
+    pub fullscreen_list: Option<PackageList>,
     pub sokoban_game: Option<SokobanGame>,
 }
 
 impl App {
-    pub fn new() -> App {
-        let config = Config::load();
-        
-        let dock_tweaks = vec![
-            Tweak::new("Dock Size", "Change the size of Dock icons", "", "", false),
-            Tweak::new("  Small (32px)", "Set Dock icon size to small", "defaults write com.apple.dock tilesize -int 32 && killall Dock", "", false),
-            Tweak::new("  Medium (48px)", "Set Dock icon size to medium", "defaults write com.apple.dock tilesize -int 48 && killall Dock", "", false),
-            Tweak::new("  Large (64px)", "Set Dock icon size to large", "defaults write com.apple.dock tilesize -int 64 && killall Dock", "", false),
-            Tweak::new("Dock Behavior", "Configure Dock behavior settings", "", "", false),
-            Tweak::new("  Disable Magnification", "Disable dock magnification effect", "defaults write com.apple.dock magnification -bool false && killall Dock", "", false),
-            Tweak::new("  Auto-hide Dock", "Auto-hide the dock", "defaults write com.apple.dock autohide -bool true && killall Dock", "", false),
-            Tweak::new("  Show Hidden Apps", "Show hidden applications in Dock", "defaults write com.apple.dock showhidden -bool true && killall Dock", "", false),
-            Tweak::new("  Disable App Bouncing", "Disable app icon bouncing", "defaults write com.apple.dock no-bouncing -bool true && killall Dock", "", false),
-            Tweak::new("Dock Spacers", "Manage Dock spacers and organization", "", "", false),
-            Tweak::new("  Add Small Spacer", "Add a small spacer tile to the Dock", r#"defaults write com.apple.dock persistent-apps -array-add '{"tile-type"="small-spacer-tile";}' && killall Dock"#, "", false),
-            Tweak::new("  Add Large Spacer", "Add a large spacer tile to the Dock", r#"defaults write com.apple.dock persistent-apps -array-add '{"tile-type"="spacer-tile";}' && killall Dock"#, "", false),
-            Tweak::new("  Remove All Spacers", "Remove all spacers from the Dock", "defaults write com.apple.dock persistent-apps -array '()' && killall Dock", "", false),
-            Tweak::new("Dock Position", "Change Dock position", "", "", false),
-            Tweak::new("  Position Left", "Move Dock to left side", "defaults write com.apple.dock orientation -string left && killall Dock", "", false),
-            Tweak::new("  Position Bottom", "Move Dock to bottom (default)", "defaults write com.apple.dock orientation -string bottom && killall Dock", "", false),
-            Tweak::new("  Position Right", "Move Dock to right side", "defaults write com.apple.dock orientation -string right && killall Dock", "", false),
-            Tweak::new("Reset Options", "Reset Dock to default settings", "", "", false),
-            Tweak::new("  Reset Dock to Default", "Reset Dock to its default settings", "defaults delete com.apple.dock && killall Dock", "", false),
-        ];
-        
-        let finder_tweaks = vec![
-            Tweak::new("Finder Appearance", "Customize Finder appearance", "", "", false),
-            Tweak::new("  Show Hidden Files", "Show hidden files in Finder", "defaults write com.apple.finder AppleShowAllFiles -bool true && killall Finder", "", false),
-            Tweak::new("  Hide Hidden Files", "Hide hidden files in Finder", "defaults write com.apple.finder AppleShowAllFiles -bool false && killall Finder", "", false),
-            Tweak::new("  Show Path Bar", "Show path bar at bottom of Finder windows", "defaults write com.apple.finder ShowPathbar -bool true && killall Finder", "", false),
-            Tweak::new("  Show Status Bar", "Show status bar at bottom of Finder windows", "defaults write com.apple.finder ShowStatusBar -bool true && killall Finder", "", false),
-            Tweak::new("  Show Sidebar", "Show sidebar in Finder windows", "defaults write com.apple.finder ShowSidebar -bool true && killall Finder", "", false),
-            Tweak::new("  Show Tab Bar", "Show tab bar in Finder windows", "defaults write com.apple.finder ShowTabView -bool true && killall Finder", "", false),
-            Tweak::new("Finder Behavior", "Configure Finder behavior", "", "", false),
-            Tweak::new("  Show All File Extensions", "Show file extensions for all files", "defaults write NSGlobalDomain AppleShowAllExtensions -bool true && killall Finder", "", false),
-            Tweak::new("  Disable .DS_Store Creation", "Prevent creation of .DS_Store files", "defaults write com.apple.desktopservices DSDontWriteNetworkStores -bool true", "", false),
-            Tweak::new("  Show Library Folder", "Show Library folder in user's home directory", "chflags nohidden ~/Library", "", false),
-            Tweak::new("  Hide Library Folder", "Hide Library folder in user's home directory", "chflags hidden ~/Library", "", false),
-            Tweak::new("  Enable Quit Option", "Enable Quit option in Finder menu", "defaults write com.apple.finder QuitMenuItem -bool true && killall Finder", "", false),
-        ];
-
-        let system_ui_tweaks = vec![
-            Tweak::new("Menu Bar", "Customize menu bar appearance", "", "", false),
-            Tweak::new("  Show Battery Percentage", "Show battery percentage in menu bar", "defaults write com.apple.menuextra.battery ShowPercent -string YES", "", false),
-            Tweak::new("  Hide Battery Percentage", "Hide battery percentage in menu bar", "defaults write com.apple.menuextra.battery ShowPercent -string NO", "", false),
-            Tweak::new("  Show Date in Menu Bar", "Show date in menu bar", "defaults write com.apple.menuextra.clock DateFormat -string 'EEE MMM d  h:mm a'", "", false),
-            Tweak::new("  Show Seconds in Clock", "Show seconds in menu bar clock", "defaults write com.apple.menuextra.clock ShowSeconds -bool true", "", false),
-            Tweak::new("  Hide Seconds in Clock", "Hide seconds in menu bar clock", "defaults write com.apple.menuextra.clock ShowSeconds -bool false", "", false),
-            Tweak::new("Desktop & Screensaver", "Customize desktop and screensaver", "", "", false),
-            Tweak::new("  Disable Screensaver", "Disable screensaver", "defaults -currentHost write com.apple.screensaver idleTime -int 0", "", false),
-            Tweak::new("  Set Screensaver to 5 minutes", "Set screensaver to activate after 5 minutes", "defaults -currentHost write com.apple.screensaver idleTime -int 300", "", false),
-            Tweak::new("  Set Screensaver to 10 minutes", "Set screensaver to activate after 10 minutes", "defaults -currentHost write com.apple.screensaver idleTime -int 600", "", false),
-            Tweak::new("  Disable Hot Corners", "Disable hot corners", "defaults write com.apple.dock wvous-tl -int 0 && defaults write com.apple.dock wvous-tr -int 0 && defaults write com.apple.dock wvous-bl -int 0 && defaults write com.apple.dock wvous-br -int 0 && killall Dock", "", false),
-            Tweak::new("Keyboard", "Customize keyboard settings", "", "", false),
-            Tweak::new("  Disable Caps Lock Delay", "Remove the delay when enabling Caps Lock", "hidutil property --set '{\"CapsLockDelayOverride\":0}'", "", false),
-            Tweak::new("  Set Custom Menu Bar Text", "Replace clock with custom text. You will be prompted for text.", "__PROMPT_FOR_TEXT__:defaults write com.apple.menuextra.clock DateFormat -string \"'{}'\"", "", false),
-            Tweak::new("  Reset Menu Bar Clock", "Restore the default clock display", "defaults delete com.apple.menuextra.clock DateFormat", "", false),
-        ];
-
-        let security_tweaks = vec![
-            Tweak::new("Gatekeeper", "Configure Gatekeeper security settings", "", "", false),
-            Tweak::new("  Disable Gatekeeper", "Disable Gatekeeper (allow apps from anywhere)", "sudo spctl --master-disable", "", false),
-            Tweak::new("  Enable Gatekeeper", "Enable Gatekeeper (default security)", "sudo spctl --master-enable", "", false),
-            Tweak::new("  Check Gatekeeper Status", "Check current Gatekeeper status", "spctl --status", "", false),
-            Tweak::new("Firewall", "Configure firewall settings", "", "", false),
-            Tweak::new("  Enable Firewall", "Enable macOS firewall", "sudo /usr/libexec/ApplicationFirewall/socketfilterfw --setglobalstate on", "", false),
-            Tweak::new("  Disable Firewall", "Disable macOS firewall", "sudo /usr/libexec/ApplicationFirewall/socketfilterfw --setglobalstate off", "", false),
-            Tweak::new("  Check Firewall Status", "Check firewall status", "sudo /usr/libexec/ApplicationFirewall/socketfilterfw --getglobalstate", "", false),
-            Tweak::new("Privacy Settings", "Configure privacy settings", "", "", false),
-            Tweak::new("  Disable Location Services", "Disable location services", "sudo defaults write /var/db/locationd/Library/Preferences/ByHost/com.apple.locationd LocationServicesEnabled -int 0", "", false),
-            Tweak::new("  Enable Location Services", "Enable location services", "sudo defaults write /var/db/locationd/Library/Preferences/ByHost/com.apple.locationd LocationServicesEnabled -int 1", "", false),
-            Tweak::new("  Disable Analytics", "Disable analytics and diagnostics", "defaults write com.apple.AnalyticsClient AnalyticsEnabled -bool false", "", false),
-            Tweak::new("  Enable Analytics", "Enable analytics and diagnostics", "defaults write com.apple.AnalyticsClient AnalyticsEnabled -bool true", "", false),
-        ];
-
-        let developer_tweaks = vec![
-            Tweak::new("Developer Tools", "Install and configure developer tools", "", "", false),
-            Tweak::new("  Install Xcode Command Line Tools", "Install Xcode command line tools", "xcode-select --install", "", false),
-            Tweak::new("  Check Xcode Tools Status", "Check if Xcode command line tools are installed", "xcode-select -p", "", false),
-            Tweak::new("  Accept Xcode License", "Accept Xcode license agreement", "sudo xcodebuild -license accept", "", false),
-            Tweak::new("  Reset Xcode Path", "Reset Xcode developer directory path", "sudo xcode-select --reset", "", false),
-            Tweak::new("Terminal Customization", "Customize terminal appearance", "", "", false),
-            Tweak::new("  Enable Terminal Colors", "Enable colors in terminal", "defaults write com.apple.Terminal 'Default Window Settings' -string 'Pro' && defaults write com.apple.Terminal 'Startup Window Settings' -string 'Pro'", "", false),
-            Tweak::new("  Set Terminal Font Size to 12", "Set terminal font size to 12", "defaults write com.apple.Terminal Pro -dict 'Font' -string 'SF Mono 12'", "", false),
-            Tweak::new("  Set Terminal Font Size to 14", "Set terminal font size to 14", "defaults write com.apple.Terminal Pro -dict 'Font' -string 'SF Mono 14'", "", false),
-            Tweak::new("  Set Terminal Font Size to 16", "Set terminal font size to 16", "defaults write com.apple.Terminal Pro -dict 'Font' -string 'SF Mono 16'", "", false),
-            Tweak::new("  Enable Terminal Transparency", "Enable transparency in terminal", "defaults write com.apple.Terminal Pro -dict 'Transparency' -float 0.8", "", false),
-            Tweak::new("Git Configuration", "Configure Git settings", "", "", false),
-            Tweak::new("  Set Git Global User", "Set Git global user name and email", "git config --global user.name 'Your Name' && git config --global user.email 'your.email@example.com'", "", false),
-            Tweak::new("  Configure Git Credentials", "Set up Git credential helper", "git config --global credential.helper osxkeychain", "", false),
-            Tweak::new("  Set Git Default Branch", "Set default branch name to main", "git config --global init.defaultBranch main", "", false),
-            Tweak::new("  Configure Git Aliases", "Set up useful Git aliases", "git config --global alias.st status && git config --global alias.co checkout && git config --global alias.br branch && git config --global alias.ci commit", "", false),
-        ];
-
-        let performance_tweaks = vec![
-            Tweak::new("Animation Settings", "Configure system animations", "", "", false),
-            Tweak::new("  Disable Window Animations", "Disable window animations", "defaults write NSGlobalDomain NSAutomaticWindowAnimationsEnabled -bool false", "", false),
-            Tweak::new("  Enable Window Animations", "Enable window animations", "defaults write NSGlobalDomain NSAutomaticWindowAnimationsEnabled -bool true", "", false),
-            Tweak::new("  Disable Dock Animations", "Disable dock animations", "defaults write com.apple.dock expose-animation-duration -float 0 && killall Dock", "", false),
-            Tweak::new("  Enable Dock Animations", "Enable dock animations", "defaults write com.apple.dock expose-animation-duration -float 0.1 && killall Dock", "", false),
-            Tweak::new("  Disable Menu Bar Animations", "Disable menu bar animations", "defaults write NSGlobalDomain NSWindowResizeTime -float 0.001", "", false),
-            Tweak::new("  Enable Menu Bar Animations", "Enable menu bar animations", "defaults write NSGlobalDomain NSWindowResizeTime -float 0.2", "", false),
-        ];
-
-        let animated_wallpapers_tweaks = vec![
-            Tweak::new("Video Wallpaper (mpv)", "Set a video as your wallpaper (requires mpv)", "", "", false),
-            Tweak::new("  Play video as wallpaper (experimental)", "Play ~/Movies/wallpaper.mp4 as wallpaper (requires mpv)", "mpv --wid=$(osascript -e 'tell application \"Finder\" to get id of window 1') --loop --no-border --geometry=100%:100% --panscan=1.0 --no-osc --no-input-default-bindings --no-audio ~/Movies/wallpaper.mp4", "", false),
-        ];
-
-        let power_management_tweaks = vec![
-            Tweak::new("Computer Sleep", "Adjust computer sleep settings", "", "", false),
-            Tweak::new("  Never", "Prevent computer from sleeping", "sudo systemsetup -setcomputersleep Never", "", false),
-            Tweak::new("  15 minutes (Default)", "Set computer sleep timer to 15 minutes", "sudo systemsetup -setcomputersleep 15", "", false),
-            Tweak::new("  30 minutes", "Set computer sleep timer to 30 minutes", "sudo systemsetup -setcomputersleep 30", "", false),
-            Tweak::new("  1 hour", "Set computer sleep timer to 60 minutes", "sudo systemsetup -setcomputersleep 60", "", false),
-            Tweak::new("Display Sleep", "Adjust display sleep settings", "", "", false),
-            Tweak::new("  5 minutes", "Set display sleep timer to 5 minutes", "sudo systemsetup -setdisplaysleep 5", "", false),
-            Tweak::new("  10 minutes (Default)", "Set display sleep timer to 10 minutes", "sudo systemsetup -setdisplaysleep 10", "", false),
-            Tweak::new("  15 minutes", "Set display sleep timer to 15 minutes", "sudo systemsetup -setdisplaysleep 15", "", false),
-            Tweak::new("  Never", "Prevent display from sleeping", "sudo systemsetup -setdisplaysleep Never", "", false),
-            Tweak::new("Wake Settings", "Configure wake behavior", "", "", false),
-            Tweak::new("  Enable Wake on Network", "Enable wake on network access", "sudo systemsetup -setwakeonnetworkaccess on", "", false),
-            Tweak::new("  Disable Wake on Network", "Disable wake on network access", "sudo systemsetup -setwakeonnetworkaccess off", "", false),
-            Tweak::new("  Enable Wake on Modem", "Enable wake on modem ring", "sudo systemsetup -setwakeonmodem on", "", false),
-            Tweak::new("  Disable Wake on Modem", "Disable wake on modem ring", "sudo systemsetup -setwakeonmodem off", "", false),
-        ];
-
-        let network_tweaks = vec![
-            Tweak::new("DNS Management", "Manage DNS settings", "", "", false),
-            Tweak::new("  Flush DNS Cache", "Removes all entries from the DNS cache", "sudo dscacheutil -flushcache; sudo killall -HUP mDNSResponder", "", false),
-            Tweak::new("  Set DNS to Google", "Set DNS servers to Google (8.8.8.8, 8.8.4.4)", "networksetup -setdnsservers Wi-Fi 8.8.8.8 8.8.4.4", "", false),
-            Tweak::new("  Set DNS to Cloudflare", "Set DNS servers to Cloudflare (1.1.1.1, 1.0.0.1)", "networksetup -setdnsservers Wi-Fi 1.1.1.1 1.0.0.1", "", false),
-            Tweak::new("  Reset DNS to DHCP", "Reset DNS to use DHCP", "networksetup -setdnsservers Wi-Fi empty", "", false),
-            Tweak::new("Network Interfaces", "Configure network interfaces", "", "", false),
-            Tweak::new("  Enable Wi-Fi", "Enable Wi-Fi interface", "networksetup -setairportpower en0 on", "", false),
-            Tweak::new("  Disable Wi-Fi", "Disable Wi-Fi interface", "networksetup -setairportpower en0 off", "", false),
-            Tweak::new("  Enable Bluetooth", "Enable Bluetooth", "sudo pkill bluetoothd", "", false),
-            Tweak::new("  Disable Bluetooth", "Disable Bluetooth", "sudo pkill bluetoothd", "", false),
-            Tweak::new("  Show Network Info", "Show detailed network information", "networksetup -listallnetworkservices && echo '---' && ifconfig", "", false),
-        ];
-
-        let optimization_tweaks = vec![
-            Tweak::new("Clean Up Caches", "Remove temporary cache files", "", "", false),
-            Tweak::new("  Clear User Cache (destructive)", "Removes all files from ~/Library/Caches", "rm -rf ~/Library/Caches/*", "", false),
-            Tweak::new("  Clear System Cache (destructive)", "Removes all files from /Library/Caches", "sudo rm -rf /Library/Caches/*", "", false),
-            Tweak::new("  Clear Launch Services Cache", "Clear Launch Services cache", "sudo rm -rf /System/Library/Caches/com.apple.LaunchServices-*.csstore", "", false),
-            Tweak::new("  Clear Xcode Derived Data", "Clear Xcode derived data (if Xcode is installed)", "rm -rf ~/Library/Developer/Xcode/DerivedData", "", false),
-
-            Tweak::new("Organize Desktop", "Move files from Desktop to organized folders", "", "", false),
-            Tweak::new("  Move screenshots to Pictures folder", "Finds all screenshots on Desktop and moves them to ~/Pictures/Screenshots", "mkdir -p ~/Pictures/Screenshots && find ~/Desktop -maxdepth 1 \\( -name 'Screen Shot*.png' -o -name 'Screenshot*.png' \\) -exec mv -n {} ~/Pictures/Screenshots/ \\;", "", false),
-            Tweak::new("  Move project folders to ~/Developer", "Moves folders with .git, .gitignore, or source code", "zsh scripts/organize_projects.sh", "", false),
-            Tweak::new("  Move images to ~/Pictures", "Moves common image files from Desktop to Pictures", "find ~/Desktop -maxdepth 1 -type f \\( -iname '*.png' -o -iname '*.jpg' -o -iname '*.jpeg' -o -iname '*.gif' \\) -exec mv -n {} ~/Pictures/ \\;", "", false),
-            Tweak::new("  Move videos to ~/Movies", "Moves common video files from Desktop to Movies", "find ~/Desktop -maxdepth 1 -type f \\( -iname '*.mov' -o -iname '*.mp4' \\) -exec mv -n {} ~/Movies/ \\;", "", false),
-            Tweak::new("  Move documents to ~/Documents", "Moves common document files from Desktop to Documents", "find ~/Desktop -maxdepth 1 -type f \\( -iname '*.pdf' -o -iname '*.docx' \\) -exec mv -n {} ~/Documents/ \\;", "", false),
-
-            Tweak::new("Find Large Files", "Identify large files to free up space", "", "", false),
-            Tweak::new("  List 10 largest files in Home", "Shows a list of the 10 biggest files in your home directory.", "echo 'Large files in home directory:' && ls -lah ~ | grep -v '^d' | sort -k5 -hr | head -n 10", "", false),
-            Tweak::new("  Find files larger than 100MB", "Find all files larger than 100MB in home directory", "find ~ -type f -size +100M -exec ls -lh {} \\; 2>/dev/null", "", false),
-            Tweak::new("  Find files larger than 1GB", "Find all files larger than 1GB in home directory", "find ~ -type f -size +1G -exec ls -lh {} \\; 2>/dev/null", "", false),
-            
-            Tweak::new("System Maintenance", "Perform system maintenance tasks", "", "", false),
-            Tweak::new("  Repair Disk Permissions", "Repair disk permissions", "sudo diskutil resetUserPermissions / `id -u`", "", false),
-            Tweak::new("  Clear System Logs", "Clear system logs (requires admin)", "sudo rm -rf /var/log/*.log", "", false),
-            Tweak::new("  Clear User Logs", "Clear user logs", "rm -rf ~/Library/Logs/*", "", false),
-            Tweak::new("  Rebuild Spotlight Index", "Rebuild Spotlight search index", "sudo mdutil -E /", "", false),
-        ];
-
-        let brew_tweaks = vec![
-            Tweak::new("Brew Installation", "Manage Homebrew installation", "", "", false),
-            Tweak::new("  Install Homebrew (interactive)", "Install Homebrew package manager", "curl -fsSL https://raw.githubusercontent.com/Homebrew/install/HEAD/install.sh | bash", "", false),
-            Tweak::new("  Uninstall Homebrew (destructive)", "Remove Homebrew and all packages (destructive)", "curl -fsSL https://raw.githubusercontent.com/Homebrew/install/HEAD/uninstall.sh | bash", "", false),
-            Tweak::new("  Check Homebrew Status", "Check if Homebrew is installed and working", "__CHECK_BREW__", "", false),
-            
-            Tweak::new("Brew Maintenance", "Maintain and update Homebrew", "", "", false),
-            Tweak::new("  Update Homebrew", "Update Homebrew and all packages", "brew update && brew upgrade", "", false),
-            Tweak::new("  Clean Up Homebrew", "Remove old versions and clean cache", "brew cleanup", "", false),
-            Tweak::new("  List Installed Packages", "View all installed Homebrew packages", "__LIST_INSTALLED__", "", false),
-            Tweak::new("  List Outdated Packages", "View packages that have updates available", "__LIST_OUTDATED__", "", false),
-            Tweak::new("  Upgrade Specific Package", "Upgrade a specific package", "brew upgrade [package_name]", "", false),
-            Tweak::new("  Install Common Dev Tools", "Install common development tools", "brew install git node python3 rust go", "", false),
-            
-            Tweak::new("Brew Analytics", "Manage Homebrew analytics", "", "", false),
-            Tweak::new("  Disable Analytics", "Disable Homebrew analytics collection", "brew analytics off", "", false),
-            Tweak::new("  Enable Analytics", "Enable Homebrew analytics collection", "brew analytics on", "", false),
-            Tweak::new("  Show Analytics Status", "Check if analytics are enabled", "brew analytics state", "", false),
-        ];
-
-        let about_tweaks = vec![
-            Tweak::new("Application Info", "Information about this application", "", "", false),
-            Tweak::new("  Version", "Show application version", "__SHOW_VERSION__", "", false),
-            Tweak::new("  About", "Show detailed information about the application", "echo 'macOS Tweaks - A terminal-based GUI for managing macOS system tweaks and optimizations.\\n\\nBuilt with Rust and Ratatui.\\n\\nFeatures:\\n- Tabbed interface with organized categories\\n- Interactive navigation\\n- Real-time status updates\\n- Customizable color schemes\\n- Safe system modifications\\n\\nAuthor: Doruk Sarp Aydın\\nLicense: MIT'", "", false),
-            Tweak::new("  System Information", "Show system information", "sw_vers && echo '\\n---\\n' && system_profiler SPHardwareDataType | grep -E '(Model Name|Model Identifier|Processor|Memory|Serial Number)'", "", false),
-            Tweak::new("  Dependencies", "Show application dependencies", "echo 'Dependencies:\\n- Rust (latest stable)\\n- ratatui (terminal UI framework)\\n- crossterm (terminal manipulation)\\n- serde (serialization)\\n- anyhow (error handling)'", "", false),
-            Tweak::new("  Sokoban Game", "Start the Sokoban puzzle game", "__SOKOBAN_GAME__", "", false),
-        ];
-
-        let utilities_tweaks = vec![
-            Tweak::new("System Information", "Get detailed system information", "", "", false),
-            Tweak::new("  Show Disk Usage", "Show disk usage information", "df -h", "", false),
-            Tweak::new("  Show Memory Usage", "Show memory usage information", "vm_stat", "", false),
-            Tweak::new("  Show CPU Info", "Show CPU information", "sysctl -n machdep.cpu.brand_string", "", false),
-            Tweak::new("  Show Network Interfaces", "Show network interface information", "ifconfig", "", false),
-            Tweak::new("  Show Running Processes", "Show top running processes", "ps aux | head -20", "", false),
-            Tweak::new("File & Directory", "Useful file and directory operations", "", "", false),
-            Tweak::new("  Count Files in Directory", "Count files in current directory", "ls -1 | wc -l", "", false),
-            Tweak::new("  Find Empty Files", "Find empty files in current directory", "find . -type f -empty", "", false),
-            Tweak::new("  Find Large Files (>100MB)", "Find files larger than 100MB in current directory", "find . -type f -size +100M -exec ls -lh {} \\;", "", false),
-            Tweak::new("Maintenance & Network", "System maintenance and network utilities", "", "", false),
-            Tweak::new("  Flush DNS Cache", "Clear DNS cache", "sudo dscacheutil -flushcache; sudo killall -HUP mDNSResponder", "", false),
-            Tweak::new("  Clear Launch Services Cache", "Clear Launch Services cache", "sudo rm -rf /System/Library/Caches/com.apple.LaunchServices-*.csstore", "", false),
-            Tweak::new("  Rebuild Spotlight Index", "Rebuild Spotlight search index", "sudo mdutil -E /", "", false),
-            Tweak::new("  Repair Disk Permissions", "Repair disk permissions", "sudo diskutil resetUserPermissions / `id -u`", "", false),
-            Tweak::new("  Show System Logs", "Show recent system logs", "log show --last 1h | head -50", "", false),
-            Tweak::new("  Test Internet Connection", "Test internet connectivity", "ping -c 3 8.8.8.8", "", false),
-            Tweak::new("  Show Network Speed", "Show current network interface speeds", "top -l 1 | grep \"Networks:\"", "", false),
-            Tweak::new("  Show Active Connections", "Show active network connections", "netstat -an | grep ESTABLISHED | head -10", "", false),
-            Tweak::new("  Test DNS Resolution", "Test DNS resolution", "nslookup google.com", "", false),
-        ];
-
-        let categories = vec![
-            TopLevelCategory::new("Dock", "Customize macOS Dock settings", dock_tweaks),
-            TopLevelCategory::new("Finder", "Customize Finder appearance and behavior", finder_tweaks),
-            TopLevelCategory::new("System UI", "Customize system user interface", system_ui_tweaks),
-            TopLevelCategory::new("Security", "Configure security and privacy settings", security_tweaks),
-            TopLevelCategory::new("Developer", "Developer tools and configurations", developer_tweaks),
-            TopLevelCategory::new("Performance", "Optimize system performance", performance_tweaks),
-            TopLevelCategory::new("Animated Wallpapers", "Enable animated wallpapers", animated_wallpapers_tweaks),
-            TopLevelCategory::new("Power Management", "Configure sleep and power settings", power_management_tweaks),
-            TopLevelCategory::new("Networking", "Configure network settings", network_tweaks),
-            TopLevelCategory::new("Optimization", "Apply system performance tweaks", optimization_tweaks),
-            TopLevelCategory::new("Brew Management", "Manage Homebrew package manager", brew_tweaks),
-            TopLevelCategory::new("About", "Application information and system details", about_tweaks),
-            TopLevelCategory::new("Utilities", "Useful system utilities", utilities_tweaks),
-        ];
-
-        let mut category_list_state = ListState::default();
-        category_list_state.select(Some(0));
+    pub fn new(runner: Arc<dyn CommandRunner>) -> Result<App> {
+        let tree = catalog::load_catalog()?;
+        Ok(Self::with_state(
+            runner,
+            Config::load(),
+            tree,
+            History::new(History::default_path()),
+            SnapshotStore::new(SnapshotStore::default_path()),
+        ))
+    }
+
+    /// An app over `tree` that keeps its history and snapshots in the
+    /// given stores.
+    pub fn with_state(
+        runner: Arc<dyn CommandRunner>,
+        config: Config,
+        tree: TweakTree,
+        history: History,
+        snapshots: SnapshotStore,
+    ) -> App {
+        // Tweaks applied in earlier sessions and not reverted since
+        let applied_tweaks = history
+            .applied_ids()
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|id| {
+                tree.find_by_id(id)
+                    .and_then(|node| tree.node(node).tweak())
+                    .is_some_and(|tweak| tweak.tracks_applied())
+            })
+            .collect();
+
+        let last_applied = history.last_applied().unwrap_or_default();
+
+        let mut list_state = ListState::default();
+        list_state.select(Some(0));
 
         App {
-            view_level: 0,
-            selected_indices: [0, 0],
-            category_list_state,
-            tweak_list_state: ListState::default(),
-            viewing_sub_category: None,
+            current_parent: None,
+            selection_stack: Vec::new(),
+            list_state,
             should_quit: false,
-            categories,
-            applied_tweaks: Vec::new(),
+            tree,
+            applied_tweaks,
+            last_applied,
+            marked_tweaks: Vec::new(),
+            history,
+            history_view: None,
+            search: None,
+            output_view: None,
+            snapshots,
+            probe_states: HashMap::new(),
+            probe_receiver: None,
+            runner,
+            dry_run: false,
+            allow_sudo: true,
+            assume_yes: false,
+            dry_run_log: Arc::new(ScriptedRunner::new()),
             status_message: None,
             status_timer: 0,
             pending_destructive_command: None,
             confirmation_message: None,
             text_input_prompt: None,
-            text_input_command_template: None,
+            text_input_tweak: None,
+            text_input_error: None,
+            text_input_marks: false,
+            profile_prompt: None,
+            drift_profile: None,
             input_buffer: String::new(),
             fullscreen_output: None,
             fullscreen_output_scroll: 0,
             config,
             fullscreen_list: None,
-            fullscreen_list_state: ListState::default(),
-            fullscreen_list_title: String::new(),
             sokoban_game: None,
         }
     }
 
-    /// Returns the list of items to be displayed based on the current view level.
-    pub fn get_current_list_items(&self) -> Vec<String> {
-        match self.view_level {
-            0 => self.categories.iter().map(|c| c.name.clone()).collect(),
-            1 => {
-                let current_cat_tweaks = &self.categories[self.selected_indices[0]].tweaks;
-                if let Some(sub_cat_name) = &self.viewing_sub_category {
-                    // Viewing options within a sub-category
-                    current_cat_tweaks.iter()
-                        .skip_while(|t| &t.name != sub_cat_name)
-                        .skip(1)
-                        .take_while(|t| t.name.starts_with("  "))
-                        .map(|t| t.name.clone())
-                        .collect()
-                } else {
-                    // Viewing sub-categories
-                    current_cat_tweaks.iter()
-                        .filter(|t| !t.name.starts_with("  "))
-                        .map(|t| t.name.clone())
-                        .collect()
+    /// Starts re-reading the live state of every probed tweak in the background.
+    pub fn refresh_probes(&mut self) {
+        let tweaks = self.tree.tweaks().filter(|t| t.probe.is_some()).cloned().collect();
+        self.probe_receiver = Some(probe::spawn_all(self.runner.clone(), tweaks));
+    }
+
+    /// Collects any probe results that have arrived since the last call.
+    pub fn poll_probes(&mut self) {
+        if let Some(receiver) = &self.probe_receiver {
+            loop {
+                match receiver.try_recv() {
+                    Ok((id, state)) => {
+                        self.probe_states.insert(id, state);
+                    }
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        self.probe_receiver = None;
+                        break;
+                    }
                 }
-            },
-            _ => vec![],
+            }
+        }
+    }
+
+    /// Runs `requests` for `tweak` one after another in the background,
+    /// showing their output as it arrives.
+    fn start_output_view(&mut self, tweak: &Tweak, label: String, requests: Vec<CommandRequest>) {
+        let mut pending: VecDeque<CommandRequest> =
+            requests.iter().map(|request| self.with_timeout(tweak, request)).collect();
+        let Some(first) = pending.pop_front() else { return };
+        let mut view = OutputView::new(tweak, label, pending);
+        view.snapshot = self.take_snapshot(tweak);
+        view.start(self.action_runner().as_ref(), first);
+        self.output_view = Some(view);
+    }
+
+    /// Applies `steps` as a batch in the background, showing the output of
+    /// each command as it arrives.
+    fn start_batch_view(&mut self, steps: Vec<BatchStep>) {
+        let Some(first) = steps.first() else { return };
+        let view = OutputView::new(&first.tweak, format!("Batch of {} tweaks", steps.len()), VecDeque::new());
+        let run = BatchRun::new(self, steps);
+        self.advance_batch(view, run);
+    }
+
+    /// Starts the batch's next command in `view`, or reports how the batch
+    /// went once it is over.
+    fn advance_batch(&mut self, mut view: OutputView, mut run: BatchRun) {
+        if let Some((tweak, action, request)) = run.next(self) {
+            let request = self.with_timeout(&tweak, &request);
+            view.tweak = tweak;
+            view.action = action;
+            view.start(self.action_runner().as_ref(), request);
+            view.batch = Some(run);
+            self.output_view = Some(view);
+            return;
+        }
+        let steps = run.steps().to_vec();
+        let report = run.into_report();
+        self.settle_batch(&steps, &report);
+        let outcome = match &report.failure {
+            None => OutputOutcome::Succeeded,
+            Some(_) if view.canceled => OutputOutcome::Canceled,
+            Some((_, error)) => OutputOutcome::Failed(error.clone()),
+        };
+        if report.succeeded() && report.warnings.is_empty() {
+            self.status_message = Some(report.summary(steps.len()));
+            self.status_timer = 50;
+        } else {
+            view.lines.push((String::new(), false));
+            view.lines.extend(report.summary(steps.len()).lines().map(|line| (line.to_string(), false)));
+        }
+        let keep = !view.lines.is_empty() || outcome != OutputOutcome::Succeeded;
+        view.finished = Some((outcome, view.started.elapsed()));
+        if keep {
+            self.output_view = Some(view);
         }
     }
 
-    /// Gets the currently selected tweak or sub-category.
-    pub fn get_selected_item(&self) -> Option<Tweak> {
-        match self.view_level {
-            1 => {
-                let list = self.get_current_list_items();
-                let selected_name = list.get(self.selected_indices[1])?;
-                let current_cat_tweaks = &self.categories[self.selected_indices[0]].tweaks;
-                current_cat_tweaks.iter().find(|t| &t.name == selected_name).cloned()
+    /// Collects output from the background commands, starting the next one
+    /// when one succeeds. A tweak that applied without printing anything
+    /// just gets a status message; otherwise the output stays up to read.
+    pub fn poll_output(&mut self) {
+        let Some(mut view) = self.output_view.take() else { return };
+        let seen = view.lines.len();
+        let polled = view.poll();
+        let working = self.fullscreen_list.as_mut().filter(|list| !list.progress.is_empty() && list.tweak.id == view.tweak.id);
+        if let Some(list) = working {
+            list.track_progress(&view.lines[seen..]);
+        }
+        let Some((request, result)) = polled else {
+            self.output_view = Some(view);
+            return;
+        };
+        // Package operations change the system even though the list tweak
+        // they are started from only shows information
+        let kind = if view.package_operation { TweakKind::Action } else { view.tweak.kind };
+        let _ = self.record_as(&view.tweak, kind, view.action, &request, &result);
+        if let Some(mut run) = view.batch.take() {
+            run.finish(self, result.and_then(CommandOutput::into_result).map(|_| ()));
+            self.advance_batch(view, run);
+            return;
+        }
+        let timed_out = result.as_ref().ok().and_then(|output| output.timed_out);
+        let canceled = view.canceled || result.as_ref().is_ok_and(CommandOutput::interrupted);
+        let outcome = match result.and_then(CommandOutput::into_result) {
+            _ if timed_out.is_some() => OutputOutcome::TimedOut(timed_out.unwrap_or_default()),
+            _ if canceled => OutputOutcome::Canceled,
+            Ok(_) => match view.pending.pop_front() {
+                Some(next) => {
+                    view.start(self.action_runner().as_ref(), next);
+                    self.output_view = Some(view);
+                    return;
+                }
+                None => OutputOutcome::Succeeded,
             },
-            _ => None,
+            Err(e) => OutputOutcome::Failed(e.to_string()),
+        };
+
+        let is_query = view.tweak.kind == TweakKind::Query;
+        match &outcome {
+            OutputOutcome::Succeeded if is_query => {
+                if view.lines.is_empty() {
+                    view.lines.push((format!("'{}' executed successfully with no output.", view.label), false));
+                }
+            }
+            OutputOutcome::Succeeded => {
+                self.mark_applied(&view.tweak);
+                self.refresh_probes();
+                let applied = format!("Successfully applied: {}", view.label);
+                let snapshot = std::mem::replace(&mut view.snapshot, Ok(None));
+                self.status_message = Some(Self::applied_message(applied, snapshot));
+                self.status_timer = 50;
+            }
+            OutputOutcome::Failed(e) => {
+                self.status_message = Some(format!("Error executing '{}': {}", view.label, e));
+                self.status_timer = 80;
+            }
+            OutputOutcome::Canceled => {
+                self.status_message = Some(format!("Canceled: {}", view.label));
+                self.status_timer = 50;
+            }
+            OutputOutcome::TimedOut(timeout) => {
+                self.status_message = Some(format!("Timed out after {}: {}", runner::format_timeout(*timeout), view.label));
+                self.status_timer = 80;
+            }
+        }
+        self.settle_packages(&view.tweak, &outcome);
+        let keep = is_query || !view.lines.is_empty() || outcome != OutputOutcome::Succeeded;
+        view.finished = Some((outcome, view.started.elapsed()));
+        if keep {
+            self.output_view = Some(view);
+        }
+    }
+
+    /// Works out which packages an operation run from the package list by
+    /// `tweak` got through, by asking brew about them again.
+    fn settle_packages(&mut self, tweak: &Tweak, outcome: &OutputOutcome) {
+        let Some(list) = self.fullscreen_list.as_ref().filter(|list| !list.progress.is_empty() && list.tweak.id == tweak.id) else {
+            return;
+        };
+        let refreshed = match list.operation {
+            Some(PackageOperation::Upgrade) => self.fetch_packages(ListSource::BrewOutdated).ok(),
+            _ => {
+                let targets: Vec<Package> =
+                    list.packages.iter().filter(|p| list.package_state(p).is_some()).cloned().collect();
+                self.package_info(&targets).ok()
+            }
+        };
+        if let Some(list) = &mut self.fullscreen_list {
+            list.finish_operation(*outcome == OutputOutcome::Succeeded, refreshed);
         }
     }
 
+    /// Runner for commands that change the system: the real one, or the
+    /// recorder that stands in for it during a dry run. Probes and brew
+    /// listings only read state and always use `runner`.
+    pub fn action_runner(&self) -> Arc<dyn CommandRunner> {
+        if self.dry_run {
+            self.dry_run_log.clone()
+        } else {
+            self.runner.clone()
+        }
+    }
+
+    /// Describes the commands a dry run recorded since the last report,
+    /// classified using `tweak`'s flags when the commands came from one.
+    pub fn take_dry_run_report(&self, tweak: Option<&Tweak>) -> String {
+        let mut report = String::from("Dry run: nothing was executed. Would run:\n");
+        for invocation in self.dry_run_log.take_invocations() {
+            let command = invocation.request.display();
+            let flags = tweaks::join_flags(&[
+                (tweak.is_some_and(|t| t.destructive), "destructive"),
+                (tweak.is_some_and(|t| t.requires_root) || command.contains("sudo "), "sudo"),
+                (tweak.map_or(invocation.interactive, |t| t.interactive), "interactive"),
+            ]);
+            report.push_str(&format!("\n  $ {}\n", command));
+            if let Some(flags) = flags {
+                report.push_str(&format!("    ({})\n", flags));
+            }
+        }
+        report
+    }
+
+    /// Returns the nodes shown at the current level of the tree.
+    pub fn get_current_list_items(&self) -> Vec<NodeId> {
+        self.tree.children(self.current_parent).to_vec()
+    }
+
+    /// Gets the currently highlighted node.
+    pub fn get_selected_item(&self) -> Option<NodeId> {
+        let selected = self.list_state.selected()?;
+        self.tree.children(self.current_parent).get(selected).copied()
+    }
+
     pub fn next_item(&mut self) {
         let count = self.get_current_list_items().len();
         if count == 0 {
             return;
         }
 
-        let (index, state) = if self.view_level == 0 {
-            (&mut self.selected_indices[0], &mut self.category_list_state)
-        } else {
-            (&mut self.selected_indices[1], &mut self.tweak_list_state)
-        };
-        
-        let new_index = (*index + 1) % count;
-        *index = new_index;
-        state.select(Some(new_index));
+        let index = self.list_state.selected().unwrap_or(0);
+        self.list_state.select(Some((index + 1) % count));
     }
-    
+
     pub fn previous_item(&mut self) {
         let count = self.get_current_list_items().len();
         if count == 0 {
             return;
         }
 
-        let (index, state) = if self.view_level == 0 {
-            (&mut self.selected_indices[0], &mut self.category_list_state)
-        } else {
-            (&mut self.selected_indices[1], &mut self.tweak_list_state)
-        };
-
-        let new_index = if *index == 0 { count - 1 } else { *index - 1 };
-        *index = new_index;
-        state.select(Some(new_index));
+        let index = self.list_state.selected().unwrap_or(0);
+        let new_index = if index == 0 { count - 1 } else { index - 1 };
+        self.list_state.select(Some(new_index));
     }
-    
+
+    /// Descends into the highlighted category or group.
     pub fn handle_right_key(&mut self) {
-        match self.view_level {
-            0 => { // From top-level to sub-categories
-                if !self.categories[self.selected_indices[0]].tweaks.is_empty() {
-                    self.view_level = 1;
-                    self.selected_indices[1] = 0;
-                    self.tweak_list_state = ListState::default();
-                    self.tweak_list_state.select(Some(0));
-                } else {
-                    self.status_message = Some("This category is empty.".to_string());
-                    self.status_timer = 50;
-                }
-            },
-            1 => { // From sub-categories to options
-                if self.viewing_sub_category.is_none() {
-                    if let Some(item) = self.get_selected_item() {
-                        if item.enable_command.is_empty() {
-                            self.viewing_sub_category = Some(item.name.clone());
-                            self.selected_indices[1] = 0;
-                            self.tweak_list_state = ListState::default();
-                            self.tweak_list_state.select(Some(0));
-                        }
-                    }
-                }
-            },
-            _ => {}
+        let Some(selected) = self.get_selected_item() else {
+            return;
+        };
+        let node = self.tree.node(selected);
+        if !node.is_container() {
+            return;
         }
+        if node.children.is_empty() {
+            self.status_message = Some(format!("'{}' is empty.", node.name));
+            self.status_timer = 50;
+            return;
+        }
+        self.selection_stack.push(self.list_state.selected().unwrap_or(0));
+        self.current_parent = Some(selected);
+        self.list_state = ListState::default();
+        self.list_state.select(Some(0));
     }
-    
+
+    /// Returns to the parent level, restoring its selection.
     pub fn handle_left_key(&mut self) {
-        match self.view_level {
-            1 => {
-                if self.viewing_sub_category.is_some() {
-                    self.viewing_sub_category = None;
-                    self.selected_indices[1] = 0;
-                    self.tweak_list_state = ListState::default();
-                    self.tweak_list_state.select(Some(0));
-                } else {
-                    self.view_level = 0;
-                }
-            },
-            _ => {}
+        if let Some(current) = self.current_parent {
+            self.current_parent = self.tree.node(current).parent;
+            let index = self.selection_stack.pop().unwrap_or(0);
+            self.list_state = ListState::default();
+            self.list_state.select(Some(index));
         }
     }
-    
-    pub fn apply_selected_tweak<B: Backend + std::io::Write>(
-        &mut self,
-        terminal: &mut Terminal<B>,
-        run_interactive: impl Fn(&mut Terminal<B>, &str) -> Result<()>,
-    ) -> Result<()> {
-        if self.view_level == 1 {
-            if let Some(tweak) = self.get_selected_item() {
-                if tweak.enable_command == "__SHOW_VERSION__" {
-                    self.fullscreen_output = Some(format!("macOS Tweaks v{}", get_app_version()));
-                    self.fullscreen_output_scroll = 0;
-                    return Ok(());
-                }
-                if tweak.enable_command == "__SOKOBAN_GAME__" {
-                    self.sokoban_game = Some(SokobanGame::new());
-                    return Ok(());
-                }
-                if tweak.enable_command.starts_with("__PROMPT_FOR_TEXT__:") {
-                    if let Some(template) = tweak.enable_command.strip_prefix("__PROMPT_FOR_TEXT__:") {
-                        self.text_input_prompt = Some(format!("Enter text for: {}", tweak.name.trim()));
-                        self.text_input_command_template = Some(template.to_string());
-                        self.input_buffer.clear();
-                        return Ok(());
-                    }
-                }
-                if tweak.enable_command == "__CHECK_BREW__" {
-                    let message = if utils::check_command_exists("brew") {
-                        "Homebrew is installed and available in your PATH."
-                    } else {
-                        "Homebrew is not installed or not in your PATH."
-                    };
-                    self.fullscreen_output = Some(message.to_string());
-                    self.fullscreen_output_scroll = 0;
+
+    /// The node the detail pane describes: the highlighted search match
+    /// while searching, otherwise the highlighted item.
+    pub fn detail_node(&self) -> Option<NodeId> {
+        match &self.search {
+            Some(view) => view.selected(),
+            None => self.get_selected_item(),
+        }
+    }
+
+    /// Shows `node` in the tree, highlighted at its level.
+    pub fn jump_to(&mut self, node: NodeId) {
+        let path = self.tree.ancestry(node);
+        self.selection_stack = path[..path.len() - 1].iter().map(|&id| self.index_in_parent(id)).collect();
+        self.current_parent = self.tree.node(node).parent;
+        self.list_state = ListState::default();
+        self.list_state.select(Some(self.index_in_parent(node)));
+    }
+
+    fn index_in_parent(&self, id: NodeId) -> usize {
+        let parent = self.tree.node(id).parent;
+        self.tree.children(parent).iter().position(|&child| child == id).unwrap_or(0)
+    }
+
+    pub fn open_search(&mut self) {
+        self.search = Some(SearchView::default());
+    }
+
+    pub fn edit_search(&mut self, edit: impl FnOnce(&mut String)) {
+        if let Some(view) = &mut self.search {
+            edit(&mut view.query);
+            view.update(&self.tree);
+        }
+    }
+
+    /// Leaves search mode, showing the highlighted match in the tree.
+    pub fn jump_to_search_hit(&mut self) {
+        if let Some(node) = self.search.take().and_then(|view| view.selected()) {
+            self.jump_to(node);
+        }
+    }
+
+    /// Leaves search mode and applies the highlighted match as if it had
+    /// been chosen in the tree.
+    pub fn apply_search_hit<B: Backend>(
+        &mut self,
+        terminal: &mut Terminal<B>,
+        run_interactive: impl Fn(&mut Terminal<B>, &dyn CommandRunner, &CommandRequest) -> Result<CommandOutput>,
+    ) -> Result<()> {
+        let Some(node) = self.search.as_ref().and_then(SearchView::selected) else {
+            return Ok(());
+        };
+        self.search = None;
+        self.jump_to(node);
+        self.apply_selected_tweak(terminal, run_interactive)
+    }
+
+    pub fn apply_selected_tweak<B: Backend>(
+        &mut self,
+        terminal: &mut Terminal<B>,
+        run_interactive: impl Fn(&mut Terminal<B>, &dyn CommandRunner, &CommandRequest) -> Result<CommandOutput>,
+    ) -> Result<()> {
+        if let Some(selected) = self.get_selected_item() {
+            if let Some(tweak) = self.tree.node(selected).tweak().cloned() {
+                if let Some(reason) = self.forbidden_reason(&tweak) {
+                    self.status_message = Some(reason);
+                    self.status_timer = 80;
                     return Ok(());
                 }
-                if tweak.enable_command == "__LIST_INSTALLED__" {
-                    match utils::execute_command("brew list", false) {
-                        Ok(output) => {
-                            let packages: Vec<String> = output.lines().filter(|l| !l.trim().is_empty()).map(String::from).collect();
-                            if packages.is_empty() {
-                                self.fullscreen_output = Some("No installed Homebrew packages found.".to_string());
-                                self.fullscreen_output_scroll = 0;
-                            } else {
-                                self.fullscreen_list = Some(packages);
-                                self.fullscreen_list_state.select(Some(0));
-                                self.fullscreen_list_title = "Installed Packages (Press Enter for info)".to_string();
-                            }
-                        }
-                        Err(e) => {
-                            self.fullscreen_output = Some(format!("Error fetching installed packages: {}", e));
-                            self.fullscreen_output_scroll = 0;
-                        }
+                let command = match &tweak.action {
+                    TweakAction::Shell { command } => command.clone(),
+                    TweakAction::Builtin { builtin } => {
+                        self.run_builtin(*builtin);
+                        return Ok(());
                     }
-                    return Ok(());
-                }
-                if tweak.enable_command == "__LIST_OUTDATED__" {
-                    match utils::execute_command("brew outdated", false) {
-                        Ok(output) => {
-                            let packages: Vec<String> = output.lines().filter(|l| !l.trim().is_empty()).map(String::from).collect();
-                            if packages.is_empty() {
-                                self.fullscreen_output = Some("All Homebrew packages are up to date.".to_string());
-                                self.fullscreen_output_scroll = 0;
-                            } else {
-                                self.fullscreen_list = Some(packages);
-                                self.fullscreen_list_state.select(Some(0));
-                                self.fullscreen_list_title = "Outdated Packages (Press Enter to upgrade)".to_string();
-                            }
-                        }
-                        Err(e) => {
-                            self.fullscreen_output = Some(format!("Error fetching outdated packages: {}", e));
-                            self.fullscreen_output_scroll = 0;
-                        }
+                    TweakAction::PromptThenRun { prompt, param, .. } => {
+                        self.text_input_prompt = Some(format!("{} [{}]", prompt, param.hint()));
+                        self.input_buffer = self.current_value(&tweak).unwrap_or_default();
+                        self.text_input_tweak = Some(tweak.clone());
+                        self.text_input_error = None;
+                        return Ok(());
                     }
-                    return Ok(());
-                }
-                if tweak.enable_command.is_empty() {
-                    self.handle_right_key();
+                    TweakAction::OpenList { source, on_select } => {
+                        self.open_list(&tweak, *source, *on_select);
+                        return Ok(());
+                    }
+                };
+                let tweak_name = tweak.name.clone();
+                let is_query = tweak.kind == TweakKind::Query;
+
+                if self.dry_run {
+                    let request = CommandRequest::shell(&command);
+                    if tweak.runs_interactively() || tweak.destructive {
+                        self.dry_run_log.run_interactive(&request)?;
+                    } else {
+                        self.dry_run_log.run(&request)?;
+                    }
+                    self.fullscreen_output = Some(self.take_dry_run_report(Some(&tweak)));
+                    self.fullscreen_output_scroll = 0;
                     return Ok(());
                 }
 
-                let tweak_name = tweak.name.clone();
-                let command = tweak.enable_command.clone();
-                let can_run_multiple = tweak_name.contains("Add Small Spacer");
-                let is_info_command = tweak_name.contains("List") || tweak_name.contains("Show") || tweak_name.contains("About") || tweak_name.contains("Version") || tweak_name.contains("Dependencies") || tweak_name.contains("System Information") || tweak_name.contains("Count") || tweak_name.contains("Find");
-                let is_destructive = tweak_name.contains("(destructive)");
-                let is_interactive = utils::require_sudo(&command) || is_destructive || tweak_name.contains("(interactive)");
-
-                if is_destructive {
-                    self.pending_destructive_command = Some((tweak_name.clone(), command.clone()));
-                    self.confirmation_message = Some(format!("⚠️  DESTRUCTIVE ACTION: {}\nType 'yes' to confirm or press any other key to cancel", tweak_name.trim()));
+                if tweak.destructive {
+                    self.pending_destructive_command = Some((tweak.clone(), command.clone()));
+                    self.confirmation_message = Some(format!("⚠️  DESTRUCTIVE ACTION: {}\nType 'yes' to confirm or press any other key to cancel", tweak_name));
                     return Ok(());
                 }
 
-                // Debug: Show what type of command this is
-                let command_type = if is_info_command { "info" } else if is_interactive { "interactive" } else { "normal" };
-                self.status_message = Some(format!("Executing {} command: {}", command_type, tweak_name.trim()));
+                let command_type = if tweak.runs_interactively() { "interactive" } else { tweak.kind.label() };
+                self.status_message = Some(format!("Executing {} command: {}", command_type, tweak_name));
                 self.status_timer = 20;
 
-                if is_interactive {
-                    run_interactive(terminal, &command)?;
-                    self.status_message = Some(format!("Successfully applied: {}", tweak_name.trim()));
-                    self.status_timer = 50;
-                    if !is_info_command && !can_run_multiple && !self.applied_tweaks.contains(&tweak_name) {
-                        self.applied_tweaks.push(tweak_name.clone());
+                let request = CommandRequest::shell(&command);
+                if !tweak.runs_interactively() {
+                    self.start_output_view(&tweak, tweak_name, vec![request]);
+                    return Ok(());
+                }
+                let snapshot = self.take_snapshot(&tweak);
+                match self.run_tweak_command(terminal, &run_interactive, &tweak, HistoryAction::Apply, &request, true) {
+                    Ok(_) if is_query => {
+                        self.fullscreen_output = Some(format!("'{}' executed successfully with no output.", tweak_name));
+                        self.fullscreen_output_scroll = 0;
                     }
-                } else {
-                    match execute_command(&command, false) {
-                        Ok(output) => {
-                            if is_info_command {
-                                let final_output = if output.trim().is_empty() {
-                                    format!("'{}' executed successfully with no output.", tweak_name.trim())
-                                } else {
-                                    output
-                                };
-                                self.fullscreen_output = Some(final_output);
-                                self.fullscreen_output_scroll = 0;
-                            } else {
-                                if !can_run_multiple && !self.applied_tweaks.contains(&tweak_name) {
-                                    self.applied_tweaks.push(tweak_name.clone());
-                                }
-                                if output.trim().is_empty() {
-                                    self.fullscreen_output = Some(format!("'{}' executed successfully with no output.", tweak_name.trim()));
-                                    self.fullscreen_output_scroll = 0;
-                                } else {
-                                    self.status_message = Some(format!("Successfully applied: {}", tweak_name.trim()));
-                                    self.status_timer = 50;
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            self.status_message = Some(format!("Error executing '{}': {}", tweak_name.trim(), e));
-                            self.status_timer = 80;
-                        }
+                    Ok(_) => {
+                        self.mark_applied(&tweak);
+                        self.refresh_probes();
+                        let applied = format!("Successfully applied: {}", tweak_name);
+                        self.status_message = Some(Self::applied_message(applied, snapshot));
+                        self.status_timer = 50;
+                    }
+                    Err(e) => {
+                        self.status_message = Some(format!("Error executing '{}': {}", tweak_name, e));
+                        self.status_timer = 80;
                     }
                 }
+            } else {
+                // Enter on a category or group behaves like the right arrow
+                self.handle_right_key();
             }
+        }
+        Ok(())
+    }
+
+    /// Adds the selected tweak to the batch, or takes it out again. Only
+    /// tweaks that can run unattended can be marked; for tweaks that take a
+    /// value, the value is asked for first.
+    pub fn toggle_mark(&mut self) {
+        let Some(tweak) = self.get_selected_item().and_then(|id| self.tree.node(id).tweak()).cloned() else {
+            return;
+        };
+        if let Some(index) = self.marked_tweaks.iter().position(|(id, _)| id == &tweak.id) {
+            self.marked_tweaks.remove(index);
+            return;
+        }
+        if let (None, false, Some(param)) = (self.forbidden_reason(&tweak), tweak.destructive, tweak.action.param()) {
+            self.text_input_prompt = Some(format!("Value of '{}' in the batch [{}]", tweak.name, param.hint()));
+            self.input_buffer = self.current_value(&tweak).unwrap_or_default();
+            self.text_input_tweak = Some(tweak);
+            self.text_input_error = None;
+            self.text_input_marks = true;
+            return;
+        }
+        self.mark(&tweak, None);
+    }
+
+    /// Adds `tweak` to the batch with `value`, or says why it cannot be.
+    fn mark(&mut self, tweak: &Tweak, value: Option<String>) {
+        let problem = if let Some(reason) = self.forbidden_reason(tweak) {
+            Some(reason)
+        } else if tweak.destructive {
+            Some(format!("'{}' is destructive and must be applied on its own", tweak.name))
         } else {
-             // If user presses enter on a category, treat it like right arrow
-            self.handle_right_key();
+            BatchStep::new(tweak, value.as_deref()).err()
+        };
+        match problem {
+            Some(problem) => {
+                self.status_message = Some(format!("Cannot select: {}", problem));
+                self.status_timer = 50;
+            }
+            None => self.marked_tweaks.push((tweak.id.clone(), value)),
+        }
+    }
+
+    /// Applies the marked tweaks in order as a batch, rolling them all back
+    /// if one fails. The batch runs in the background unless a tweak in it
+    /// needs the terminal. The marks are kept after a failure so it can be
+    /// retried.
+    pub fn apply_marked_tweaks<B: Backend>(
+        &mut self,
+        terminal: &mut Terminal<B>,
+        run_interactive: impl Fn(&mut Terminal<B>, &dyn CommandRunner, &CommandRequest) -> Result<CommandOutput>,
+    ) -> Result<()> {
+        let steps: Vec<BatchStep> = self
+            .marked_tweaks
+            .iter()
+            .filter_map(|(id, value)| {
+                let tweak = self.tree.find_by_id(id).and_then(|node| self.tree.node(node).tweak())?;
+                BatchStep::new(tweak, value.as_deref()).ok()
+            })
+            .collect();
+        if steps.is_empty() {
+            self.status_message = Some("Select tweaks with Space first.".to_string());
+            self.status_timer = 50;
+            return Ok(());
+        }
+        if !self.dry_run && !steps.iter().any(|step| step.tweak.runs_interactively()) {
+            self.start_batch_view(steps);
+            return Ok(());
+        }
+
+        let report = batch::run(self, &steps, &mut |tweak, action, request| {
+            self.run_tweak_command(terminal, &run_interactive, tweak, action, request, tweak.runs_interactively())
+                .map(|_| ())
+        });
+        if self.dry_run {
+            self.fullscreen_output = Some(self.take_dry_run_report(None));
+            self.fullscreen_output_scroll = 0;
+            return Ok(());
+        }
+        self.settle_batch(&steps, &report);
+        if report.succeeded() && report.warnings.is_empty() {
+            self.status_message = Some(report.summary(steps.len()));
+            self.status_timer = 50;
+        } else {
+            self.fullscreen_output = Some(report.summary(steps.len()));
+            self.fullscreen_output_scroll = 0;
+        }
+        Ok(())
+    }
+
+    /// Notes which tweaks of a finished batch are applied, clearing the
+    /// marks if it succeeded.
+    fn settle_batch(&mut self, steps: &[BatchStep], report: &BatchReport) {
+        for step in steps.iter().filter(|step| report.applied.contains(&step.tweak.id)) {
+            self.mark_applied(&step.tweak);
+        }
+        if report.succeeded() {
+            self.marked_tweaks.clear();
+        }
+        self.refresh_probes();
+    }
+
+    /// Undoes the selected tweak, restoring its snapshot or running its
+    /// `disable_command`.
+    pub fn revert_selected_tweak<B: Backend>(
+        &mut self,
+        terminal: &mut Terminal<B>,
+        run_interactive: impl Fn(&mut Terminal<B>, &dyn CommandRunner, &CommandRequest) -> Result<CommandOutput>,
+    ) -> Result<()> {
+        let Some(tweak) = self.get_selected_item().and_then(|id| self.tree.node(id).tweak()).cloned() else {
+            return Ok(());
+        };
+        if !tweak.tracks_applied() {
+            return Ok(());
+        }
+        if let Some(reason) = self.forbidden_reason(&tweak) {
+            self.status_message = Some(reason);
+            self.status_timer = 80;
+            return Ok(());
+        }
+        let requests = match self.revert_requests(&tweak) {
+            Ok(requests) => requests,
+            Err(reason) => {
+                self.status_message = Some(format!("Cannot revert '{}': {}", tweak.name, reason));
+                self.status_timer = 80;
+                return Ok(());
+            }
+        };
+
+        let interactive = tweak.runs_interactively();
+        let result = requests.iter().try_for_each(|request| {
+            self.run_tweak_command(terminal, &run_interactive, &tweak, HistoryAction::Revert, request, interactive)
+                .map(|_| ())
+        });
+        match result {
+            Ok(()) if self.dry_run => {
+                self.fullscreen_output = Some(self.take_dry_run_report(Some(&tweak)));
+                self.fullscreen_output_scroll = 0;
+            }
+            Ok(()) => {
+                self.status_message = Some(match self.mark_reverted(&tweak) {
+                    Ok(()) => format!("Successfully reverted: {}", tweak.name),
+                    Err(e) => format!("Reverted {}, but could not update snapshots: {}", tweak.name, e),
+                });
+                self.status_timer = 50;
+                self.refresh_probes();
+            }
+            Err(e) => {
+                self.status_message = Some(format!("Error reverting '{}': {}", tweak.name, e));
+                self.status_timer = 80;
+            }
         }
         Ok(())
     }
 
+    /// Reads the current value of every `defaults` key `tweak` writes and
+    /// saves it, so the tweak can be reverted later. An existing snapshot is
+    /// kept, so the one from before the first apply wins. Returns the values
+    /// just read; nothing is read or saved during a dry run.
+    pub fn take_snapshot(&self, tweak: &Tweak) -> Result<Option<Snapshot>> {
+        let keys = snapshot::written_keys(&tweak.action);
+        if self.dry_run || tweak.kind == TweakKind::Query || keys.is_empty() {
+            return Ok(None);
+        }
+        let snapshot = Snapshot::take(self.runner.as_ref(), &keys, snapshot::restarts(&tweak.action))?;
+        self.snapshots.insert_if_absent(&tweak.id, snapshot.clone())?;
+        Ok(Some(snapshot))
+    }
+
+    /// Why `tweak` may not be applied or reverted on this machine, if it may
+    /// not: it is listed in the config's `forbidden_tweaks`, or it needs sudo
+    /// and sudo is not allowed.
+    pub fn forbidden_reason(&self, tweak: &Tweak) -> Option<String> {
+        if self.config.forbids(&tweak.id) {
+            Some(format!("'{}' is forbidden on this machine by forbidden_tweaks in config.json", tweak.name))
+        } else if tweak.requires_root && !self.allow_sudo {
+            Some(format!("'{}' needs sudo, which is not allowed (--allow-sudo=false)", tweak.name))
+        } else {
+            None
+        }
+    }
+
+    /// How long commands not run for a particular tweak may take.
+    pub fn default_timeout(&self) -> Option<Duration> {
+        let secs = self.config.command_timeout_secs;
+        (secs > 0).then(|| Duration::from_secs(secs))
+    }
+
+    /// How long each of `tweak`'s commands may run: its own limit, or the
+    /// configured default.
+    pub fn timeout_for(&self, tweak: &Tweak) -> Option<Duration> {
+        match tweak.timeout_secs {
+            Some(0) => None,
+            Some(secs) => Some(Duration::from_secs(secs)),
+            None => self.default_timeout(),
+        }
+    }
+
+    /// `request` limited to `tweak`'s timeout, unless it has one already.
+    pub fn with_timeout(&self, tweak: &Tweak, request: &CommandRequest) -> CommandRequest {
+        let mut request = request.clone();
+        if request.timeout.is_none() {
+            request.timeout = self.timeout_for(tweak);
+        }
+        request
+    }
+
+    /// Commands that undo `tweak`: restoring its snapshot when there is a
+    /// usable one, otherwise its `disable_command`.
+    pub fn revert_requests(&self, tweak: &Tweak) -> Result<Vec<CommandRequest>, String> {
+        let restored = match self.snapshots.get(&tweak.id) {
+            Ok(Some(snapshot)) => snapshot.restore_requests(),
+            Ok(None) => Err(format!("no snapshot was taken when '{}' was applied", tweak.name)),
+            Err(e) => Err(e.to_string()),
+        };
+        match restored {
+            Err(_) if !tweak.disable_command.is_empty() => Ok(vec![CommandRequest::shell(&tweak.disable_command)]),
+            restored => restored,
+        }
+    }
+
+    /// Forgets that `tweak` was applied after a successful revert.
+    pub fn mark_reverted(&mut self, tweak: &Tweak) -> Result<()> {
+        if self.dry_run {
+            return Ok(());
+        }
+        self.applied_tweaks.retain(|id| id != &tweak.id);
+        self.snapshots.remove(&tweak.id)
+    }
+
+    /// Success message for an apply, noting when no undo snapshot could be saved.
+    fn applied_message(applied: String, snapshot: Result<Option<Snapshot>>) -> String {
+        match snapshot {
+            Ok(_) => applied,
+            Err(e) => format!("{} (could not save undo snapshot: {})", applied, e),
+        }
+    }
+
+    /// Runs one of `tweak`'s commands, handing it the terminal when
+    /// `interactive`, and records the outcome in the history. Returns the
+    /// captured stdout.
+    fn run_tweak_command<B: Backend>(
+        &self,
+        terminal: &mut Terminal<B>,
+        run_interactive: &impl Fn(&mut Terminal<B>, &dyn CommandRunner, &CommandRequest) -> Result<CommandOutput>,
+        tweak: &Tweak,
+        action: HistoryAction,
+        request: &CommandRequest,
+        interactive: bool,
+    ) -> Result<String> {
+        let runner = self.action_runner();
+        let request = &self.with_timeout(tweak, request);
+        let result = if interactive && !self.dry_run {
+            run_interactive(terminal, runner.as_ref(), request)
+        } else {
+            runner.capture(request)
+        };
+        // Like the config file, a history that cannot be written is not
+        // worth interrupting the TUI for
+        let _ = self.record(tweak, action, request, &result);
+        result.and_then(CommandOutput::into_result)
+    }
+
+    /// Appends a command run for `tweak` to the history. Dry runs and
+    /// queries are not recorded.
+    pub fn record(
+        &self,
+        tweak: &Tweak,
+        action: HistoryAction,
+        request: &CommandRequest,
+        result: &Result<CommandOutput>,
+    ) -> Result<()> {
+        self.record_as(tweak, tweak.kind, action, request, result)
+    }
+
+    /// Like [`App::record`], for a command of the given `kind` run on
+    /// behalf of `tweak`.
+    fn record_as(
+        &self,
+        tweak: &Tweak,
+        kind: TweakKind,
+        action: HistoryAction,
+        request: &CommandRequest,
+        result: &Result<CommandOutput>,
+    ) -> Result<()> {
+        if self.dry_run || kind == TweakKind::Query {
+            return Ok(());
+        }
+        let command = request.display();
+        let sudo = tweak.requires_root || command.contains("sudo ");
+        self.history
+            .record(&HistoryEntry::new(&tweak.id, &tweak.name, action, &command, sudo, result))
+    }
+
+    fn mark_applied(&mut self, tweak: &Tweak) {
+        self.last_applied.insert(tweak.id.clone(), Utc::now());
+        if tweak.tracks_applied() && !self.applied_tweaks.contains(&tweak.id) {
+            self.applied_tweaks.push(tweak.id.clone());
+        }
+    }
+
+    fn run_builtin(&mut self, builtin: BuiltinKind) {
+        match builtin {
+            BuiltinKind::ShowVersion => {
+                self.fullscreen_output = Some(version_message());
+                self.fullscreen_output_scroll = 0;
+            }
+            BuiltinKind::Sokoban => {
+                self.sokoban_game = Some(SokobanGame::new());
+            }
+            BuiltinKind::CheckBrew => {
+                self.fullscreen_output = Some(check_brew_message(self.runner.as_ref()).to_string());
+                self.fullscreen_output_scroll = 0;
+            }
+        }
+    }
+
+    fn open_list(&mut self, tweak: &Tweak, source: ListSource, on_select: ListSelectAction) {
+        if source.searches() {
+            // Filled in once the user has typed what to look for
+            self.fullscreen_list = Some(PackageList::new(tweak, source, on_select, Vec::new()));
+            return;
+        }
+        match self.fetch_packages(source) {
+            Ok(packages) if packages.is_empty() => {
+                self.fullscreen_output = Some(source.empty_message().to_string());
+                self.fullscreen_output_scroll = 0;
+            }
+            Ok(packages) => {
+                self.fullscreen_list = Some(PackageList::new(tweak, source, on_select, packages));
+            }
+            Err(e) => {
+                self.fullscreen_output = Some(format!("Error fetching {}: {:#}", source.title().to_lowercase(), e));
+                self.fullscreen_output_scroll = 0;
+            }
+        }
+    }
+
+    fn fetch_packages(&self, source: ListSource) -> Result<Vec<Package>> {
+        let command = source.command().ok_or_else(|| anyhow!("search lists are filled by searching"))?;
+        let mut request = CommandRequest::shell(command);
+        request.timeout = self.default_timeout();
+        let output = self.runner.run(&request)?;
+        source.parse(&output)
+    }
+
+    /// A read-only `brew` command, such as a search.
+    fn brew_request(&self, args: &[&str]) -> CommandRequest {
+        let mut request = CommandRequest::exec("brew", args.iter().copied());
+        request.timeout = self.default_timeout();
+        request
+    }
+
+    /// Looks up `packages` with `brew info`, to see what they are now.
+    fn package_info(&self, packages: &[Package]) -> Result<Vec<Package>> {
+        let mut found = Vec::new();
+        for (kind, flag) in [(PackageKind::Formula, "--formula"), (PackageKind::Cask, "--cask")] {
+            let names: Vec<&str> = packages.iter().filter(|p| p.kind == kind).map(|p| p.full_name.as_str()).collect();
+            if names.is_empty() {
+                continue;
+            }
+            let args: Vec<&str> = ["info", "--json=v2", flag].into_iter().chain(names).collect();
+            let output = self.runner.capture(&self.brew_request(&args))?;
+            found.extend(brew::parse_installed(&output.into_result()?)?);
+        }
+        Ok(found)
+    }
+
+    /// Starts searching for the list's query in the background; see
+    /// [`App::poll_search`].
+    pub fn search_packages(&mut self) {
+        let Some(list) = &self.fullscreen_list else { return };
+        let query = list.query.as_deref().unwrap_or_default().trim().to_string();
+        if query.is_empty() || list.search.is_some() {
+            return;
+        }
+        let request = self.brew_request(&["search", "--formula", &query]);
+        let search = PackageSearch {
+            query,
+            started: Instant::now(),
+            step: SearchStep::Search(PackageKind::Formula),
+            running: self.runner.stream(&request),
+            canceled: false,
+            matches: Vec::new(),
+            found: Vec::new(),
+        };
+        self.status_message = None;
+        if let Some(list) = &mut self.fullscreen_list {
+            list.editing_query = false;
+            list.search = Some(search);
+        }
+    }
+
+    /// Moves the list's search on once its command has finished, and shows
+    /// the formulae and casks it found, with their details from `brew
+    /// info`, once it is over. Exact matches come first.
+    pub fn poll_search(&mut self) {
+        const MAX_PER_KIND: usize = 20;
+        let Some(mut search) = self.fullscreen_list.as_mut().and_then(|list| list.search.take()) else { return };
+        let finished = search.running.poll().into_iter().find_map(|event| match event {
+            StreamEvent::Finished(result) => Some(result),
+            StreamEvent::Line { .. } => None,
+        });
+        let Some(result) = finished else {
+            if let Some(list) = &mut self.fullscreen_list {
+                list.search = Some(search);
+            }
+            return;
+        };
+        if search.canceled {
+            self.finish_search(Err(format!("Search for '{}' canceled.", search.query)));
+            return;
+        }
+        match search.step {
+            SearchStep::Search(kind) => match search_results(result) {
+                Ok(names) => search.matches.extend(names.into_iter().take(MAX_PER_KIND).map(|full_name| Package {
+                    kind,
+                    name: full_name.rsplit('/').next().unwrap_or(&full_name).to_string(),
+                    full_name,
+                    tap: None,
+                    description: None,
+                    installed: Vec::new(),
+                    latest: None,
+                    pinned: false,
+                    outdated: false,
+                    requested: false,
+                    dependencies: Vec::new(),
+                })),
+                Err(e) => {
+                    self.finish_search(Err(format!("Error searching packages: {}", e)));
+                    return;
+                }
+            },
+            SearchStep::Info(_) => match result.and_then(CommandOutput::into_result).and_then(|output| brew::parse_installed(&output)) {
+                Ok(packages) => search.found.extend(packages),
+                Err(_) => {
+                    // Still show what matched, without the details
+                    let mut matches = search.matches;
+                    matches.sort_by_key(|p| p.name != search.query);
+                    self.finish_search(Ok((search.query, matches)));
+                    return;
+                }
+            },
+        }
+        let next = SearchStep::ALL
+            .into_iter()
+            .skip_while(|step| *step != search.step)
+            .skip(1)
+            .find_map(|step| search.args(step).map(|args| (step, args)));
+        match next {
+            Some((step, args)) => {
+                search.step = step;
+                search.running = self.runner.stream(&self.brew_request(&args.iter().map(String::as_str).collect::<Vec<_>>()));
+                if let Some(list) = &mut self.fullscreen_list {
+                    list.search = Some(search);
+                }
+            }
+            None => {
+                let mut found = search.found;
+                found.sort_by_key(|p| p.name != search.query);
+                self.finish_search(Ok((search.query, found)));
+            }
+        }
+    }
+
+    /// Shows the packages a search found, or why it found none.
+    fn finish_search(&mut self, outcome: std::result::Result<(String, Vec<Package>), String>) {
+        let Some(list) = &mut self.fullscreen_list else { return };
+        let packages = match outcome {
+            Ok((query, packages)) => {
+                self.status_message = packages.is_empty().then(|| format!("No formulae or casks match '{}'", query));
+                self.status_timer = 50;
+                packages
+            }
+            Err(message) => {
+                self.status_message = Some(message);
+                self.status_timer = 80;
+                list.editing_query = list.packages.is_empty();
+                return;
+            }
+        };
+        list.table_state.select((!packages.is_empty()).then_some(0));
+        list.editing_query = packages.is_empty();
+        list.packages = packages;
+        list.marked.clear();
+        list.progress.clear();
+    }
+
+    /// Asks to confirm `operation` on the marked packages, or on the
+    /// highlighted one if none are marked.
+    pub fn confirm_operation(&mut self, operation: PackageOperation) {
+        let Some(list) = &mut self.fullscreen_list else { return };
+        if list.selected().is_none() {
+            return;
+        }
+        let targets = list.targets(operation);
+        if targets.is_empty() {
+            let reason = match operation {
+                PackageOperation::Upgrade => "Pinned packages cannot be upgraded; unpin them first.",
+                PackageOperation::Install => "Already installed.",
+                PackageOperation::Uninstall => "Not installed.",
+            };
+            self.status_message = Some(reason.to_string());
+            self.status_timer = 50;
+            return;
+        }
+        list.confirming = Some((operation, targets));
+    }
+
+    /// Runs the operation waiting for confirmation in the background,
+    /// following each package's progress in the list.
+    pub fn start_operation(&mut self) {
+        let Some(list) = &mut self.fullscreen_list else { return };
+        let Some((operation, targets)) = list.confirming.take() else { return };
+        let requests = operation.requests(&targets);
+        if self.dry_run {
+            for request in &requests {
+                let _ = self.dry_run_log.run(request);
+            }
+            self.fullscreen_list = None;
+            self.fullscreen_output = Some(self.take_dry_run_report(None));
+            self.fullscreen_output_scroll = 0;
+            return;
+        }
+        list.operation = Some(operation);
+        list.progress = targets.iter().map(|p| (p.full_name.clone(), PackageState::Waiting)).collect();
+        let tweak = list.tweak.clone();
+        let mut label = match targets.as_slice() {
+            [package] => format!("{} {}", operation.running_label(), package.name),
+            _ => format!("{} {} packages", operation.running_label(), targets.len()),
+        };
+        label[..1].make_ascii_uppercase();
+        self.start_output_view(&tweak, label, requests);
+        if let Some(view) = &mut self.output_view {
+            view.package_operation = true;
+        }
+    }
+
     pub fn handle_confirmation<B: Backend>(
         &mut self,
         input: &str,
         terminal: &mut Terminal<B>,
-        run_interactive: impl Fn(&mut Terminal<B>, &str) -> Result<()>,
+        run_interactive: impl Fn(&mut Terminal<B>, &dyn CommandRunner, &CommandRequest) -> Result<CommandOutput>,
     ) -> Result<()> {
-        if let Some((tweak_name, command)) = self.pending_destructive_command.clone() {
+        if let Some((tweak, command)) = self.pending_destructive_command.clone() {
             if input.trim().to_lowercase() == "yes" {
                 // User confirmed, execute the destructive command
-                run_interactive(terminal, &command)?;
-                self.status_message = Some(format!("Successfully applied: {}", tweak_name.trim()));
-                self.status_timer = 50;
+                let snapshot = self.take_snapshot(&tweak);
+                let request = CommandRequest::shell(&command);
+                match self.run_tweak_command(terminal, &run_interactive, &tweak, HistoryAction::Apply, &request, true) {
+                    Ok(_) => {
+                        self.mark_applied(&tweak);
+                        let applied = format!("Successfully applied: {}", tweak.name);
+                        self.status_message = Some(Self::applied_message(applied, snapshot));
+                        self.status_timer = 50;
+                    }
+                    Err(e) => {
+                        self.status_message = Some(format!("Error executing '{}': {}", tweak.name, e));
+                        self.status_timer = 80;
+                    }
+                }
+                // Even a failed command may have changed something
+                self.refresh_probes();
             } else {
                 self.status_message = Some("Action canceled.".to_string());
                 self.status_timer = 50;
@@ -722,6 +1796,211 @@ impl App {
         Ok(())
     }
 
+    /// The tweak's current value according to its probe, if it can be read
+    /// and is acceptable to its param; used to pre-fill the value picker.
+    fn current_value(&self, tweak: &Tweak) -> Option<String> {
+        let param = tweak.action.param()?;
+        let value = match self.probe_states.get(&tweak.id) {
+            Some(ProbeState::Value(value)) => value.clone(),
+            _ => probe::current_value(self.runner.as_ref(), tweak.probe.as_ref()?).ok()??,
+        };
+        param.parse(&value).ok()
+    }
+
+    /// Moves the value picker one step up or down, for params that have steps.
+    pub fn step_text_input(&mut self, forward: bool) {
+        let stepped = self
+            .text_input_tweak
+            .as_ref()
+            .and_then(|tweak| tweak.action.param())
+            .and_then(|param| param.step(&self.input_buffer, forward));
+        if let Some(value) = stepped {
+            self.input_buffer = value;
+            self.text_input_error = None;
+        }
+    }
+
+    /// Validates the typed value and runs the prompting tweak with it. An
+    /// invalid value is reported in the prompt and nothing runs.
+    pub fn submit_text_input<B: Backend>(
+        &mut self,
+        terminal: &mut Terminal<B>,
+        run_interactive: impl Fn(&mut Terminal<B>, &dyn CommandRunner, &CommandRequest) -> Result<CommandOutput>,
+    ) -> Result<()> {
+        if let Some(prompt) = self.profile_prompt {
+            let name = self.input_buffer.trim().to_string();
+            self.cancel_text_input();
+            match prompt {
+                ProfilePrompt::Save => self.save_profile(&name),
+                ProfilePrompt::Compare => self.compare_profile(&name),
+            }
+            return Ok(());
+        }
+        let Some(tweak) = self.text_input_tweak.clone() else {
+            return Ok(());
+        };
+        let TweakAction::PromptThenRun { param, run, .. } = &tweak.action else {
+            return Ok(());
+        };
+        let value = match param.parse(&self.input_buffer) {
+            Ok(value) => value,
+            Err(problem) => {
+                self.text_input_error = Some(problem);
+                return Ok(());
+            }
+        };
+        let marks = self.text_input_marks;
+        self.cancel_text_input();
+        if marks {
+            self.mark(&tweak, Some(value));
+            return Ok(());
+        }
+
+        let requests = param::commands_for(run, &value);
+        if !tweak.runs_interactively() && !self.dry_run {
+            self.start_output_view(&tweak, format!("{} = {}", tweak.name, value), requests);
+            return Ok(());
+        }
+        let snapshot = self.take_snapshot(&tweak);
+        let interactive = tweak.runs_interactively();
+        let result = requests.iter().try_for_each(|request| {
+            self.run_tweak_command(terminal, &run_interactive, &tweak, HistoryAction::Apply, request, interactive)
+                .map(|_| ())
+        });
+        match result {
+            Ok(()) if self.dry_run => {
+                self.fullscreen_output = Some(self.take_dry_run_report(Some(&tweak)));
+                self.fullscreen_output_scroll = 0;
+            }
+            Ok(()) => {
+                let applied = format!("Successfully applied: {} = {}", tweak.name, value);
+                self.status_message = Some(Self::applied_message(applied, snapshot));
+                self.status_timer = 50;
+                self.mark_applied(&tweak);
+                self.refresh_probes();
+            }
+            Err(e) => {
+                self.status_message = Some(format!("Error executing '{}': {}", tweak.name, e));
+                self.status_timer = 80;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn cancel_text_input(&mut self) {
+        self.text_input_prompt = None;
+        self.text_input_tweak = None;
+        self.text_input_error = None;
+        self.text_input_marks = false;
+        self.profile_prompt = None;
+        self.input_buffer.clear();
+    }
+
+    /// Asks for the name to save the currently applied tweaks under.
+    pub fn prompt_save_profile(&mut self) {
+        if self.applied_tweaks.is_empty() {
+            self.status_message = Some("No tweaks are applied, so there is nothing to save.".to_string());
+            self.status_timer = 50;
+            return;
+        }
+        self.text_input_prompt = Some(format!("Save {} applied tweaks as profile [name]", self.applied_tweaks.len()));
+        self.input_buffer.clear();
+        self.text_input_error = None;
+        self.profile_prompt = Some(ProfilePrompt::Save);
+    }
+
+    /// Asks for a profile to compare the machine with, or stops comparing.
+    pub fn prompt_compare_profile(&mut self) {
+        if let Some((name, _)) = self.drift_profile.take() {
+            self.status_message = Some(format!("Stopped comparing with profile '{}'", name));
+            self.status_timer = 50;
+            return;
+        }
+        let names = Profile::list().unwrap_or_default();
+        self.text_input_prompt = Some(if names.is_empty() {
+            "Compare with profile [name or path]".to_string()
+        } else {
+            format!("Compare with profile [{}]", names.join(" | "))
+        });
+        self.input_buffer.clear();
+        self.text_input_error = None;
+        self.profile_prompt = Some(ProfilePrompt::Compare);
+    }
+
+    fn compare_profile(&mut self, name: &str) {
+        match self.profile_steps(name) {
+            Ok(steps) => {
+                self.status_message = Some(format!("Comparing with profile '{}'; drifted tweaks are highlighted", name));
+                self.drift_profile = Some((name.to_string(), steps));
+                self.refresh_probes();
+            }
+            Err(e) => self.status_message = Some(format!("Error loading profile: {:#}", e)),
+        }
+        self.status_timer = 80;
+    }
+
+    /// How the tweak compares with the profile being compared with, if it
+    /// is part of it. Based on the last probe results.
+    pub fn drift_state(&self, tweak_id: &str) -> Option<DriftState> {
+        let (_, steps) = self.drift_profile.as_ref()?;
+        let step = steps.iter().find(|step| step.tweak.id == tweak_id)?;
+        Some(drift::classify(step, self.probe_states.get(tweak_id)))
+    }
+
+    /// Ids of the tweaks in the compared profile that have drifted.
+    pub fn drifted_ids(&self) -> Vec<&str> {
+        self.drift_profile
+            .iter()
+            .flat_map(|(_, steps)| steps)
+            .map(|step| step.tweak.id.as_str())
+            .filter(|id| self.drift_state(id).is_some_and(|state| state.is_drifted()))
+            .collect()
+    }
+
+    fn save_profile(&mut self, name: &str) {
+        let (profile, skipped) = self.profile_from_applied();
+        self.status_message = Some(match profile.save(name) {
+            Ok(path) if skipped.is_empty() => format!("Saved profile '{}' to {}", name, path.display()),
+            Ok(path) => format!(
+                "Saved profile '{}' to {} without {} (current value unknown)",
+                name,
+                path.display(),
+                skipped.join(", ")
+            ),
+            Err(e) => format!("Error saving profile: {}", e),
+        });
+        self.status_timer = 80;
+    }
+
+    pub fn open_history(&mut self) {
+        match self.history.entries() {
+            Ok(entries) => self.history_view = Some(HistoryView::new(entries)),
+            Err(e) => {
+                self.status_message = Some(format!("Error reading history: {}", e));
+                self.status_timer = 80;
+            }
+        }
+    }
+
+    /// Display name of a top-level category id, falling back to the id for
+    /// categories that are no longer in the catalog.
+    pub fn category_name<'a>(&'a self, id: &'a str) -> &'a str {
+        self.tree
+            .find_by_id(id)
+            .map_or(id, |node| self.tree.node(node).name.as_str())
+    }
+
+    /// Switches dry-run mode on or off.
+    pub fn toggle_dry_run(&mut self) {
+        self.dry_run = !self.dry_run;
+        self.status_message = Some(if self.dry_run {
+            "Dry run on: commands are shown instead of executed.".to_string()
+        } else {
+            "Dry run off: commands will be executed.".to_string()
+        });
+        self.status_timer = 50;
+    }
+
     pub fn update_status_timer(&mut self) {
         if self.status_timer > 0 {
             self.status_timer -= 1;
@@ -731,11 +2010,669 @@ impl App {
         }
     }
 
-    pub fn find_tweak_by_name(&self, name: &str) -> Option<Tweak> {
-        self.categories
+    /// The batch for profile `name`, with includes expanded. A tweak listed
+    /// more than once keeps its first position and takes the value from the
+    /// last profile that lists it, so layered profiles can override a
+    /// baseline's values. Problems name the file the offending entry is in.
+    pub fn profile_steps(&self, name: &str) -> Result<Vec<BatchStep>> {
+        let mut entries: Vec<(Tweak, Option<String>, PathBuf)> = Vec::new();
+        for (source, entry) in Profile::resolve(name)? {
+            let tweak = self.lookup_tweak(&entry.tweak).map_err(|e| anyhow!("{}: {}", source.display(), e))?;
+            match entries.iter_mut().find(|(existing, _, _)| existing.id == tweak.id) {
+                Some((_, value, path)) => (*value, *path) = (entry.value, source),
+                None => entries.push((tweak, entry.value, source)),
+            }
+        }
+        entries
             .iter()
-            .flat_map(|category| &category.tweaks)
-            .find(|tweak| tweak.name.trim().eq_ignore_ascii_case(name.trim()))
-            .cloned()
+            .map(|(tweak, value, source)| {
+                BatchStep::new(tweak, value.as_deref()).map_err(|problem| anyhow!("{}: {}", source.display(), problem))
+            })
+            .collect()
+    }
+
+    /// A profile of the tweaks currently applied, with the current values of
+    /// those that take one. Returns the names of applied tweaks whose value
+    /// could not be read, which are left out.
+    pub fn profile_from_applied(&self) -> (Profile, Vec<String>) {
+        let mut profile = Profile::default();
+        let mut skipped = Vec::new();
+        for tweak in self.applied_tweaks.iter().filter_map(|id| self.find_tweak_by_name(id)) {
+            let value = match tweak.action.param() {
+                Some(_) => match self.current_value(&tweak) {
+                    Some(value) => Some(value),
+                    None => {
+                        skipped.push(tweak.name.clone());
+                        continue;
+                    }
+                },
+                None => None,
+            };
+            profile.tweaks.push(ProfileTweak { tweak: tweak.id.clone(), value });
+        }
+        (profile, skipped)
+    }
+
+    /// Looks a tweak up by its full id, by the last segment of its id, or
+    /// by its display name, ignoring case. Names that match several tweaks
+    /// are reported rather than resolved to one of them.
+    pub fn lookup_tweak(&self, name: &str) -> Result<Tweak, LookupError> {
+        let query = name.trim();
+        if let Some(tweak) = self.tree.tweaks().find(|tweak| tweak.id == query) {
+            return Ok(tweak.clone());
+        }
+        let leaf_matches: Vec<&Tweak> = self
+            .tree
+            .tweaks()
+            .filter(|tweak| tweak.id.rsplit('/').next() == Some(query))
+            .collect();
+        let matches = if leaf_matches.is_empty() {
+            self.tree.tweaks().filter(|tweak| tweak.name.eq_ignore_ascii_case(query)).collect()
+        } else {
+            leaf_matches
+        };
+        match matches.as_slice() {
+            [] => Err(LookupError::NotFound(query.to_string())),
+            [tweak] => Ok((*tweak).clone()),
+            _ => Err(LookupError::Ambiguous {
+                name: query.to_string(),
+                ids: matches.iter().map(|tweak| tweak.id.clone()).collect(),
+            }),
+        }
     }
-} 
\ No newline at end of file
+
+    /// Like [`App::lookup_tweak`], for callers that only need to know
+    /// whether `name` picks out one tweak.
+    pub fn find_tweak_by_name(&self, name: &str) -> Option<Tweak> {
+        self.lookup_tweak(name).ok()
+    }
+}
+
+/// Why a name given for a tweak does not pick out exactly one.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum LookupError {
+    #[error("Tweak not found: '{0}'")]
+    NotFound(String),
+    #[error("'{name}' matches more than one tweak: {}; use the full id", ids.join(", "))]
+    Ambiguous { name: String, ids: Vec<String> },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, shell_tweak, value_tweak};
+    use ratatui::backend::TestBackend;
+
+    const WRITE: &str = "defaults write com.apple.finder ShowPathbar -bool true";
+
+    fn terminal() -> Terminal<TestBackend> {
+        Terminal::new(TestBackend::new(80, 24)).unwrap()
+    }
+
+    /// Stands in for handing the terminal over; the fake runner marks the
+    /// invocation as interactive.
+    fn run_interactive(
+        _: &mut Terminal<TestBackend>,
+        runner: &dyn CommandRunner,
+        request: &CommandRequest,
+    ) -> Result<CommandOutput> {
+        runner.interactive(request)
+    }
+
+    /// A runner on which every key read for a snapshot is unset.
+    fn runner() -> Arc<ScriptedRunner> {
+        Arc::new(ScriptedRunner::new().always("read-type", CommandOutput::failed(1, "does not exist")))
+    }
+
+    /// The commands run for tweaks, leaving out snapshot reads.
+    fn commands(runner: &ScriptedRunner) -> Vec<(String, bool)> {
+        runner
+            .invocations()
+            .into_iter()
+            .map(|invocation| (invocation.request.display(), invocation.interactive))
+            .filter(|(command, _)| !command.contains("read-type"))
+            .collect()
+    }
+
+    fn apply(app: &mut App, name: &str) {
+        testing::select(app, name);
+        app.apply_selected_tweak(&mut terminal(), run_interactive).unwrap();
+        app.poll_output();
+    }
+
+    #[test]
+    fn apply_runs_a_setting_in_the_background_and_records_it() {
+        let runner = runner();
+        let mut app = testing::app(&runner, vec![shell_tweak("Show Path Bar", WRITE)]);
+        apply(&mut app, "Show Path Bar");
+
+        assert_eq!(commands(&runner), [(WRITE.to_string(), false)]);
+        assert_eq!(app.status_message.as_deref(), Some("Successfully applied: Show Path Bar"));
+        assert_eq!(app.applied_tweaks, ["test/show-path-bar"]);
+        let entries = app.history.entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert!(entries[0].succeeded());
+        assert_eq!(entries[0].command, WRITE);
+        let snapshot = app.snapshots.get("test/show-path-bar").unwrap().unwrap();
+        assert_eq!(snapshot.keys[0].previous, snapshot::SavedValue::Absent);
+    }
+
+    #[test]
+    fn failed_apply_is_reported_and_not_marked_applied() {
+        let runner = Arc::new(ScriptedRunner::new().always("defaults write", CommandOutput::failed(1, "boom")));
+        let mut app = testing::app(&runner, vec![shell_tweak("Show Path Bar", WRITE)]);
+        apply(&mut app, "Show Path Bar");
+
+        assert_eq!(app.status_message.as_deref(), Some("Error executing 'Show Path Bar': Command failed: boom"));
+        assert!(app.applied_tweaks.is_empty());
+        let entries = app.history.entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].exit_status, Some(1));
+        assert_eq!(entries[0].stderr, "boom");
+    }
+
+    #[test]
+    fn interactive_tweak_gets_the_terminal() {
+        let runner = runner();
+        let mut tweak = shell_tweak("Install Tools", "xcode-select --install");
+        tweak.interactive = true;
+        let mut app = testing::app(&runner, vec![tweak]);
+        apply(&mut app, "Install Tools");
+
+        assert_eq!(commands(&runner), [("xcode-select --install".to_string(), true)]);
+        assert!(app.output_view.is_none());
+        assert_eq!(app.applied_tweaks, ["test/install-tools"]);
+    }
+
+    #[test]
+    fn destructive_tweak_waits_for_confirmation() {
+        let runner = runner();
+        let mut tweak = shell_tweak("Reset Launchpad", "defaults delete com.apple.dock");
+        tweak.destructive = true;
+        let mut app = testing::app(&runner, vec![tweak]);
+
+        apply(&mut app, "Reset Launchpad");
+        assert!(app.confirmation_message.is_some());
+        assert!(commands(&runner).is_empty());
+
+        app.handle_confirmation("no", &mut terminal(), run_interactive).unwrap();
+        assert_eq!(app.status_message.as_deref(), Some("Action canceled."));
+        assert!(app.confirmation_message.is_none());
+        assert!(commands(&runner).is_empty());
+        assert!(app.history.entries().unwrap().is_empty());
+
+        apply(&mut app, "Reset Launchpad");
+        app.handle_confirmation(" YES ", &mut terminal(), run_interactive).unwrap();
+        assert_eq!(commands(&runner), [("defaults delete com.apple.dock".to_string(), true)]);
+        assert_eq!(app.status_message.as_deref(), Some("Successfully applied: Reset Launchpad"));
+        assert!(app.pending_destructive_command.is_none());
+        assert_eq!(app.history.entries().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn confirmed_tweak_refreshes_probes() {
+        let runner = Arc::new(ScriptedRunner::new().always("defaults read", CommandOutput::ok("1\n")));
+        let mut tweak = shell_tweak("Reset Launchpad", "defaults write com.apple.dock ResetLaunchPad -bool true");
+        tweak.destructive = true;
+        tweak.probe = Some(tweaks::Probe {
+            domain: "com.apple.dock".to_string(),
+            key: "ResetLaunchPad".to_string(),
+            expected: Some("1".to_string()),
+            default: None,
+            current_host: false,
+        });
+        let mut app = testing::app(&runner, vec![tweak]);
+
+        apply(&mut app, "Reset Launchpad");
+        app.handle_confirmation("yes", &mut terminal(), run_interactive).unwrap();
+        for _ in 0..200 {
+            app.poll_probes();
+            if app.probe_receiver.is_none() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(app.probe_states.get("test/reset-launchpad"), Some(&ProbeState::On));
+    }
+
+    #[test]
+    fn failed_confirmed_command_is_reported() {
+        let runner = Arc::new(ScriptedRunner::new().always("defaults delete", CommandOutput::failed(2, "")));
+        let mut tweak = shell_tweak("Reset Launchpad", "defaults delete com.apple.dock");
+        tweak.destructive = true;
+        let mut app = testing::app(&runner, vec![tweak]);
+
+        apply(&mut app, "Reset Launchpad");
+        app.handle_confirmation("yes", &mut terminal(), run_interactive).unwrap();
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("Error executing 'Reset Launchpad': Command failed with exit code 2")
+        );
+        assert!(app.applied_tweaks.is_empty());
+    }
+
+    #[test]
+    fn dry_run_only_reports_the_command() {
+        let runner = runner();
+        let mut app = testing::app(&runner, vec![shell_tweak("Show Path Bar", WRITE)]);
+        app.dry_run = true;
+        apply(&mut app, "Show Path Bar");
+
+        assert!(runner.invocations().is_empty());
+        assert!(app.fullscreen_output.as_deref().is_some_and(|report| report.contains(&format!("$ {}", WRITE))));
+        assert!(app.history.entries().unwrap().is_empty());
+    }
+
+    #[test]
+    fn forbidden_tweak_does_not_run() {
+        let runner = runner();
+        let mut app = testing::app(&runner, vec![shell_tweak("Show Path Bar", WRITE)]);
+        app.config.forbidden_tweaks = vec!["test".to_string()];
+        apply(&mut app, "Show Path Bar");
+
+        assert!(runner.invocations().is_empty());
+        assert!(app.status_message.as_deref().is_some_and(|message| message.contains("forbidden")));
+    }
+
+    #[test]
+    fn prompted_value_is_validated_and_passed_as_an_argument() {
+        let runner = runner();
+        let tweak = value_tweak("Dock Size", &[&["defaults", "write", "com.apple.dock", "tilesize", "-int", "{}"]]);
+        let mut app = testing::app(&runner, vec![tweak]);
+        apply(&mut app, "Dock Size");
+        assert_eq!(app.text_input_prompt.as_deref(), Some("Dock Size? [16-128]"));
+
+        app.input_buffer = "200".to_string();
+        app.submit_text_input(&mut terminal(), run_interactive).unwrap();
+        assert!(app.text_input_error.is_some());
+        assert!(commands(&runner).is_empty());
+
+        app.input_buffer = "64".to_string();
+        app.submit_text_input(&mut terminal(), run_interactive).unwrap();
+        app.poll_output();
+        assert!(app.text_input_prompt.is_none());
+        assert_eq!(commands(&runner), [("defaults write com.apple.dock tilesize -int 64".to_string(), false)]);
+        assert_eq!(app.status_message.as_deref(), Some("Successfully applied: Dock Size = 64"));
+    }
+
+    #[test]
+    fn revert_restores_the_snapshot() {
+        let runner = runner();
+        let mut app = testing::app(&runner, vec![shell_tweak("Show Path Bar", WRITE)]);
+        apply(&mut app, "Show Path Bar");
+        runner.take_invocations();
+
+        app.revert_selected_tweak(&mut terminal(), run_interactive).unwrap();
+        assert_eq!(commands(&runner), [("defaults delete com.apple.finder ShowPathbar".to_string(), false)]);
+        assert_eq!(app.status_message.as_deref(), Some("Successfully reverted: Show Path Bar"));
+        assert!(app.applied_tweaks.is_empty());
+        assert!(app.snapshots.get("test/show-path-bar").unwrap().is_none());
+    }
+
+    /// Polls the output pane until its commands have finished.
+    fn finish_output(app: &mut App) {
+        for _ in 0..100 {
+            app.poll_output();
+            if app.output_view.as_ref().is_none_or(|view| !view.is_running()) {
+                return;
+            }
+        }
+        panic!("commands did not finish");
+    }
+
+    #[test]
+    fn package_operation_from_a_list_is_recorded() {
+        let outdated = r#"{"formulae": [{"name": "wget", "installed_versions": ["1.24.5"], "current_version": "1.25.0"}]}"#;
+        let runner = Arc::new(
+            ScriptedRunner::new()
+                .once("brew outdated", CommandOutput::ok(outdated))
+                .always("brew outdated", CommandOutput::ok("{}")),
+        );
+        let mut tweak = testing::tweak(
+            "Outdated Packages",
+            TweakAction::OpenList { source: ListSource::BrewOutdated, on_select: ListSelectAction::BrewUpgrade },
+        );
+        tweak.kind = TweakKind::Query;
+        let mut app = testing::app(&runner, vec![tweak]);
+        apply(&mut app, "Outdated Packages");
+        app.confirm_operation(PackageOperation::Upgrade);
+        app.start_operation();
+        finish_output(&mut app);
+
+        let entries = app.history.entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].tweak_id, "test/outdated-packages");
+        assert_eq!(entries[0].command, "brew upgrade wget");
+        assert!(entries[0].succeeded());
+        let list = app.fullscreen_list.as_ref().unwrap();
+        assert_eq!(list.package_state(&list.packages[0]), Some(PackageState::Done));
+    }
+
+    /// An app showing the search list, with `query` typed in.
+    fn search_list(runner: &Arc<ScriptedRunner>, query: &str) -> App {
+        let mut tweak = testing::tweak(
+            "Search Packages",
+            TweakAction::OpenList { source: ListSource::BrewSearch, on_select: ListSelectAction::BrewInstall },
+        );
+        tweak.kind = TweakKind::Query;
+        let mut app = testing::app(runner, vec![tweak]);
+        apply(&mut app, "Search Packages");
+        app.fullscreen_list.as_mut().unwrap().query = Some(query.to_string());
+        app
+    }
+
+    fn finish_search(app: &mut App) -> &PackageList {
+        for _ in 0..10 {
+            app.poll_search();
+        }
+        let list = app.fullscreen_list.as_ref().unwrap();
+        assert!(list.search.is_none(), "search did not finish");
+        list
+    }
+
+    #[test]
+    fn search_runs_in_the_background() {
+        let info = r#"{"formulae": [
+            {"name": "wget2", "full_name": "wget2", "installed": []},
+            {"name": "wget", "full_name": "wget", "installed": []}
+        ]}"#;
+        let runner = Arc::new(
+            ScriptedRunner::new()
+                .always("brew search --formula", CommandOutput::ok("==> Formulae\nwget2\nwget\n"))
+                .always("brew search --cask", CommandOutput::failed(1, "Error: No formulae or casks found for \"wget\"."))
+                .always("brew info", CommandOutput::ok(info)),
+        );
+        let mut app = search_list(&runner, " wget ");
+        app.search_packages();
+        assert!(app.fullscreen_list.as_ref().unwrap().search.is_some());
+        assert!(app.fullscreen_list.as_ref().unwrap().packages.is_empty());
+
+        let list = finish_search(&mut app);
+        let names: Vec<&str> = list.packages.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["wget", "wget2"]);
+        assert!(!list.editing_query);
+        assert_eq!(app.status_message, None);
+        assert_eq!(
+            runner.invocations().iter().map(|i| i.request.display()).collect::<Vec<_>>(),
+            ["brew search --formula wget", "brew search --cask wget", "brew info --json=v2 --formula wget2 wget"]
+        );
+    }
+
+    #[test]
+    fn search_without_matches_is_not_an_error() {
+        let runner = Arc::new(ScriptedRunner::new().always(
+            "brew search",
+            CommandOutput::failed(1, "Error: No formulae or casks found for \"nope\"."),
+        ));
+        let mut app = search_list(&runner, "nope");
+        app.search_packages();
+        let list = finish_search(&mut app);
+
+        assert!(list.packages.is_empty());
+        assert!(list.editing_query);
+        assert_eq!(app.status_message.as_deref(), Some("No formulae or casks match 'nope'"));
+        assert_eq!(runner.invocations().len(), 2);
+    }
+
+    #[test]
+    fn failed_search_is_reported() {
+        let runner = Arc::new(ScriptedRunner::new().always(
+            "brew search --formula",
+            CommandOutput::failed(1, "Error: Failed to download formula.jws.json"),
+        ));
+        let mut app = search_list(&runner, "wget");
+        app.search_packages();
+        let list = finish_search(&mut app);
+
+        assert!(list.packages.is_empty());
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("Error searching packages: Command failed: Error: Failed to download formula.jws.json")
+        );
+        assert_eq!(runner.invocations().len(), 1);
+    }
+
+    #[test]
+    fn canceled_search_keeps_the_list() {
+        let runner = Arc::new(ScriptedRunner::new().always("brew search --formula", CommandOutput::ok("wget\n")));
+        let mut app = search_list(&runner, "wget");
+        app.search_packages();
+        app.fullscreen_list.as_mut().unwrap().search.as_mut().unwrap().cancel();
+        let list = finish_search(&mut app);
+
+        assert!(list.packages.is_empty());
+        assert!(list.editing_query);
+        assert_eq!(app.status_message.as_deref(), Some("Search for 'wget' canceled."));
+        assert_eq!(runner.invocations().len(), 1);
+    }
+
+    fn sleep_app() -> App {
+        testing::grouped_app(
+            &runner(),
+            vec![
+                ("computer-sleep", vec![shell_tweak("Sleep After", "pmset sleep 30"), shell_tweak("Never", "pmset sleep 0")]),
+                ("display-sleep", vec![shell_tweak("Sleep After", "pmset displaysleep 30")]),
+            ],
+        )
+    }
+
+    fn looked_up(app: &App, name: &str) -> Result<String, LookupError> {
+        app.lookup_tweak(name).map(|tweak| tweak.id)
+    }
+
+    #[test]
+    fn lookup_by_id_leaf_id_or_name() {
+        let app = sleep_app();
+        assert_eq!(looked_up(&app, "test/display-sleep/sleep-after"), Ok("test/display-sleep/sleep-after".to_string()));
+        assert_eq!(looked_up(&app, "never"), Ok("test/computer-sleep/never".to_string()));
+        assert_eq!(looked_up(&app, " NEVER "), Ok("test/computer-sleep/never".to_string()));
+        assert_eq!(looked_up(&app, "Never"), Ok("test/computer-sleep/never".to_string()));
+        assert_eq!(looked_up(&app, "always"), Err(LookupError::NotFound("always".to_string())));
+    }
+
+    #[test]
+    fn lookup_reports_ambiguous_names() {
+        let app = sleep_app();
+        let ambiguous = |name: &str| LookupError::Ambiguous {
+            name: name.to_string(),
+            ids: vec!["test/computer-sleep/sleep-after".to_string(), "test/display-sleep/sleep-after".to_string()],
+        };
+        assert_eq!(looked_up(&app, "sleep-after"), Err(ambiguous("sleep-after")));
+        assert_eq!(looked_up(&app, "sleep after"), Err(ambiguous("sleep after")));
+        assert!(app.find_tweak_by_name("Sleep After").is_none());
+        assert_eq!(
+            ambiguous("sleep-after").to_string(),
+            "'sleep-after' matches more than one tweak: test/computer-sleep/sleep-after, test/display-sleep/sleep-after; use the full id"
+        );
+    }
+
+    #[test]
+    fn marking_a_tweak_that_takes_a_value_asks_for_it() {
+        let runner = runner();
+        let tweak = value_tweak("Dock Size", &[&["defaults", "write", "com.apple.dock", "tilesize", "-int", "{}"]]);
+        let mut app = testing::app(&runner, vec![tweak, shell_tweak("Show Path Bar", WRITE)]);
+        testing::select(&mut app, "Dock Size");
+        app.toggle_mark();
+        assert!(app.text_input_marks);
+        app.input_buffer = "64".to_string();
+        app.submit_text_input(&mut terminal(), run_interactive).unwrap();
+        testing::select(&mut app, "Show Path Bar");
+        app.toggle_mark();
+
+        assert!(commands(&runner).is_empty());
+        assert_eq!(
+            app.marked_tweaks,
+            [("test/dock-size".to_string(), Some("64".to_string())), ("test/show-path-bar".to_string(), None)]
+        );
+
+        app.apply_marked_tweaks(&mut terminal(), run_interactive).unwrap();
+        assert!(app.output_view.is_some());
+        finish_output(&mut app);
+        assert_eq!(
+            commands(&runner),
+            [
+                ("defaults write com.apple.dock tilesize -int 64".to_string(), false),
+                (WRITE.to_string(), false),
+            ]
+        );
+        assert_eq!(app.status_message.as_deref(), Some("Applied 2 of 2 tweaks."));
+        assert!(app.marked_tweaks.is_empty());
+        assert_eq!(app.applied_tweaks, ["test/dock-size", "test/show-path-bar"]);
+        assert_eq!(app.history.entries().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn failed_batch_is_rolled_back_in_the_output_pane() {
+        let runner = Arc::new(
+            ScriptedRunner::new()
+                .always("read-type", CommandOutput::failed(1, "does not exist"))
+                .always("Dock", CommandOutput::failed(1, "no such domain")),
+        );
+        let dock = shell_tweak("Dock Autohide", "defaults write com.apple.Dock autohide -bool true");
+        let mut app = testing::app(&runner, vec![shell_tweak("Show Path Bar", WRITE), dock]);
+        for name in ["Show Path Bar", "Dock Autohide"] {
+            testing::select(&mut app, name);
+            app.toggle_mark();
+        }
+        app.apply_marked_tweaks(&mut terminal(), run_interactive).unwrap();
+        finish_output(&mut app);
+
+        assert_eq!(
+            commands(&runner),
+            [
+                (WRITE.to_string(), false),
+                ("defaults write com.apple.Dock autohide -bool true".to_string(), false),
+                ("defaults delete com.apple.Dock autohide".to_string(), false),
+                ("defaults delete com.apple.finder ShowPathbar".to_string(), false),
+            ]
+        );
+        let view = app.output_view.as_ref().unwrap();
+        assert!(matches!(&view.finished, Some((OutputOutcome::Failed(e), _)) if e.contains("no such domain")));
+        assert!(view.lines.iter().any(|(line, _)| line == "Rolled back: Show Path Bar"));
+        assert_eq!(app.marked_tweaks.len(), 2);
+        assert!(app.applied_tweaks.is_empty());
+        let actions: Vec<HistoryAction> = app.history.entries().unwrap().iter().map(|entry| entry.action).collect();
+        assert_eq!(actions, [HistoryAction::Apply, HistoryAction::Apply, HistoryAction::Revert, HistoryAction::Revert]);
+    }
+
+    fn package(full_name: &str, outdated: bool, pinned: bool) -> Package {
+        Package {
+            kind: if full_name.contains("/cask/") { PackageKind::Cask } else { PackageKind::Formula },
+            name: full_name.rsplit('/').next().unwrap().to_string(),
+            full_name: full_name.to_string(),
+            tap: None,
+            description: None,
+            installed: vec!["1.0".to_string()],
+            latest: Some("2.0".to_string()),
+            pinned,
+            outdated,
+            requested: true,
+            dependencies: Vec::new(),
+        }
+    }
+
+    /// An upgrade list of `packages`, with all of them being upgraded.
+    fn upgrading(packages: Vec<Package>) -> PackageList {
+        let tweak = testing::tweak(
+            "Outdated Packages",
+            TweakAction::OpenList { source: ListSource::BrewOutdated, on_select: ListSelectAction::BrewUpgrade },
+        );
+        let mut list = PackageList::new(&tweak, ListSource::BrewOutdated, ListSelectAction::BrewUpgrade, packages);
+        list.operation = Some(PackageOperation::Upgrade);
+        list.progress = list.packages.iter().map(|p| (p.full_name.clone(), PackageState::Waiting)).collect();
+        list
+    }
+
+    fn states(list: &PackageList) -> Vec<PackageState> {
+        list.packages.iter().map(|p| list.package_state(p).unwrap()).collect()
+    }
+
+    fn marked(list: &PackageList) -> Vec<&str> {
+        let mut marked: Vec<&str> = list.marked.iter().map(String::as_str).collect();
+        marked.sort();
+        marked
+    }
+
+    #[test]
+    fn mark_all_then_invert_skips_pinned_packages() {
+        let mut list = upgrading(vec![package("git", true, false), package("node", true, true), package("wget", true, false)]);
+        list.mark_all();
+        assert_eq!(marked(&list), ["git", "wget"]);
+        list.invert_marks();
+        assert!(list.marked.is_empty());
+
+        list.toggle_mark();
+        list.invert_marks();
+        assert_eq!(marked(&list), ["wget"]);
+        let targets: Vec<String> = list.targets(PackageOperation::Upgrade).into_iter().map(|p| p.name).collect();
+        assert_eq!(targets, ["wget"]);
+    }
+
+    #[test]
+    fn progress_follows_each_package_as_it_finishes() {
+        use PackageState::*;
+        let mut list = upgrading(vec![package("git", true, false), package("homebrew/cask/iterm2", true, false), package("wget", true, false)]);
+        let lines = |lines: &[&str]| -> Vec<(String, bool)> { lines.iter().map(|line| (line.to_string(), false)).collect() };
+
+        list.track_progress(&lines(&["==> Upgrading git", "==> Downloading https://example.com/git.tar.gz"]));
+        assert_eq!(states(&list), [Running, Waiting, Waiting]);
+        list.track_progress(&lines(&["==> Upgrading Cask iterm2", "==> Upgrading wget"]));
+        assert_eq!(states(&list), [Done, Done, Running]);
+        list.track_progress(&lines(&["Error: wget: checksum mismatch"]));
+        assert_eq!(states(&list), [Done, Done, Failed]);
+
+        list.finish_operation(false, None);
+        assert_eq!(states(&list), [Done, Done, Failed]);
+        assert_eq!(marked(&list), ["wget"]);
+    }
+
+    #[test]
+    fn packages_not_seen_to_finish_fail_with_the_command() {
+        use PackageState::*;
+        let mut list = upgrading(vec![package("git", true, false), package("wget", true, false)]);
+        list.track_progress(&[("==> Upgrading git".to_string(), false)]);
+        list.finish_operation(false, None);
+        assert_eq!(states(&list), [Failed, Failed]);
+
+        let mut list = upgrading(vec![package("git", true, false), package("wget", true, false)]);
+        list.track_progress(&[("==> Upgrading git".to_string(), false)]);
+        list.finish_operation(true, None);
+        assert_eq!(states(&list), [Done, Done]);
+        assert!(list.marked.is_empty());
+    }
+
+    #[test]
+    fn refreshed_packages_decide_what_failed() {
+        let mut list = upgrading(vec![package("git", true, false), package("wget", true, false)]);
+        list.finish_operation(false, Some(vec![package("wget", true, false)]));
+
+        assert_eq!(states(&list), [PackageState::Done, PackageState::Failed]);
+        assert_eq!(marked(&list), ["wget"]);
+        assert!(!list.packages[0].outdated);
+        assert_eq!(list.packages[0].installed, ["2.0"]);
+        assert!(list.packages[1].outdated);
+    }
+
+    #[test]
+    fn list_is_kept_when_brew_info_fails_after_an_install() {
+        let info = r#"{"formulae": [{"name": "wget", "full_name": "wget", "installed": []}]}"#;
+        let runner = Arc::new(
+            ScriptedRunner::new()
+                .always("brew search --formula", CommandOutput::ok("wget\n"))
+                .once("brew info", CommandOutput::ok(info))
+                .always("brew info", CommandOutput::failed(1, "Error: API unavailable")),
+        );
+        let mut app = search_list(&runner, "wget");
+        app.search_packages();
+        finish_search(&mut app);
+        app.confirm_operation(PackageOperation::Install);
+        app.start_operation();
+        finish_output(&mut app);
+
+        assert_eq!(runner.invocations().last().unwrap().request.display(), "brew info --json=v2 --formula wget");
+        let list = app.fullscreen_list.as_ref().unwrap();
+        assert_eq!(list.packages.len(), 1);
+        assert_eq!(list.package_state(&list.packages[0]), Some(PackageState::Done));
+        assert!(list.packages[0].installed.is_empty(), "kept as it was listed");
+    }
+}
@@ -0,0 +1,504 @@
+use crate::app::App;
+use crate::history::HistoryAction;
+use crate::param;
+use crate::runner::{CommandLine, CommandRequest};
+use crate::snapshot::Snapshot;
+use crate::tweaks::{Tweak, TweakAction, TweakKind};
+use anyhow::Result;
+use std::collections::VecDeque;
+
+/// One tweak in a batch, with the commands that apply it. Restarts such as
+/// `killall Dock` are split off so they can run once for the whole batch.
+#[derive(Debug, Clone)]
+pub struct BatchStep {
+    pub tweak: Tweak,
+    pub value: Option<String>,
+    pub requests: Vec<CommandRequest>,
+    pub restarts: Vec<String>,
+}
+
+impl BatchStep {
+    /// Checks that `tweak` can be applied unattended with `value` and
+    /// builds its commands.
+    pub fn new(tweak: &Tweak, value: Option<&str>) -> Result<Self, String> {
+        if tweak.kind == TweakKind::Query {
+            return Err(format!("'{}' only shows information and cannot be part of a batch", tweak.name));
+        }
+        let (requests, value) = match (&tweak.action, value) {
+            (TweakAction::Shell { command }, None) => (vec![CommandRequest::shell(command)], None),
+            (TweakAction::Shell { .. }, Some(_)) => return Err(format!("'{}' does not take a value", tweak.name)),
+            (TweakAction::PromptThenRun { param, run, .. }, Some(value)) => {
+                let value = param
+                    .parse(value)
+                    .map_err(|problem| format!("invalid value for '{}': {}", tweak.name, problem))?;
+                (param::commands_for(run, &value), Some(value))
+            }
+            (TweakAction::PromptThenRun { param, .. }, None) => {
+                return Err(format!("'{}' needs a value <{}>", tweak.name, param.hint()));
+            }
+            (TweakAction::Builtin { .. } | TweakAction::OpenList { .. }, _) => {
+                return Err(format!("'{}' cannot be part of a batch", tweak.name));
+            }
+        };
+        let (requests, restarts) = split_restarts(&requests);
+        Ok(BatchStep {
+            tweak: tweak.clone(),
+            value,
+            requests,
+            restarts,
+        })
+    }
+
+    /// The tweak's name, with the value it is set to.
+    pub fn label(&self) -> String {
+        match &self.value {
+            Some(value) => format!("{} = {}", self.tweak.name, value),
+            None => self.tweak.name.clone(),
+        }
+    }
+}
+
+/// What happened to a batch.
+#[derive(Debug, Default)]
+pub struct BatchReport {
+    /// Ids of the tweaks that are applied once the batch has finished.
+    pub applied: Vec<String>,
+    /// The step that failed and why; it and everything before it were
+    /// rolled back.
+    pub failure: Option<(String, String)>,
+    /// Labels of the steps that were undone after the failure.
+    pub rolled_back: Vec<String>,
+    /// Problems that did not stop the batch, such as a step that could not
+    /// be rolled back or a restart that failed.
+    pub warnings: Vec<String>,
+}
+
+impl BatchReport {
+    pub fn succeeded(&self) -> bool {
+        self.failure.is_none()
+    }
+
+    /// A few lines describing the outcome, for the CLI and the TUI.
+    pub fn summary(&self, total: usize) -> String {
+        let mut lines = Vec::new();
+        match &self.failure {
+            None => lines.push(format!("Applied {} of {} tweaks.", self.applied.len(), total)),
+            Some((label, error)) => {
+                lines.push(format!("Failed at '{}': {}", label, error));
+                if self.rolled_back.is_empty() {
+                    lines.push("Nothing needed to be rolled back.".to_string());
+                } else {
+                    lines.push(format!("Rolled back: {}", self.rolled_back.join(", ")));
+                }
+            }
+        }
+        lines.extend(self.warnings.iter().map(|warning| format!("Warning: {}", warning)));
+        lines.join("\n")
+    }
+}
+
+/// Applies `steps` in order through `execute`, which runs one command for a
+/// tweak and records it. See [`BatchRun`] for what happens on failure.
+pub fn run(
+    app: &App,
+    steps: &[BatchStep],
+    execute: &mut dyn FnMut(&Tweak, HistoryAction, &CommandRequest) -> Result<()>,
+) -> BatchReport {
+    let mut run = BatchRun::new(app, steps.to_vec());
+    while let Some((tweak, action, request)) = run.next(app) {
+        let result = execute(&tweak, action, &request);
+        run.finish(app, result);
+    }
+    run.into_report()
+}
+
+/// A batch being applied one command at a time, so the caller decides how
+/// each command runs: [`BatchRun::next`] hands out the next command and
+/// [`BatchRun::finish`] takes its outcome.
+///
+/// The steps run in order until one fails. That step and the ones that
+/// already ran are then reverted, newest first, using their snapshots or
+/// disable commands, since a failed command may have changed something too.
+/// The merged restarts of every step that ran are executed once at the end,
+/// whether or not the batch succeeded.
+#[derive(Debug)]
+pub struct BatchRun {
+    steps: Vec<BatchStep>,
+    phase: Phase,
+    /// Steps that ran, with the snapshot taken before each.
+    started: Vec<(usize, Option<Snapshot>)>,
+    /// Processes to restart, with the step they were first seen in.
+    restarts: Vec<(String, usize)>,
+    report: BatchReport,
+}
+
+#[derive(Debug)]
+enum Phase {
+    /// Running the `next` command of `step`.
+    Apply {
+        step: usize,
+        next: usize,
+        snapshot: Option<Snapshot>,
+    },
+    /// Undoing the last of `started` with the commands left in `requests`.
+    Rollback { requests: VecDeque<CommandRequest> },
+    /// Running the `next` restart.
+    Restart { next: usize },
+    Done,
+}
+
+impl BatchRun {
+    pub fn new(app: &App, steps: Vec<BatchStep>) -> Self {
+        let mut run = BatchRun {
+            steps,
+            phase: Phase::Done,
+            started: Vec::new(),
+            restarts: Vec::new(),
+            report: BatchReport::default(),
+        };
+        run.start_step(app, 0);
+        run
+    }
+
+    pub fn steps(&self) -> &[BatchStep] {
+        &self.steps
+    }
+
+    /// The next command to run, with the tweak and action to record it
+    /// under, or `None` once the batch is over.
+    pub fn next(&mut self, app: &App) -> Option<(Tweak, HistoryAction, CommandRequest)> {
+        loop {
+            match &mut self.phase {
+                Phase::Apply { step, next, .. } => {
+                    let (step, next) = (*step, *next);
+                    if let Some(request) = self.steps[step].requests.get(next) {
+                        return Some((self.steps[step].tweak.clone(), HistoryAction::Apply, request.clone()));
+                    }
+                    self.end_step(step);
+                    self.start_step(app, step + 1);
+                }
+                Phase::Rollback { requests } => match requests.front() {
+                    Some(request) => {
+                        let (step, _) = self.started.last()?;
+                        return Some((self.steps[*step].tweak.clone(), HistoryAction::Revert, request.clone()));
+                    }
+                    None => {
+                        let (step, snapshot) = self.started.pop()?;
+                        match forget_own_snapshot(app, &self.steps[step], snapshot.as_ref()) {
+                            Ok(()) => self.report.rolled_back.push(self.steps[step].label()),
+                            Err(e) => self.rollback_failed(step, e),
+                        }
+                        self.start_rollback(app);
+                    }
+                },
+                Phase::Restart { next } => {
+                    let (process, step) = self.restarts.get(*next)?;
+                    let action = if self.report.succeeded() { HistoryAction::Apply } else { HistoryAction::Revert };
+                    let request = CommandRequest::exec("killall", [process.as_str()]);
+                    return Some((self.steps[*step].tweak.clone(), action, request));
+                }
+                Phase::Done => return None,
+            }
+        }
+    }
+
+    /// Takes the outcome of the command [`BatchRun::next`] handed out last.
+    pub fn finish(&mut self, app: &App, result: Result<()>) {
+        match (&mut self.phase, result) {
+            (Phase::Apply { next, .. }, Ok(())) => {
+                *next += 1;
+            }
+            (Phase::Apply { step, .. }, Err(e)) => {
+                let step = *step;
+                self.report.failure = Some((self.steps[step].label(), e.to_string()));
+                self.end_step(step);
+                self.start_rollback(app);
+            }
+            (Phase::Rollback { requests }, Ok(())) => {
+                requests.pop_front();
+            }
+            (Phase::Rollback { .. }, Err(e)) => {
+                if let Some((step, _)) = self.started.pop() {
+                    self.rollback_failed(step, e);
+                }
+                self.start_rollback(app);
+            }
+            (Phase::Restart { next }, result) => {
+                if let Err(e) = result {
+                    let process = &self.restarts[*next].0;
+                    self.report.warnings.push(format!("could not restart {}: {}", process, e));
+                }
+                *next += 1;
+            }
+            (Phase::Done, _) => {}
+        }
+    }
+
+    pub fn into_report(self) -> BatchReport {
+        self.report
+    }
+
+    /// Snapshots `step` and starts running it, or moves on to the restarts
+    /// once every step has succeeded.
+    fn start_step(&mut self, app: &App, step: usize) {
+        let Some(batch_step) = self.steps.get(step) else {
+            self.report.applied = self
+                .steps
+                .iter()
+                .filter(|step| step.tweak.tracks_applied())
+                .map(|step| step.tweak.id.clone())
+                .collect();
+            self.phase = Phase::Restart { next: 0 };
+            return;
+        };
+        let snapshot = app.take_snapshot(&batch_step.tweak).unwrap_or_else(|e| {
+            self.report.warnings.push(format!("no undo snapshot for '{}': {}", batch_step.tweak.name, e));
+            None
+        });
+        self.phase = Phase::Apply { step, next: 0, snapshot };
+    }
+
+    /// Remembers `step` and its restarts for the rollback, whether it
+    /// finished or failed part-way.
+    fn end_step(&mut self, step: usize) {
+        let Phase::Apply { snapshot, .. } = std::mem::replace(&mut self.phase, Phase::Done) else {
+            return;
+        };
+        for process in &self.steps[step].restarts {
+            if !self.restarts.iter().any(|(p, _)| p == process) {
+                self.restarts.push((process.clone(), step));
+            }
+        }
+        self.started.push((step, snapshot));
+    }
+
+    /// Starts undoing the newest step that ran, putting back the values read
+    /// just before it or falling back to the tweak's usual revert. Moves on
+    /// to the restarts once nothing is left to undo.
+    fn start_rollback(&mut self, app: &App) {
+        while let Some((step, snapshot)) = self.started.last() {
+            let step = *step;
+            let requests = match snapshot.as_ref().map(Snapshot::restore_requests) {
+                Some(Ok(requests)) => Ok(requests),
+                _ => app.revert_requests(&self.steps[step].tweak),
+            };
+            match requests {
+                Ok(requests) => {
+                    // Restarts already run once at the end of the batch
+                    let (requests, _) = split_restarts(&requests);
+                    self.phase = Phase::Rollback { requests: requests.into() };
+                    return;
+                }
+                Err(e) => {
+                    self.started.pop();
+                    self.rollback_failed(step, anyhow::Error::msg(e));
+                }
+            }
+        }
+        self.phase = Phase::Restart { next: 0 };
+    }
+
+    fn rollback_failed(&mut self, step: usize, e: anyhow::Error) {
+        let name = &self.steps[step].tweak.name;
+        self.report.warnings.push(format!("could not roll back '{}': {}", name, e));
+    }
+}
+
+/// Removes the stored snapshot for `step` if it is the one this batch took,
+/// leaving one kept from an earlier apply alone.
+fn forget_own_snapshot(app: &App, step: &BatchStep, snapshot: Option<&Snapshot>) -> Result<()> {
+    if snapshot.is_some() && app.snapshots.get(&step.tweak.id)?.as_ref() == snapshot {
+        app.snapshots.remove(&step.tweak.id)?;
+    }
+    Ok(())
+}
+
+/// Separates plain `killall <process>` restarts from `requests`: exec
+/// requests that only restart are dropped, and a trailing `&& killall ...`
+/// or `; killall ...` is cut from shell lines.
+pub fn split_restarts(requests: &[CommandRequest]) -> (Vec<CommandRequest>, Vec<String>) {
+    let mut kept = Vec::new();
+    let mut restarts: Vec<String> = Vec::new();
+    let mut add = |processes: Vec<String>| {
+        for process in processes {
+            if !restarts.contains(&process) {
+                restarts.push(process);
+            }
+        }
+    };
+    for request in requests {
+        match &request.line {
+            CommandLine::Exec { program, args } if program == "killall" => match restart_targets(args) {
+                Some(processes) => add(processes),
+                None => kept.push(request.clone()),
+            },
+            CommandLine::Shell(line) => {
+                let mut line = line.as_str();
+                let mut trailing = Vec::new();
+                while let Some((head, tail)) = split_last_command(line) {
+                    let words: Vec<&str> = tail.split_whitespace().collect();
+                    let Some(processes) = words.split_first().filter(|(w, _)| **w == "killall").and_then(|(_, rest)| restart_targets(rest)) else {
+                        break;
+                    };
+                    trailing.splice(0..0, processes);
+                    line = head.trim_end();
+                }
+                add(trailing);
+                let mut request = request.clone();
+                request.line = CommandLine::Shell(line.to_string());
+                kept.push(request);
+            }
+            _ => kept.push(request.clone()),
+        }
+    }
+    (kept, restarts)
+}
+
+/// Splits `line` before its last command, at whichever of `&&` and `;`
+/// comes last.
+fn split_last_command(line: &str) -> Option<(&str, &str)> {
+    ["&&", ";"]
+        .iter()
+        .filter_map(|separator| line.rsplit_once(separator))
+        .max_by_key(|(head, _)| head.len())
+}
+
+/// The processes named by the arguments of a plain `killall`, or `None`
+/// if it passes options (e.g. a signal) or anything that is not a name.
+pub fn restart_targets<S: AsRef<str>>(args: &[S]) -> Option<Vec<String>> {
+    let plain = !args.is_empty()
+        && args.iter().all(|arg| {
+            let arg = arg.as_ref();
+            !arg.starts_with('-') && arg.chars().all(|c| c.is_alphanumeric() || c == '.' || c == '_')
+        });
+    plain.then(|| args.iter().map(|arg| arg.as_ref().to_string()).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::{CommandOutput, ScriptedRunner};
+    use crate::testing::{self, shell_tweak};
+    use anyhow::anyhow;
+    use std::sync::Arc;
+
+    fn steps(app: &App, names: &[&str]) -> Vec<BatchStep> {
+        names
+            .iter()
+            .map(|name| BatchStep::new(&app.find_tweak_by_name(name).unwrap(), None).unwrap())
+            .collect()
+    }
+
+    /// Runs `steps`, failing every command that contains one of `fail`,
+    /// and returns the report with what ran.
+    fn run_failing(app: &App, steps: &[BatchStep], fail: &[&str]) -> (BatchReport, Vec<(HistoryAction, String)>) {
+        let mut ran = Vec::new();
+        let report = run(app, steps, &mut |_, action, request| {
+            ran.push((action, request.display()));
+            match fail.iter().any(|fail| request.display().contains(fail)) {
+                true => Err(anyhow!("boom")),
+                false => Ok(()),
+            }
+        });
+        (report, ran)
+    }
+
+    fn app() -> App {
+        let runner = Arc::new(ScriptedRunner::new().always("read-type", CommandOutput::failed(1, "does not exist")));
+        let mut finder = shell_tweak("Path Bar", "defaults write com.apple.finder ShowPathbar -bool true && killall Finder");
+        finder.disable_command = "defaults write com.apple.finder ShowPathbar -bool false && killall Finder".to_string();
+        let status = shell_tweak("Status Bar", "defaults write com.apple.finder ShowStatusBar -bool true && killall Finder");
+        let dock = shell_tweak("Autohide", "defaults write com.apple.dock autohide -bool true; killall Dock");
+        testing::app(&runner, vec![finder, status, dock])
+    }
+
+    #[test]
+    fn restarts_run_once_after_every_step() {
+        let app = app();
+        let steps = steps(&app, &["Path Bar", "Status Bar"]);
+        assert_eq!(steps[0].restarts, ["Finder"]);
+        let (report, ran) = run_failing(&app, &steps, &[]);
+
+        assert!(report.succeeded());
+        assert_eq!(report.applied, ["test/path-bar", "test/status-bar"]);
+        assert_eq!(
+            ran,
+            [
+                (HistoryAction::Apply, "defaults write com.apple.finder ShowPathbar -bool true".to_string()),
+                (HistoryAction::Apply, "defaults write com.apple.finder ShowStatusBar -bool true".to_string()),
+                (HistoryAction::Apply, "killall Finder".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn restarts_after_semicolons_are_split_off() {
+        let (requests, restarts) = split_restarts(&[
+            CommandRequest::shell("defaults write com.apple.dock autohide -bool true; killall Dock"),
+            CommandRequest::shell("defaults write a b -bool true && killall Finder ; killall SystemUIServer"),
+            CommandRequest::shell("echo '{x;}' || killall -HUP Dock"),
+        ]);
+        let lines: Vec<String> = requests.iter().map(CommandRequest::display).collect();
+        assert_eq!(
+            lines,
+            [
+                "defaults write com.apple.dock autohide -bool true",
+                "defaults write a b -bool true",
+                "echo '{x;}' || killall -HUP Dock",
+            ]
+        );
+        assert_eq!(restarts, ["Dock", "Finder", "SystemUIServer"]);
+    }
+
+    #[test]
+    fn failure_rolls_back_newest_first() {
+        let app = app();
+        let steps = steps(&app, &["Path Bar", "Status Bar", "Autohide"]);
+        let (report, ran) = run_failing(&app, &steps, &["autohide -bool"]);
+
+        assert_eq!(report.failure, Some(("Autohide".to_string(), "boom".to_string())));
+        assert_eq!(report.rolled_back, ["Autohide", "Status Bar", "Path Bar"]);
+        assert!(report.applied.is_empty());
+        assert_eq!(
+            ran[3..],
+            [
+                (HistoryAction::Revert, "defaults delete com.apple.dock autohide".to_string()),
+                (HistoryAction::Revert, "defaults delete com.apple.finder ShowStatusBar".to_string()),
+                (HistoryAction::Revert, "defaults delete com.apple.finder ShowPathbar".to_string()),
+                (HistoryAction::Revert, "killall Finder".to_string()),
+                (HistoryAction::Revert, "killall Dock".to_string()),
+            ]
+        );
+        // The snapshots taken for the batch are gone again
+        assert!(app.snapshots.get("test/path-bar").unwrap().is_none());
+        assert!(app.snapshots.get("test/autohide").unwrap().is_none());
+    }
+
+    #[test]
+    fn step_failing_at_its_first_command_is_rolled_back() {
+        let app = app();
+        let steps = steps(&app, &["Path Bar", "Autohide"]);
+        let (report, ran) = run_failing(&app, &steps, &["ShowPathbar -bool true"]);
+
+        assert_eq!(report.failure.as_ref().map(|(label, _)| label.as_str()), Some("Path Bar"));
+        assert_eq!(report.rolled_back, ["Path Bar"]);
+        assert!(report.warnings.is_empty(), "{:?}", report.warnings);
+        assert_eq!(
+            ran[1..],
+            [
+                (HistoryAction::Revert, "defaults delete com.apple.finder ShowPathbar".to_string()),
+                (HistoryAction::Revert, "killall Finder".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn failed_rollback_is_a_warning() {
+        let app = app();
+        let steps = steps(&app, &["Path Bar", "Autohide"]);
+        let (report, _) = run_failing(&app, &steps, &["autohide -bool", "delete com.apple.finder"]);
+        assert_eq!(report.rolled_back, ["Autohide"]);
+        assert_eq!(report.warnings.len(), 1, "{:?}", report.warnings);
+        assert!(report.warnings[0].starts_with("could not roll back 'Path Bar'"));
+    }
+}
@@ -0,0 +1,432 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// Whether a package is a formula or a cask; brew needs `--cask` for the
+/// latter when a formula has the same name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PackageKind {
+    Formula,
+    Cask,
+}
+
+impl PackageKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            PackageKind::Formula => "formula",
+            PackageKind::Cask => "cask",
+        }
+    }
+}
+
+/// A Homebrew formula or cask, installed or found by a search.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Package {
+    pub kind: PackageKind,
+    /// Short name (the token, for casks), as `brew list` prints it.
+    pub name: String,
+    /// Name qualified with its tap for packages outside the core taps.
+    pub full_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tap: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Installed versions, oldest first; formulae can have several kegs.
+    /// Empty when the package is not installed.
+    pub installed: Vec<String>,
+    /// The newest version available, when brew reports one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latest: Option<String>,
+    pub pinned: bool,
+    pub outdated: bool,
+    /// Installed on request rather than only as a dependency; always true
+    /// for casks.
+    pub requested: bool,
+    /// Formulae (and casks, for casks) this package depends on.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub dependencies: Vec<String>,
+}
+
+/// Headings of the columns returned by [`Package::columns`].
+pub const COLUMNS: [&str; 7] = ["Name", "Type", "Installed", "Latest", "Pinned", "Tap", "Dependencies"];
+
+impl Package {
+    /// The package's fields as display text, in the order of [`COLUMNS`].
+    pub fn columns(&self) -> [String; 7] {
+        let or_dash = |text: String| if text.is_empty() { "-".to_string() } else { text };
+        [
+            self.name.clone(),
+            self.kind.label().to_string(),
+            or_dash(self.installed.join(", ")),
+            or_dash(self.latest.clone().unwrap_or_default()),
+            if self.pinned { "yes" } else { "no" }.to_string(),
+            or_dash(self.tap.clone().unwrap_or_default()),
+            or_dash(self.dependencies.join(", ")),
+        ]
+    }
+}
+
+/// Parses the output of `brew info --json=v2 --installed`, or of
+/// `brew info --json=v2` for named formulae or casks.
+pub fn parse_installed(json: &str) -> Result<Vec<Package>> {
+    let info: Info = serde_json::from_str(json).context("unexpected output from brew info")?;
+    let formulae = info.formulae.into_iter().map(|formula| Package {
+        kind: PackageKind::Formula,
+        latest: formula.versions.stable.or(formula.versions.head),
+        requested: formula.installed.iter().any(|keg| keg.installed_on_request),
+        installed: formula.installed.into_iter().map(|keg| keg.version).collect(),
+        name: formula.name,
+        full_name: formula.full_name,
+        tap: formula.tap,
+        description: formula.desc,
+        pinned: formula.pinned,
+        outdated: formula.outdated,
+        dependencies: formula.dependencies,
+    });
+    let casks = info.casks.into_iter().map(|cask| Package {
+        kind: PackageKind::Cask,
+        installed: cask.installed.into_iter().collect(),
+        latest: cask.version,
+        name: cask.token,
+        full_name: cask.full_token,
+        tap: cask.tap,
+        description: cask.desc,
+        pinned: false,
+        outdated: cask.outdated,
+        requested: true,
+        dependencies: cask.depends_on.formula.into_iter().chain(cask.depends_on.cask).collect(),
+    });
+    Ok(formulae.chain(casks).collect())
+}
+
+/// Parses the output of `brew outdated --json=v2`. Dependencies are not
+/// part of it, so they are left empty.
+pub fn parse_outdated(json: &str) -> Result<Vec<Package>> {
+    let outdated: Outdated = serde_json::from_str(json).context("unexpected output from brew outdated")?;
+    let formulae = outdated.formulae.into_iter().map(|formula| {
+        outdated_package(PackageKind::Formula, formula.name, formula.installed_versions, formula.current_version, formula.pinned)
+    });
+    let casks = outdated.casks.into_iter().map(|cask| {
+        outdated_package(PackageKind::Cask, cask.name, cask.installed_versions, cask.current_version, false)
+    });
+    Ok(formulae.chain(casks).collect())
+}
+
+fn outdated_package(kind: PackageKind, full_name: String, installed: Versions, latest: String, pinned: bool) -> Package {
+    // brew outdated only qualifies names from taps other than the core ones
+    let (tap, name) = match full_name.rsplit_once('/') {
+        Some((tap, name)) => (tap.to_string(), name.to_string()),
+        None if kind == PackageKind::Cask => ("homebrew/cask".to_string(), full_name.clone()),
+        None => ("homebrew/core".to_string(), full_name.clone()),
+    };
+    Package {
+        kind,
+        name,
+        full_name,
+        tap: Some(tap),
+        description: None,
+        installed: installed.into_vec(),
+        latest: Some(latest),
+        pinned,
+        outdated: true,
+        requested: true,
+        dependencies: Vec::new(),
+    }
+}
+
+#[derive(Deserialize)]
+struct Info {
+    #[serde(default)]
+    formulae: Vec<InfoFormula>,
+    #[serde(default)]
+    casks: Vec<InfoCask>,
+}
+
+#[derive(Deserialize)]
+struct InfoFormula {
+    name: String,
+    full_name: String,
+    tap: Option<String>,
+    desc: Option<String>,
+    #[serde(default)]
+    versions: FormulaVersions,
+    #[serde(default)]
+    pinned: bool,
+    #[serde(default)]
+    outdated: bool,
+    #[serde(default)]
+    dependencies: Vec<String>,
+    #[serde(default)]
+    installed: Vec<Keg>,
+}
+
+#[derive(Deserialize, Default)]
+struct FormulaVersions {
+    stable: Option<String>,
+    head: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Keg {
+    version: String,
+    #[serde(default)]
+    installed_on_request: bool,
+}
+
+#[derive(Deserialize)]
+struct InfoCask {
+    token: String,
+    full_token: String,
+    tap: Option<String>,
+    desc: Option<String>,
+    version: Option<String>,
+    installed: Option<String>,
+    #[serde(default)]
+    outdated: bool,
+    #[serde(default)]
+    depends_on: CaskDependencies,
+}
+
+#[derive(Deserialize, Default)]
+struct CaskDependencies {
+    #[serde(default)]
+    formula: Vec<String>,
+    #[serde(default)]
+    cask: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct Outdated {
+    #[serde(default)]
+    formulae: Vec<OutdatedFormula>,
+    #[serde(default)]
+    casks: Vec<OutdatedCask>,
+}
+
+#[derive(Deserialize)]
+struct OutdatedFormula {
+    name: String,
+    installed_versions: Versions,
+    current_version: String,
+    #[serde(default)]
+    pinned: bool,
+}
+
+#[derive(Deserialize)]
+struct OutdatedCask {
+    name: String,
+    installed_versions: Versions,
+    current_version: String,
+}
+
+/// Older brew releases report a cask's installed version as a string
+/// rather than a list.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Versions {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Versions {
+    fn into_vec(self) -> Vec<String> {
+        match self {
+            Versions::One(version) => vec![version],
+            Versions::Many(versions) => versions,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Trimmed from `brew info --json=v2 --installed`.
+    const INSTALLED: &str = r#"{
+      "formulae": [
+        {
+          "name": "python@3.12",
+          "full_name": "python@3.12",
+          "tap": "homebrew/core",
+          "aliases": ["python3"],
+          "desc": "Interpreted, interactive, object-oriented programming language",
+          "versions": { "stable": "3.12.7", "head": null, "bottle": true },
+          "pinned": false,
+          "outdated": true,
+          "dependencies": ["mpdecimal", "openssl@3", "sqlite", "xz"],
+          "installed": [
+            {
+              "version": "3.12.6",
+              "used_options": [],
+              "built_as_bottle": true,
+              "poured_from_bottle": true,
+              "time": 1726000000,
+              "installed_as_dependency": true,
+              "installed_on_request": false
+            },
+            {
+              "version": "3.12.7",
+              "used_options": [],
+              "built_as_bottle": true,
+              "poured_from_bottle": true,
+              "time": 1728000000,
+              "installed_as_dependency": true,
+              "installed_on_request": true
+            }
+          ]
+        },
+        {
+          "name": "k9s",
+          "full_name": "derailed/k9s/k9s",
+          "tap": "derailed/k9s",
+          "desc": "Kubernetes CLI To Manage Your Clusters In Style!",
+          "versions": { "stable": "0.32.5", "head": null, "bottle": false },
+          "pinned": true,
+          "outdated": false,
+          "dependencies": [],
+          "installed": [
+            { "version": "0.32.5", "installed_as_dependency": false, "installed_on_request": true }
+          ]
+        }
+      ],
+      "casks": [
+        {
+          "token": "docker",
+          "full_token": "docker",
+          "old_tokens": [],
+          "tap": "homebrew/cask",
+          "name": ["Docker Desktop"],
+          "desc": "App to build and share containerised applications and microservices",
+          "homepage": "https://www.docker.com/products/docker-desktop",
+          "version": "4.34.3,170107",
+          "installed": "4.34.2,167172",
+          "outdated": true,
+          "depends_on": { "macos": { ">=": ["12"] } }
+        },
+        {
+          "token": "temurin@17",
+          "full_token": "homebrew/cask-versions/temurin@17",
+          "tap": "homebrew/cask-versions",
+          "desc": null,
+          "version": "17.0.12,7",
+          "installed": "17.0.12,7",
+          "outdated": false,
+          "depends_on": { "formula": ["openssl@3"], "cask": ["temurin"] }
+        }
+      ]
+    }"#;
+
+    /// Trimmed from `brew outdated --json=v2`.
+    const OUTDATED: &str = r#"{
+      "formulae": [
+        {
+          "name": "python@3.12",
+          "installed_versions": ["3.12.6"],
+          "current_version": "3.12.7",
+          "pinned": false,
+          "pinned_version": null
+        },
+        {
+          "name": "hashicorp/tap/terraform",
+          "installed_versions": ["1.9.6", "1.9.7"],
+          "current_version": "1.9.8",
+          "pinned": true,
+          "pinned_version": "1.9.7"
+        }
+      ],
+      "casks": [
+        {
+          "name": "docker",
+          "installed_versions": "4.34.2,167172",
+          "current_version": "4.34.3,170107"
+        },
+        {
+          "name": "homebrew/cask-fonts/font-fira-code",
+          "installed_versions": ["6.2"],
+          "current_version": "6.2.1"
+        }
+      ]
+    }"#;
+
+    fn strings(items: &[&str]) -> Vec<String> {
+        items.iter().map(|item| item.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_installed_reads_formula_kegs() {
+        let packages = parse_installed(INSTALLED).unwrap();
+        assert_eq!(packages.len(), 4);
+
+        let python = &packages[0];
+        assert_eq!(python.kind, PackageKind::Formula);
+        assert_eq!(python.name, "python@3.12");
+        assert_eq!(python.installed, strings(&["3.12.6", "3.12.7"]));
+        assert_eq!(python.latest.as_deref(), Some("3.12.7"));
+        assert!(python.requested, "one of its kegs was installed on request");
+        assert!(python.outdated);
+        assert!(!python.pinned);
+        assert_eq!(python.dependencies, strings(&["mpdecimal", "openssl@3", "sqlite", "xz"]));
+
+        let k9s = &packages[1];
+        assert_eq!(k9s.name, "k9s");
+        assert_eq!(k9s.full_name, "derailed/k9s/k9s");
+        assert_eq!(k9s.tap.as_deref(), Some("derailed/k9s"));
+        assert!(k9s.pinned);
+        assert!(k9s.dependencies.is_empty());
+    }
+
+    #[test]
+    fn parse_installed_reads_casks_and_their_dependencies() {
+        let packages = parse_installed(INSTALLED).unwrap();
+
+        let docker = &packages[2];
+        assert_eq!(docker.kind, PackageKind::Cask);
+        assert_eq!(docker.name, "docker");
+        assert_eq!(docker.installed, strings(&["4.34.2,167172"]));
+        assert_eq!(docker.latest.as_deref(), Some("4.34.3,170107"));
+        assert!(docker.requested && docker.outdated);
+        assert!(docker.dependencies.is_empty(), "a macOS requirement is not a dependency");
+
+        let temurin = &packages[3];
+        assert_eq!(temurin.full_name, "homebrew/cask-versions/temurin@17");
+        assert_eq!(temurin.description, None);
+        assert_eq!(temurin.dependencies, strings(&["openssl@3", "temurin"]));
+    }
+
+    #[test]
+    fn parse_installed_rejects_other_output() {
+        assert!(parse_installed("Error: No available formula with the name \"nope\".").is_err());
+        assert!(parse_installed("{}").unwrap().is_empty());
+    }
+
+    #[test]
+    fn parse_outdated_reads_one_or_many_versions() {
+        let packages = parse_outdated(OUTDATED).unwrap();
+        assert_eq!(packages.len(), 4);
+
+        assert_eq!(packages[0].installed, strings(&["3.12.6"]));
+        assert_eq!(packages[1].installed, strings(&["1.9.6", "1.9.7"]));
+        assert_eq!(packages[2].installed, strings(&["4.34.2,167172"]));
+        assert_eq!(packages[2].latest.as_deref(), Some("4.34.3,170107"));
+        assert!(packages[1].pinned);
+        assert!(packages.iter().all(|package| package.outdated && package.requested));
+    }
+
+    #[test]
+    fn outdated_names_are_split_from_their_tap() {
+        let packages = parse_outdated(OUTDATED).unwrap();
+        let names: Vec<_> = packages
+            .iter()
+            .map(|package| (package.kind, package.name.as_str(), package.full_name.as_str(), package.tap.as_deref().unwrap()))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                (PackageKind::Formula, "python@3.12", "python@3.12", "homebrew/core"),
+                (PackageKind::Formula, "terraform", "hashicorp/tap/terraform", "hashicorp/tap"),
+                (PackageKind::Cask, "docker", "docker", "homebrew/cask"),
+                (PackageKind::Cask, "font-fira-code", "homebrew/cask-fonts/font-fira-code", "homebrew/cask-fonts"),
+            ]
+        );
+    }
+}
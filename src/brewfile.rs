@@ -0,0 +1,485 @@
+use crate::brew::{Package, PackageKind};
+use crate::runner::CommandRequest;
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Serialize;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// The kinds of Brewfile entries this app understands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryKind {
+    Tap,
+    Brew,
+    Cask,
+    Mas,
+}
+
+impl EntryKind {
+    fn keyword(&self) -> &'static str {
+        match self {
+            EntryKind::Tap => "tap",
+            EntryKind::Brew => "brew",
+            EntryKind::Cask => "cask",
+            EntryKind::Mas => "mas",
+        }
+    }
+}
+
+/// One line of a Brewfile, e.g. `brew "wget"` or `mas "Xcode", id: 497799835`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Entry {
+    pub kind: EntryKind,
+    /// Tap, formula or cask name, or the app's name for `mas`.
+    pub name: String,
+    /// Everything after the name, such as `restart_service: true`, kept as
+    /// written so it survives a round trip.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub options: Option<String>,
+}
+
+impl Entry {
+    pub fn new(kind: EntryKind, name: impl Into<String>) -> Self {
+        Entry {
+            kind,
+            name: name.into(),
+            options: None,
+        }
+    }
+
+    /// The App Store id of a `mas` entry.
+    pub fn mas_id(&self) -> Option<&str> {
+        let options = self.options.as_deref()?;
+        let (_, rest) = options.split_once("id:")?;
+        let rest = rest.trim_start();
+        let end = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        (end > 0).then(|| &rest[..end])
+    }
+
+    /// The tap's URL, for `tap "user/repo", "https://..."`.
+    fn tap_url(&self) -> Option<String> {
+        let (url, _) = parse_string(self.options.as_deref()?).ok()?;
+        Some(url)
+    }
+
+    /// Whether this entry and `other` name the same thing. Formulae and
+    /// casks match by short name unless both are qualified with a tap.
+    fn same_as(&self, other: &Entry) -> bool {
+        if self.kind != other.kind {
+            return false;
+        }
+        match self.kind {
+            EntryKind::Tap => self.name.eq_ignore_ascii_case(&other.name),
+            EntryKind::Mas => self.mas_id() == other.mas_id(),
+            EntryKind::Brew | EntryKind::Cask => {
+                let (a, b) = (self.name.as_str(), other.name.as_str());
+                a == b || (!(a.contains('/') && b.contains('/')) && short_name(a) == short_name(b))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Not `{:?}`, whose Rust escapes such as `\u{e9}` Ruby would misread
+        let name = self.name.replace('\\', "\\\\").replace('"', "\\\"");
+        write!(f, "{} \"{}\"", self.kind.keyword(), name)?;
+        match &self.options {
+            Some(options) => write!(f, ", {}", options),
+            None => Ok(()),
+        }
+    }
+}
+
+fn short_name(name: &str) -> &str {
+    name.rsplit('/').next().unwrap_or(name)
+}
+
+/// The taps, formulae, casks and App Store apps a Brewfile lists. Only the
+/// entry lines are understood; Ruby code and other entry kinds such as
+/// `vscode` are reported in `unsupported` and otherwise ignored.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Brewfile {
+    pub entries: Vec<Entry>,
+    /// Lines that were skipped, with their line numbers.
+    pub unsupported: Vec<(usize, String)>,
+}
+
+impl Brewfile {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path).with_context(|| format!("{}: failed to read Brewfile", path.display()))?;
+        Self::parse(&text).with_context(|| format!("{}: invalid Brewfile", path.display()))
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut brewfile = Brewfile::default();
+        for (index, line) in text.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            let keyword_end = line.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(line.len());
+            let kind = match &line[..keyword_end] {
+                "tap" => EntryKind::Tap,
+                "brew" => EntryKind::Brew,
+                "cask" => EntryKind::Cask,
+                "mas" => EntryKind::Mas,
+                _ => {
+                    brewfile.unsupported.push((index + 1, line.to_string()));
+                    continue;
+                }
+            };
+            let entry = parse_entry(kind, line[keyword_end..].trim()).map_err(|e| anyhow!("line {}: {}", index + 1, e))?;
+            brewfile.entries.push(entry);
+        }
+        Ok(brewfile)
+    }
+
+    /// A Brewfile for what is installed: every tap, formulae installed on
+    /// request, casks and App Store apps given as `(id, name)`.
+    pub fn from_installed(taps: &[String], packages: &[Package], apps: &[(String, String)]) -> Self {
+        let mut entries: Vec<Entry> = taps.iter().map(|tap| Entry::new(EntryKind::Tap, tap)).collect();
+        for package in packages {
+            match package.kind {
+                PackageKind::Formula if package.requested => entries.push(Entry::new(EntryKind::Brew, &package.full_name)),
+                PackageKind::Formula => {}
+                PackageKind::Cask => entries.push(Entry::new(EntryKind::Cask, &package.full_name)),
+            }
+        }
+        entries.extend(apps.iter().map(|(id, name)| Entry {
+            kind: EntryKind::Mas,
+            name: name.clone(),
+            options: Some(format!("id: {}", id)),
+        }));
+        Brewfile {
+            entries,
+            unsupported: Vec::new(),
+        }
+    }
+
+    pub fn count(&self, kind: EntryKind) -> usize {
+        self.entries.iter().filter(|entry| entry.kind == kind).count()
+    }
+
+    /// What has to change for `installed` to match this Brewfile. Only
+    /// formulae and casks can be extras, and formulae that something else
+    /// installed depends on never are.
+    pub fn plan(&self, installed: &Brewfile, packages: &[Package]) -> Plan {
+        let missing = self
+            .entries
+            .iter()
+            .filter(|entry| !installed.entries.iter().any(|other| other.same_as(entry)))
+            .cloned()
+            .collect();
+        let needed = |entry: &Entry| {
+            packages.iter().any(|package| package.dependencies.iter().any(|dep| short_name(dep) == short_name(&entry.name)))
+        };
+        let extra = installed
+            .entries
+            .iter()
+            .filter(|entry| match entry.kind {
+                EntryKind::Brew => !needed(entry),
+                EntryKind::Cask => true,
+                EntryKind::Tap | EntryKind::Mas => false,
+            })
+            .filter(|entry| !self.entries.iter().any(|other| other.same_as(entry)))
+            .cloned()
+            .collect();
+        Plan { missing, extra }
+    }
+}
+
+/// Writes the entries grouped as `brew bundle dump` does: taps, formulae,
+/// casks, then App Store apps.
+impl fmt::Display for Brewfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut entries: Vec<&Entry> = self.entries.iter().collect();
+        entries.sort_by_key(|entry| entry.kind);
+        for entry in entries {
+            writeln!(f, "{}", entry)?;
+        }
+        Ok(())
+    }
+}
+
+/// Entries a Brewfile lists that are not installed, and installed formulae
+/// and casks it does not list.
+#[derive(Debug, Clone)]
+pub struct Plan {
+    pub missing: Vec<Entry>,
+    pub extra: Vec<Entry>,
+}
+
+impl Plan {
+    /// Commands installing the missing entries: taps first, then one
+    /// `brew install` each for formulae and casks, then App Store apps.
+    pub fn install_requests(&self) -> Vec<CommandRequest> {
+        let mut requests: Vec<CommandRequest> = self
+            .missing_of(EntryKind::Tap)
+            .map(|entry| CommandRequest::exec("brew", ["tap".to_string(), entry.name.clone()].into_iter().chain(entry.tap_url())))
+            .collect();
+        requests.extend(batch("brew", &["install", "--formula"], self.missing_of(EntryKind::Brew)));
+        requests.extend(batch("brew", &["install", "--cask"], self.missing_of(EntryKind::Cask)));
+        requests.extend(
+            self.missing_of(EntryKind::Mas)
+                .filter_map(Entry::mas_id)
+                .map(|id| CommandRequest::exec("mas", ["install", id])),
+        );
+        requests
+    }
+
+    /// Commands uninstalling the extra formulae and casks, casks first.
+    pub fn uninstall_requests(&self) -> Vec<CommandRequest> {
+        let extra = |kind| self.extra.iter().filter(move |entry: &&Entry| entry.kind == kind);
+        let mut requests: Vec<CommandRequest> = batch("brew", &["uninstall", "--cask"], extra(EntryKind::Cask)).into_iter().collect();
+        requests.extend(batch("brew", &["uninstall", "--formula"], extra(EntryKind::Brew)));
+        requests
+    }
+
+    fn missing_of(&self, kind: EntryKind) -> impl Iterator<Item = &Entry> {
+        self.missing.iter().filter(move |entry| entry.kind == kind)
+    }
+}
+
+/// One `program args... names...` command for all of `entries`, or none
+/// when there are no entries.
+fn batch<'a>(program: &str, args: &[&str], entries: impl Iterator<Item = &'a Entry>) -> Option<CommandRequest> {
+    let names: Vec<String> = entries.map(|entry| entry.name.clone()).collect();
+    (!names.is_empty()).then(|| CommandRequest::exec(program, args.iter().map(|arg| arg.to_string()).chain(names)))
+}
+
+/// Parses the App Store apps printed by `mas list`, e.g.
+/// `497799835  Xcode  (15.0)`, into `(id, name)` pairs.
+pub fn parse_mas_list(output: &str) -> Vec<(String, String)> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (id, rest) = line.trim().split_once(char::is_whitespace)?;
+            if !id.chars().all(|c| c.is_ascii_digit()) {
+                return None;
+            }
+            let name = rest.rsplit_once('(').map_or(rest, |(name, _)| name).trim();
+            Some((id.to_string(), name.to_string()))
+        })
+        .collect()
+}
+
+/// Parses `"name", options...` following an entry's keyword.
+fn parse_entry(kind: EntryKind, rest: &str) -> Result<Entry> {
+    let (name, rest) = parse_string(rest)?;
+    if name.is_empty() {
+        bail!("empty {} name", kind.keyword());
+    }
+    let rest = rest.trim();
+    let options = match rest.strip_prefix(',') {
+        Some(options) if !options.trim().is_empty() => Some(options.trim().to_string()),
+        Some(_) => bail!("expected options after ','"),
+        None if rest.is_empty() => None,
+        None => bail!("unexpected '{}' after the name", rest),
+    };
+    let entry = Entry { kind, name, options };
+    if kind == EntryKind::Mas && entry.mas_id().is_none() {
+        bail!("mas entry \"{}\" needs an id: option", entry.name);
+    }
+    Ok(entry)
+}
+
+/// Reads a single- or double-quoted string at the start of `text`,
+/// returning it unescaped along with what follows it.
+fn parse_string(text: &str) -> Result<(String, &str)> {
+    let text = text.trim_start();
+    let quote = match text.chars().next() {
+        Some(quote @ ('"' | '\'')) => quote,
+        _ => bail!("expected a quoted name"),
+    };
+    let mut value = String::new();
+    let mut chars = text.char_indices().skip(1);
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some((_, escaped)) = chars.next() {
+                    value.push(escaped);
+                }
+            }
+            c if c == quote => return Ok((value, &text[index + 1..])),
+            c => value.push(c),
+        }
+    }
+    bail!("unterminated string")
+}
+
+/// `line` without a trailing `#` comment; a `#` inside quotes is kept.
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    for (index, c) in line.char_indices() {
+        match (quote, c) {
+            _ if escaped => escaped = false,
+            (Some(_), '\\') => escaped = true,
+            (Some(q), c) if c == q => quote = None,
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '#') => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(kind: EntryKind, name: &str, options: Option<&str>) -> Entry {
+        Entry {
+            kind,
+            name: name.to_string(),
+            options: options.map(str::to_string),
+        }
+    }
+
+    fn package(kind: PackageKind, full_name: &str, dependencies: &[&str]) -> Package {
+        Package {
+            kind,
+            name: short_name(full_name).to_string(),
+            full_name: full_name.to_string(),
+            tap: None,
+            description: None,
+            installed: vec!["1.0".to_string()],
+            latest: None,
+            pinned: false,
+            outdated: false,
+            requested: true,
+            dependencies: dependencies.iter().map(|dep| dep.to_string()).collect(),
+        }
+    }
+
+    fn names(entries: &[Entry]) -> Vec<&str> {
+        entries.iter().map(|entry| entry.name.as_str()).collect()
+    }
+
+    #[test]
+    fn parse_reads_entries_and_skips_comments() {
+        let brewfile = Brewfile::parse(
+            r#"# Tools for the dev laptop
+tap "homebrew/bundle"
+tap 'hashicorp/tap', "https://github.com/hashicorp/homebrew-tap"
+brew "wget" # the downloader
+brew "postgresql@16", restart_service: :changed
+cask 'font-#1'
+brew "say \"hi\"" # quoted
+mas "Xcode", id: 497799835
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            brewfile.entries,
+            [
+                entry(EntryKind::Tap, "homebrew/bundle", None),
+                entry(EntryKind::Tap, "hashicorp/tap", Some("\"https://github.com/hashicorp/homebrew-tap\"")),
+                entry(EntryKind::Brew, "wget", None),
+                entry(EntryKind::Brew, "postgresql@16", Some("restart_service: :changed")),
+                entry(EntryKind::Cask, "font-#1", None),
+                entry(EntryKind::Brew, "say \"hi\"", None),
+                entry(EntryKind::Mas, "Xcode", Some("id: 497799835")),
+            ]
+        );
+        assert!(brewfile.unsupported.is_empty());
+        assert_eq!(brewfile.entries[1].tap_url().as_deref(), Some("https://github.com/hashicorp/homebrew-tap"));
+        assert_eq!(brewfile.entries[6].mas_id(), Some("497799835"));
+    }
+
+    #[test]
+    fn parse_reports_unsupported_lines() {
+        let brewfile = Brewfile::parse("brew \"git\"\nvscode \"rust-lang.rust-analyzer\"\nif OS.mac?\n").unwrap();
+        assert_eq!(names(&brewfile.entries), ["git"]);
+        assert_eq!(
+            brewfile.unsupported,
+            [(2, "vscode \"rust-lang.rust-analyzer\"".to_string()), (3, "if OS.mac?".to_string())]
+        );
+    }
+
+    #[test]
+    fn parse_rejects_malformed_entries() {
+        for (text, error) in [
+            ("brew wget", "line 1: expected a quoted name"),
+            ("\nbrew \"wget", "line 2: unterminated string"),
+            ("cask \"\"", "line 1: empty cask name"),
+            ("brew \"wget\" extra", "line 1: unexpected 'extra' after the name"),
+            ("brew \"wget\",", "line 1: expected options after ','"),
+            ("mas \"Xcode\"", "line 1: mas entry \"Xcode\" needs an id: option"),
+        ] {
+            let result = Brewfile::parse(text);
+            assert_eq!(result.map_err(|e| e.to_string()), Err(error.to_string()), "{}", text);
+        }
+    }
+
+    #[test]
+    fn display_round_trips_through_parse() {
+        let brewfile = Brewfile {
+            entries: vec![
+                entry(EntryKind::Mas, "Things 3", Some("id: 904280696")),
+                entry(EntryKind::Cask, "homebrew/cask-fonts/font-fira-code", None),
+                entry(EntryKind::Brew, "odd \"name\" with \\ and é", Some("args: [\"with-x\"]")),
+                entry(EntryKind::Tap, "hashicorp/tap", None),
+            ],
+            unsupported: Vec::new(),
+        };
+        let text = brewfile.to_string();
+        assert_eq!(
+            text,
+            "tap \"hashicorp/tap\"\n\
+             brew \"odd \\\"name\\\" with \\\\ and é\", args: [\"with-x\"]\n\
+             cask \"homebrew/cask-fonts/font-fira-code\"\n\
+             mas \"Things 3\", id: 904280696\n"
+        );
+
+        let mut sorted = brewfile.entries.clone();
+        sorted.sort_by_key(|entry| entry.kind);
+        assert_eq!(Brewfile::parse(&text).unwrap().entries, sorted);
+    }
+
+    #[test]
+    fn plan_finds_missing_and_extra_entries() {
+        let wanted = Brewfile::parse("tap \"hashicorp/tap\"\nbrew \"hashicorp/tap/terraform\"\nbrew \"wget\"\ncask \"docker\"\nmas \"Xcode\", id: 497799835\n").unwrap();
+        let packages = [
+            package(PackageKind::Formula, "terraform", &[]),
+            package(PackageKind::Formula, "jq", &[]),
+            package(PackageKind::Cask, "firefox", &[]),
+        ];
+        let installed = Brewfile::from_installed(&[], &packages, &[("497799835".to_string(), "Xcode".to_string())]);
+        let plan = wanted.plan(&installed, &packages);
+
+        assert_eq!(names(&plan.missing), ["hashicorp/tap", "wget", "docker"]);
+        assert_eq!(names(&plan.extra), ["jq", "firefox"]);
+    }
+
+    #[test]
+    fn plan_keeps_dependencies_of_installed_packages() {
+        let wanted = Brewfile::parse("brew \"node\"\n").unwrap();
+        let packages = [
+            package(PackageKind::Formula, "node", &["icu4c", "openssl@3"]),
+            package(PackageKind::Formula, "icu4c", &[]),
+            package(PackageKind::Formula, "homebrew/core/openssl@3", &[]),
+            package(PackageKind::Formula, "wget", &[]),
+        ];
+        let installed = Brewfile::from_installed(&[], &packages, &[]);
+        let plan = wanted.plan(&installed, &packages);
+
+        assert!(plan.missing.is_empty());
+        assert_eq!(names(&plan.extra), ["wget"]);
+    }
+
+    #[test]
+    fn parse_mas_list_reads_ids_and_names() {
+        let output = "497799835  Xcode          (15.0)\n904280696  Things 3       (3.20.1)\n\nNo installed apps found\n";
+        assert_eq!(
+            parse_mas_list(output),
+            [
+                ("497799835".to_string(), "Xcode".to_string()),
+                ("904280696".to_string(), "Things 3".to_string()),
+            ]
+        );
+    }
+}
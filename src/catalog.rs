@@ -0,0 +1,535 @@
+use crate::config::Config;
+use crate::param;
+use crate::tweaks::{NodeId, NodeKind, Probe, Tweak, TweakAction, TweakKind, TweakTree};
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Catalog files shipped with the binary, in display order.
+const BUNDLED_CATALOGS: &[(&str, &str)] = &[
+    ("dock.toml", include_str!("../catalog/dock.toml")),
+    ("finder.toml", include_str!("../catalog/finder.toml")),
+    ("system_ui.toml", include_str!("../catalog/system_ui.toml")),
+    ("security.toml", include_str!("../catalog/security.toml")),
+    ("developer.toml", include_str!("../catalog/developer.toml")),
+    ("performance.toml", include_str!("../catalog/performance.toml")),
+    ("animated_wallpapers.toml", include_str!("../catalog/animated_wallpapers.toml")),
+    ("power_management.toml", include_str!("../catalog/power_management.toml")),
+    ("networking.toml", include_str!("../catalog/networking.toml")),
+    ("optimization.toml", include_str!("../catalog/optimization.toml")),
+    ("brew.toml", include_str!("../catalog/brew.toml")),
+    ("about.toml", include_str!("../catalog/about.toml")),
+    ("utilities.toml", include_str!("../catalog/utilities.toml")),
+];
+
+/// On-disk schema of a single catalog file: one top-level category.
+///
+/// Every entry may set an explicit `id`; otherwise one is derived from its
+/// name. Ids are joined with `/` to form the stable id of each node.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CatalogFile {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub name: String,
+    pub description: String,
+    #[serde(default)]
+    pub groups: Vec<GroupEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GroupEntry {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub name: String,
+    pub description: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<GroupEntry>,
+    #[serde(default)]
+    pub tweaks: Vec<TweakEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TweakEntry {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub name: String,
+    pub description: String,
+    pub kind: TweakKind,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub destructive: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub requires_root: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub interactive: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub repeatable: bool,
+    /// Shorthand for a `shell` action.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enable_command: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub action: Option<TweakAction>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub disable_command: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub probe: Option<Probe>,
+    /// Seconds each command may run, overriding `command_timeout_secs` in
+    /// the config; 0 for no limit.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_secs: Option<u64>,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+impl TweakEntry {
+    /// Resolves `enable_command`/`action` into the action to run.
+    fn resolve_action(&self) -> Result<TweakAction, String> {
+        let action = match (&self.enable_command, &self.action) {
+            (Some(_), Some(_)) => return Err("set either enable_command or action, not both".to_string()),
+            (None, None) => return Err("needs an enable_command or an action".to_string()),
+            (Some(command), None) => {
+                if command.trim_start().starts_with("__") {
+                    return Err(format!(
+                        "enable_command '{}' looks like a built-in sentinel; use an `action` instead",
+                        command
+                    ));
+                }
+                TweakAction::Shell { command: command.clone() }
+            }
+            (None, Some(action)) => action.clone(),
+        };
+        match &action {
+            TweakAction::Shell { command } if command.trim().is_empty() => {
+                Err("command must not be empty".to_string())
+            }
+            TweakAction::PromptThenRun { prompt, param, run } => {
+                if prompt.trim().is_empty() {
+                    return Err("prompt must not be empty".to_string());
+                }
+                match param.check().or_else(|| param::check_run(run)) {
+                    Some(problem) => Err(problem),
+                    None => Ok(action),
+                }
+            }
+            _ => Ok(action),
+        }
+    }
+}
+
+/// Loads the bundled catalog followed by any user catalogs in `tweaks.d`.
+///
+/// A user catalog whose category name matches an existing category adds its
+/// groups to that category; otherwise it becomes a new category.
+pub fn load_catalog() -> Result<TweakTree> {
+    let mut files: Vec<(String, String)> = BUNDLED_CATALOGS
+        .iter()
+        .map(|(name, contents)| (format!("<bundled>/{}", name), contents.to_string()))
+        .collect();
+    for path in user_catalog_paths()? {
+        let contents = fs::read_to_string(&path)
+            .with_context(|| format!("{}: failed to read catalog", path.display()))?;
+        files.push((path.display().to_string(), contents));
+    }
+    build_catalog(&files)
+}
+
+/// Builds the tree from catalog files given as `(source, contents)`, in
+/// order; the source names the file in errors and picks its format.
+fn build_catalog(sources: &[(String, String)]) -> Result<TweakTree> {
+    let mut files = Vec::new();
+    for (source, contents) in sources {
+        files.push((source.clone(), parse_catalog(source, contents)?));
+    }
+
+    let mut merged: Vec<(String, CatalogFile)> = Vec::new();
+    for (source, file) in files {
+        validate_catalog(&source, &file)?;
+        match merged.iter_mut().find(|(_, existing)| existing.name == file.name) {
+            Some((existing_source, existing)) => {
+                for group in file.groups {
+                    if existing.groups.iter().any(|g| g.name == group.name) {
+                        return Err(anyhow!(
+                            "{}: group '{}' already exists in category '{}' (defined in {})",
+                            source, group.name, existing.name, existing_source
+                        ));
+                    }
+                    existing.groups.push(group);
+                }
+            }
+            None => merged.push((source, file)),
+        }
+    }
+
+    let mut tree = TweakTree::default();
+    for (source, file) in merged {
+        let category_id = entry_id(file.id.as_deref(), &file.name);
+        if tree.find_by_id(&category_id).is_some() {
+            return Err(anyhow!(
+                "{}: category '{}' has id '{}' which is already in use; set an explicit `id`",
+                source, file.name, category_id
+            ));
+        }
+        let category = tree.add(None, &category_id, &file.name, NodeKind::Category);
+        for group in file.groups {
+            add_group(&mut tree, category, group).map_err(|e| anyhow!("{}: {}", source, e))?;
+        }
+        check_unique_ids(&source, &tree, category)?;
+    }
+    Ok(tree)
+}
+
+/// Directory scanned for user-provided catalog files.
+pub fn user_catalog_dir() -> PathBuf {
+    Config::config_dir().join("tweaks.d")
+}
+
+fn user_catalog_paths() -> Result<Vec<PathBuf>> {
+    let dir = user_catalog_dir();
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut paths: Vec<PathBuf> = fs::read_dir(&dir)
+        .with_context(|| format!("{}: failed to read catalog directory", dir.display()))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.is_file() && catalog_format(path).is_some())
+        .collect();
+    paths.sort();
+    Ok(paths)
+}
+
+enum CatalogFormat {
+    Toml,
+    Json,
+}
+
+fn catalog_format(path: &Path) -> Option<CatalogFormat> {
+    match path.extension()?.to_str()? {
+        "toml" => Some(CatalogFormat::Toml),
+        "json" => Some(CatalogFormat::Json),
+        _ => None,
+    }
+}
+
+fn parse_catalog(source: &str, contents: &str) -> Result<CatalogFile> {
+    match catalog_format(Path::new(source)) {
+        Some(CatalogFormat::Toml) => toml::from_str(contents).map_err(|e| {
+            match e.span().and_then(|span| entry_name_at(contents, span.start)) {
+                Some(name) => anyhow!("{}: invalid catalog: entry '{}': {}", source, name, e),
+                None => anyhow!("{}: invalid catalog: {}", source, e),
+            }
+        }),
+        Some(CatalogFormat::Json) => {
+            serde_json::from_str(contents).map_err(|e| anyhow!("{}: invalid catalog: {}", source, e))
+        }
+        None => Err(anyhow!("{}: unsupported catalog format (expected .toml or .json)", source)),
+    }
+}
+
+/// Name of the `[[...]]` entry whose table holds byte `offset` of a TOML
+/// catalog, to point parse errors at it.
+fn entry_name_at(contents: &str, offset: usize) -> Option<String> {
+    let offset = offset.min(contents.len());
+    let start = contents.get(..offset)?.rfind("\n[[").map_or(0, |index| index + 1);
+    let end = contents.get(offset..)?.find("\n[[").map_or(contents.len(), |index| offset + index);
+    contents[start..end].lines().find_map(|line| {
+        let (key, value) = line.split_once('=')?;
+        (key.trim() == "name").then(|| value.trim().trim_matches('"').to_string())
+    })
+}
+
+/// Checks the parts of the schema serde cannot express, reporting every
+/// problem found with the offending group and tweak named.
+fn validate_catalog(source: &str, file: &CatalogFile) -> Result<()> {
+    let mut problems = Vec::new();
+
+    if let Some(problem) = check_name(&file.name) {
+        problems.push(format!("category name {}", problem));
+    }
+    if let Some(problem) = file.id.as_deref().and_then(check_id) {
+        problems.push(format!("category id {}", problem));
+    }
+    if file.groups.is_empty() {
+        problems.push(format!("category '{}' has no groups", file.name));
+    }
+    validate_groups("", &file.groups, &mut problems);
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!("{}: invalid catalog:\n  - {}", source, problems.join("\n  - ")))
+    }
+}
+
+fn validate_groups(prefix: &str, groups: &[GroupEntry], problems: &mut Vec<String>) {
+    let mut group_names = HashSet::new();
+    for group in groups {
+        let group_label = format!("{}group '{}'", prefix, group.name);
+        if let Some(problem) = check_name(&group.name) {
+            problems.push(format!("{}: name {}", group_label, problem));
+        }
+        if let Some(problem) = group.id.as_deref().and_then(check_id) {
+            problems.push(format!("{}: id {}", group_label, problem));
+        }
+        if !group_names.insert(group.name.as_str()) {
+            problems.push(format!("{}: duplicate group name", group_label));
+        }
+        if group.tweaks.is_empty() && group.groups.is_empty() {
+            problems.push(format!("{}: has no tweaks or groups", group_label));
+        }
+
+        let mut tweak_names = HashSet::new();
+        for tweak in &group.tweaks {
+            let tweak_label = format!("{}, tweak '{}'", group_label, tweak.name);
+            if let Some(problem) = check_name(&tweak.name) {
+                problems.push(format!("{}: name {}", tweak_label, problem));
+            }
+            if let Some(problem) = tweak.id.as_deref().and_then(check_id) {
+                problems.push(format!("{}: id {}", tweak_label, problem));
+            }
+            if !tweak_names.insert(tweak.name.as_str()) {
+                problems.push(format!("{}: duplicate tweak name", tweak_label));
+            }
+            match tweak.resolve_action() {
+                Ok(action) => {
+                    let uses_sudo = match &action {
+                        TweakAction::Shell { command } => command.contains("sudo "),
+                        TweakAction::PromptThenRun { run, .. } => run.iter().any(|argv| argv[0] == "sudo"),
+                        TweakAction::Builtin { .. } | TweakAction::OpenList { .. } => false,
+                    } || tweak.disable_command.contains("sudo ");
+                    if !tweak.requires_root && uses_sudo {
+                        problems.push(format!("{}: command uses sudo but requires_root is not set", tweak_label));
+                    }
+                }
+                Err(problem) => problems.push(format!("{}: {}", tweak_label, problem)),
+            }
+            if let Some(probe) = &tweak.probe {
+                if tweak.kind != TweakKind::Setting {
+                    problems.push(format!("{}: only setting tweaks can have a probe", tweak_label));
+                }
+                if probe.domain.trim().is_empty() || probe.key.trim().is_empty() {
+                    problems.push(format!("{}: probe domain and key must not be empty", tweak_label));
+                }
+                let takes_value = matches!(tweak.action, Some(TweakAction::PromptThenRun { .. }));
+                if probe.expected.is_none() && !takes_value {
+                    problems.push(format!("{}: probe needs an expected value unless the tweak takes one", tweak_label));
+                }
+            }
+        }
+
+        validate_groups(&format!("{} > ", group_label), &group.groups, problems);
+    }
+}
+
+fn check_name(name: &str) -> Option<&'static str> {
+    if name.trim().is_empty() {
+        Some("must not be empty")
+    } else if name.trim() != name {
+        Some("must not have leading or trailing whitespace")
+    } else {
+        None
+    }
+}
+
+fn check_id(id: &str) -> Option<&'static str> {
+    if id.is_empty() {
+        Some("must not be empty")
+    } else if !id.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-') {
+        Some("may only contain lowercase letters, digits and '-'")
+    } else {
+        None
+    }
+}
+
+/// Returns the explicit id of an entry, or a slug derived from its name.
+fn entry_id(id: Option<&str>, name: &str) -> String {
+    if let Some(id) = id {
+        return id.to_string();
+    }
+    let mut slug = String::new();
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+fn add_group(tree: &mut TweakTree, parent: NodeId, group: GroupEntry) -> Result<()> {
+    let node = tree.add(
+        Some(parent),
+        &entry_id(group.id.as_deref(), &group.name),
+        &group.name,
+        NodeKind::Group,
+    );
+    for sub_group in group.groups {
+        add_group(tree, node, sub_group)?;
+    }
+    for entry in group.tweaks {
+        // The tweak id is filled in by `TweakTree::add` from its position.
+        let action = entry
+            .resolve_action()
+            .map_err(|problem| anyhow!("group '{}', tweak '{}': {}", group.name, entry.name, problem))?;
+        let tweak = Tweak {
+            id: String::new(),
+            name: entry.name.clone(),
+            description: entry.description,
+            action,
+            disable_command: entry.disable_command,
+            kind: entry.kind,
+            destructive: entry.destructive,
+            requires_root: entry.requires_root,
+            interactive: entry.interactive,
+            repeatable: entry.repeatable,
+            probe: entry.probe,
+            timeout_secs: entry.timeout_secs,
+        };
+        tree.add(
+            Some(node),
+            &entry_id(entry.id.as_deref(), &entry.name),
+            &entry.name,
+            NodeKind::Tweak(Box::new(tweak)),
+        );
+    }
+    Ok(())
+}
+
+/// Ensures no two siblings below `category` ended up with the same id.
+fn check_unique_ids(source: &str, tree: &TweakTree, category: NodeId) -> Result<()> {
+    let mut stack = vec![category];
+    while let Some(id) = stack.pop() {
+        let mut seen = HashSet::new();
+        for &child in tree.children(Some(id)) {
+            let node = tree.node(child);
+            if !seen.insert(node.id.as_str()) {
+                return Err(anyhow!(
+                    "{}: '{}' has id '{}' which is already used by a sibling; set an explicit `id`",
+                    source, node.name, node.id
+                ));
+            }
+            stack.push(child);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "tweaks.d/extra.toml";
+
+    /// A catalog with one group holding `tweak`, a `[[groups.tweaks]]` table
+    /// written without its header.
+    fn catalog(tweak: &str) -> String {
+        format!(
+            "name = \"Extra\"\ndescription = \"Extra tweaks\"\n\n[[groups]]\nname = \"Misc\"\ndescription = \"Odds and ends\"\n\n[[groups.tweaks]]\n{}\n",
+            tweak
+        )
+    }
+
+    fn load(contents: &str) -> Result<TweakTree> {
+        build_catalog(&[(SOURCE.to_string(), contents.to_string())])
+    }
+
+    /// The error loading `contents`, checked to name the file and `entry`.
+    fn rejected(contents: &str, entry: &str) -> String {
+        let error = format!("{:#}", load(contents).unwrap_err());
+        assert!(error.starts_with(SOURCE), "{}", error);
+        assert!(error.contains(entry), "{}", error);
+        error
+    }
+
+    #[test]
+    fn bundled_catalogs_load() {
+        let files: Vec<(String, String)> = BUNDLED_CATALOGS
+            .iter()
+            .map(|(name, contents)| (format!("<bundled>/{}", name), contents.to_string()))
+            .collect();
+        let tree = build_catalog(&files).unwrap();
+        let categories = tree.children(None);
+        assert_eq!(categories.len(), BUNDLED_CATALOGS.len());
+        assert!(tree.find_by_id("dock/dock-size/dock-size").is_some());
+
+        // Names are how most people look tweaks up, so none may repeat within a category
+        let mut names: Vec<String> = tree
+            .tweaks()
+            .map(|tweak| {
+                let category = tweak.id.split('/').next().unwrap_or_default();
+                format!("{}: {}", category, tweak.name.to_lowercase())
+            })
+            .collect();
+        names.sort();
+        let repeated: Vec<&String> = names.windows(2).filter(|pair| pair[0] == pair[1]).map(|pair| &pair[0]).collect();
+        assert!(repeated.is_empty(), "tweak names used more than once: {:?}", repeated);
+    }
+
+    #[test]
+    fn loads_a_tweak_with_ids_from_names() {
+        let tree = load(&catalog(
+            "name = \"Show Path Bar\"\ndescription = \"d\"\nkind = \"setting\"\nenable_command = \"defaults write com.apple.finder ShowPathbar -bool true\"",
+        ))
+        .unwrap();
+        let node = tree.find_by_id("extra/misc/show-path-bar").unwrap();
+        let tweak = tree.node(node).tweak().unwrap();
+        assert_eq!(tweak.id, "extra/misc/show-path-bar");
+        assert!(matches!(&tweak.action, TweakAction::Shell { command } if command.contains("ShowPathbar")));
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        let error = rejected(
+            &catalog("name = \"Show Path Bar\"\ndescription = \"d\"\nkind = \"setting\"\nenable_command = \"true\"\ncolour = \"red\""),
+            "entry 'Show Path Bar'",
+        );
+        assert!(error.contains("unknown field `colour`"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_an_empty_run() {
+        let error = rejected(
+            &catalog(
+                "name = \"Dock Size\"\ndescription = \"d\"\nkind = \"setting\"\naction = { type = \"prompt_then_run\", prompt = \"Size\", param = { type = \"int\", min = 16, max = 128 }, run = [] }",
+            ),
+            "group 'Misc', tweak 'Dock Size'",
+        );
+        assert!(error.contains("run must list at least one command"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_a_malformed_probe() {
+        let error = rejected(
+            &catalog(
+                "name = \"Autohide\"\ndescription = \"d\"\nkind = \"setting\"\nenable_command = \"true\"\nprobe = { domain = \"\", key = \"autohide\" }",
+            ),
+            "group 'Misc', tweak 'Autohide'",
+        );
+        assert!(error.contains("probe domain and key must not be empty"), "{}", error);
+        assert!(error.contains("probe needs an expected value"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_sudo_without_requires_root() {
+        let error = rejected(
+            &catalog("name = \"Never Sleep\"\ndescription = \"d\"\nkind = \"action\"\nenable_command = \"sudo pmset -a sleep 0\""),
+            "group 'Misc', tweak 'Never Sleep'",
+        );
+        assert!(error.contains("command uses sudo but requires_root is not set"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_duplicate_slugs() {
+        let contents = catalog(
+            "name = \"Show Bar\"\ndescription = \"d\"\nkind = \"action\"\nenable_command = \"true\"\n\n[[groups.tweaks]]\nname = \"Show-Bar\"\ndescription = \"d\"\nkind = \"action\"\nenable_command = \"true\"",
+        );
+        let error = rejected(&contents, "'Show-Bar'");
+        assert!(error.contains("id 'extra/misc/show-bar' which is already used by a sibling"), "{}", error);
+    }
+}
@@ -69,6 +69,18 @@ impl ColorScheme {
 pub struct Config {
     pub color_scheme: ColorScheme,
     pub theme: String,
+    /// Ids of tweaks, groups or categories that may not be applied or
+    /// reverted, e.g. on managed machines.
+    #[serde(default)]
+    pub forbidden_tweaks: Vec<String>,
+    /// Seconds a tweak's command may run before it is killed, unless the
+    /// tweak sets its own limit; 0 for no limit.
+    #[serde(default = "default_command_timeout")]
+    pub command_timeout_secs: u64,
+}
+
+fn default_command_timeout() -> u64 {
+    600
 }
 
 impl Default for Config {
@@ -76,6 +88,8 @@ impl Default for Config {
         Self {
             color_scheme: ColorScheme::default(),
             theme: "default".to_string(),
+            forbidden_tweaks: Vec::new(),
+            command_timeout_secs: default_command_timeout(),
         }
     }
 }
@@ -107,13 +121,26 @@ impl Config {
         }
     }
     
-    fn get_config_path() -> PathBuf {
+    /// Whether the tweak `id` is forbidden, directly or through the group or
+    /// category it is in.
+    pub fn forbids(&self, id: &str) -> bool {
+        self.forbidden_tweaks.iter().any(|forbidden| {
+            let forbidden = forbidden.trim_end_matches('/');
+            id == forbidden || id.strip_prefix(forbidden).is_some_and(|rest| rest.starts_with('/'))
+        })
+    }
+
+    /// Directory holding the config file and other user data.
+    pub fn config_dir() -> PathBuf {
         let mut path = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
         path.push(".config");
         path.push("macos-tweaks");
-        path.push("config.json");
         path
     }
+
+    fn get_config_path() -> PathBuf {
+        Self::config_dir().join("config.json")
+    }
     
     pub fn get_color_scheme(&self) -> &ColorScheme {
         &self.color_scheme
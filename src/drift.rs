@@ -0,0 +1,147 @@
+use crate::batch::BatchStep;
+use crate::probe::{self, ProbeState};
+use crate::runner::CommandRunner;
+
+/// How a tweak in a profile compares with the machine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DriftState {
+    /// The setting is what the profile asks for.
+    Matches,
+    /// The setting differs from the profile.
+    Drifted { expected: String, found: String },
+    /// The setting cannot be read, with the reason why.
+    Unknown(String),
+}
+
+impl DriftState {
+    pub fn is_drifted(&self) -> bool {
+        matches!(self, DriftState::Drifted { .. })
+    }
+}
+
+/// Reads the setting `step` changes and compares it with what the step
+/// would set it to.
+pub fn check(runner: &dyn CommandRunner, step: &BatchStep) -> DriftState {
+    match &step.tweak.probe {
+        Some(probe) => compare(step, probe::current_value(runner, probe)),
+        None => DriftState::Unknown("no probe".to_string()),
+    }
+}
+
+/// Like [`check`], from a state the TUI has already probed.
+pub fn classify(step: &BatchStep, state: Option<&ProbeState>) -> DriftState {
+    match state {
+        Some(ProbeState::On) => DriftState::Matches,
+        Some(ProbeState::Off) => DriftState::Drifted {
+            expected: "on".to_string(),
+            found: "off".to_string(),
+        },
+        Some(ProbeState::Value(value)) => compare(step, Ok(Some(value.clone()))),
+        Some(ProbeState::Unknown) => DriftState::Unknown("could not be read".to_string()),
+        None if step.tweak.probe.is_none() => DriftState::Unknown("no probe".to_string()),
+        None => DriftState::Unknown("not probed yet".to_string()),
+    }
+}
+
+/// Compares a value read from `defaults` (`None` when unset) with the
+/// step's target: its value for tweaks that take one, otherwise the value
+/// its probe expects.
+fn compare(step: &BatchStep, current: Result<Option<String>, String>) -> DriftState {
+    let expected = match (&step.value, step.tweak.probe.as_ref().and_then(|p| p.expected.as_ref())) {
+        (Some(value), _) => value.clone(),
+        (None, Some(expected)) => expected.clone(),
+        (None, None) => return DriftState::Unknown("no expected value".to_string()),
+    };
+    let found = match current {
+        Ok(Some(found)) => found,
+        Ok(None) => "not set".to_string(),
+        Err(reason) => return DriftState::Unknown(reason),
+    };
+    if probe::values_match(&found, &expected) {
+        DriftState::Matches
+    } else {
+        DriftState::Drifted { expected, found }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::{CommandOutput, ScriptedRunner};
+    use crate::testing::{shell_tweak, value_tweak};
+    use crate::tweaks::Probe;
+
+    fn probe(key: &str, expected: Option<&str>, default: Option<&str>) -> Probe {
+        Probe {
+            domain: "com.apple.dock".to_string(),
+            key: key.to_string(),
+            expected: expected.map(str::to_string),
+            default: default.map(str::to_string),
+            current_host: false,
+        }
+    }
+
+    fn toggle(key: &str, expected: &str, default: Option<&str>) -> BatchStep {
+        let mut tweak = shell_tweak(key, &format!("defaults write com.apple.dock {} -bool true", key));
+        tweak.probe = Some(probe(key, Some(expected), default));
+        BatchStep::new(&tweak, None).unwrap()
+    }
+
+    fn sized(value: &str, default: Option<&str>) -> BatchStep {
+        let mut tweak = value_tweak("Dock Size", &[&["defaults", "write", "com.apple.dock", "tilesize", "-int", "{}"]]);
+        tweak.probe = Some(probe("tilesize", None, default));
+        BatchStep::new(&tweak, Some(value)).unwrap()
+    }
+
+    fn missing() -> CommandOutput {
+        CommandOutput::failed(1, "The domain/default pair does not exist")
+    }
+
+    fn drifted(expected: &str, found: &str) -> DriftState {
+        DriftState::Drifted { expected: expected.to_string(), found: found.to_string() }
+    }
+
+    #[test]
+    fn check_compares_with_the_probe_or_the_value() {
+        let runner = ScriptedRunner::new()
+            .always("autohide", CommandOutput::ok("1\n"))
+            .always("tilesize", CommandOutput::ok("48.0\n"));
+        assert_eq!(check(&runner, &toggle("autohide", "true", None)), DriftState::Matches);
+        assert_eq!(check(&runner, &toggle("autohide", "0", None)), drifted("0", "1"));
+        assert_eq!(check(&runner, &sized("48", None)), DriftState::Matches);
+        assert_eq!(check(&runner, &sized("64", None)), drifted("64", "48.0"));
+    }
+
+    #[test]
+    fn missing_key_is_compared_as_its_default() {
+        let runner = ScriptedRunner::new().always("defaults read", missing());
+        assert_eq!(check(&runner, &toggle("autohide", "1", Some("NO"))), drifted("1", "NO"));
+        assert_eq!(check(&runner, &toggle("autohide", "0", Some("false"))), DriftState::Matches);
+        assert_eq!(check(&runner, &sized("48", Some("48"))), DriftState::Matches);
+        assert_eq!(check(&runner, &toggle("autohide", "1", None)), drifted("1", "not set"));
+    }
+
+    #[test]
+    fn unreadable_or_unprobed_tweak_is_unknown() {
+        let runner = ScriptedRunner::new().always("defaults read", CommandOutput::failed(1, "Could not read domain"));
+        assert_eq!(
+            check(&runner, &toggle("autohide", "1", Some("0"))),
+            DriftState::Unknown("Could not read domain".to_string())
+        );
+        let unprobed = BatchStep::new(&shell_tweak("Autohide", "true"), None).unwrap();
+        assert_eq!(check(&runner, &unprobed), DriftState::Unknown("no probe".to_string()));
+        assert_eq!(classify(&unprobed, None), DriftState::Unknown("no probe".to_string()));
+    }
+
+    #[test]
+    fn classify_uses_the_probed_state() {
+        let step = toggle("autohide", "1", None);
+        assert_eq!(classify(&step, Some(&ProbeState::On)), DriftState::Matches);
+        assert_eq!(classify(&step, Some(&ProbeState::Off)), drifted("on", "off"));
+        assert!(matches!(classify(&step, Some(&ProbeState::Unknown)), DriftState::Unknown(_)));
+        assert!(matches!(classify(&step, None), DriftState::Unknown(_)));
+        let step = sized("64", None);
+        assert_eq!(classify(&step, Some(&ProbeState::Value("64".to_string()))), DriftState::Matches);
+        assert_eq!(classify(&step, Some(&ProbeState::Value("36".to_string()))), drifted("64", "36"));
+    }
+}
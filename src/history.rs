@@ -0,0 +1,290 @@
+use crate::config::Config;
+use crate::runner::CommandOutput;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Longest stdout/stderr excerpt kept per entry, in characters.
+const EXCERPT_LEN: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HistoryAction {
+    Apply,
+    Revert,
+}
+
+/// One command run on behalf of a tweak.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub timestamp: DateTime<Utc>,
+    pub tweak_id: String,
+    pub tweak_name: String,
+    pub action: HistoryAction,
+    pub command: String,
+    /// Exit code, or `None` if the command could not be started or was
+    /// killed by a signal.
+    pub exit_status: Option<i32>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub stdout: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub stderr: String,
+    pub sudo: bool,
+    /// Killed for running past its timeout.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub timed_out: bool,
+    /// Stopped by Ctrl-C or the cancel key.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub canceled: bool,
+}
+
+impl HistoryEntry {
+    pub fn new(
+        tweak_id: &str,
+        tweak_name: &str,
+        action: HistoryAction,
+        command: &str,
+        sudo: bool,
+        result: &Result<CommandOutput>,
+    ) -> Self {
+        let (exit_status, stdout, stderr) = match result {
+            Ok(output) => (output.status, excerpt(&output.stdout), excerpt(&output.stderr)),
+            Err(e) => (None, String::new(), excerpt(&e.to_string())),
+        };
+        let output = result.as_ref().ok();
+        HistoryEntry {
+            timestamp: Utc::now(),
+            tweak_id: tweak_id.to_string(),
+            tweak_name: tweak_name.to_string(),
+            action,
+            command: command.to_string(),
+            exit_status,
+            stdout,
+            stderr,
+            sudo,
+            timed_out: output.is_some_and(|output| output.timed_out.is_some()),
+            canceled: output.is_some_and(CommandOutput::interrupted),
+        }
+    }
+
+    pub fn succeeded(&self) -> bool {
+        self.exit_status == Some(0) && !self.timed_out && !self.canceled
+    }
+
+    /// Top-level category id the tweak belongs to.
+    pub fn category(&self) -> &str {
+        self.tweak_id.split('/').next().unwrap_or_default()
+    }
+
+    pub fn local_time(&self) -> String {
+        self.timestamp.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S").to_string()
+    }
+
+    /// `ok` or the way the command failed.
+    pub fn outcome_label(&self) -> String {
+        match self.exit_status {
+            _ if self.timed_out => "timed out".to_string(),
+            _ if self.canceled => "canceled".to_string(),
+            Some(0) => "ok".to_string(),
+            Some(code) => format!("failed (exit {})", code),
+            None => "failed".to_string(),
+        }
+    }
+}
+
+fn excerpt(text: &str) -> String {
+    let text = text.trim();
+    match text.char_indices().nth(EXCERPT_LEN) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+/// Which outcomes a history listing shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum OutcomeFilter {
+    #[default]
+    All,
+    Succeeded,
+    Failed,
+}
+
+impl OutcomeFilter {
+    pub fn matches(&self, entry: &HistoryEntry) -> bool {
+        match self {
+            OutcomeFilter::All => true,
+            OutcomeFilter::Succeeded => entry.succeeded(),
+            OutcomeFilter::Failed => !entry.succeeded(),
+        }
+    }
+
+    pub fn next(&self) -> Self {
+        match self {
+            OutcomeFilter::All => OutcomeFilter::Succeeded,
+            OutcomeFilter::Succeeded => OutcomeFilter::Failed,
+            OutcomeFilter::Failed => OutcomeFilter::All,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            OutcomeFilter::All => "all",
+            OutcomeFilter::Succeeded => "succeeded",
+            OutcomeFilter::Failed => "failed",
+        }
+    }
+}
+
+/// Append-only log of every command run for a tweak, stored as JSON lines.
+#[derive(Debug, Clone)]
+pub struct History {
+    path: PathBuf,
+}
+
+impl History {
+    pub fn new(path: PathBuf) -> Self {
+        History { path }
+    }
+
+    pub fn default_path() -> PathBuf {
+        Config::config_dir().join("history.jsonl")
+    }
+
+    pub fn record(&self, entry: &HistoryEntry) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("{}: failed to open history", self.path.display()))?;
+        writeln!(file, "{}", serde_json::to_string(entry)?)?;
+        Ok(())
+    }
+
+    /// All entries, oldest first. Lines that cannot be parsed are skipped.
+    pub fn entries(&self) -> Result<Vec<HistoryEntry>> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e).with_context(|| format!("{}: failed to read history", self.path.display())),
+        };
+        Ok(contents
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect())
+    }
+
+    /// Ids of tweaks whose most recent successful entry is an apply.
+    pub fn applied_ids(&self) -> Result<Vec<String>> {
+        let mut applied: Vec<String> = Vec::new();
+        for entry in self.entries()?.iter().filter(|e| e.succeeded()) {
+            applied.retain(|id| id != &entry.tweak_id);
+            if entry.action == HistoryAction::Apply {
+                applied.push(entry.tweak_id.clone());
+            }
+        }
+        Ok(applied)
+    }
+
+    /// When each tweak was last applied successfully.
+    pub fn last_applied(&self) -> Result<HashMap<String, DateTime<Utc>>> {
+        Ok(self
+            .entries()?
+            .into_iter()
+            .filter(|entry| entry.succeeded() && entry.action == HistoryAction::Apply)
+            .map(|entry| (entry.tweak_id, entry.timestamp))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::temp_dir;
+    use std::time::Duration;
+
+    fn entry(tweak_id: &str, action: HistoryAction, result: Result<CommandOutput>) -> HistoryEntry {
+        HistoryEntry::new(tweak_id, "Test", action, "true", false, &result)
+    }
+
+    fn history() -> History {
+        History::new(temp_dir().join("history.jsonl"))
+    }
+
+    #[test]
+    fn reads_back_appended_entries_in_order() {
+        let history = history();
+        assert!(history.entries().unwrap().is_empty());
+        history.record(&entry("dock/a", HistoryAction::Apply, Ok(CommandOutput::ok("done\n")))).unwrap();
+        history.record(&entry("dock/a", HistoryAction::Revert, Ok(CommandOutput::failed(1, "nope")))).unwrap();
+
+        let entries = history.entries().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].action, HistoryAction::Apply);
+        assert_eq!(entries[0].stdout, "done");
+        assert_eq!(entries[1].action, HistoryAction::Revert);
+        assert_eq!(entries[1].exit_status, Some(1));
+        assert_eq!(entries[1].stderr, "nope");
+    }
+
+    #[test]
+    fn skips_corrupt_lines() {
+        let history = history();
+        history.record(&entry("dock/a", HistoryAction::Apply, Ok(CommandOutput::ok("")))).unwrap();
+        let mut file = OpenOptions::new().append(true).open(&history.path).unwrap();
+        writeln!(file, "{{\"timestamp\": \"not a time\"").unwrap();
+        writeln!(file, "garbage").unwrap();
+        history.record(&entry("finder/b", HistoryAction::Apply, Ok(CommandOutput::ok("")))).unwrap();
+
+        let ids: Vec<String> = history.entries().unwrap().into_iter().map(|e| e.tweak_id).collect();
+        assert_eq!(ids, ["dock/a", "finder/b"]);
+    }
+
+    #[test]
+    fn filters_by_outcome() {
+        let mut timed_out = CommandOutput::ok("");
+        timed_out.timed_out = Some(Duration::from_secs(1));
+        let entries = [
+            entry("dock/a", HistoryAction::Apply, Ok(CommandOutput::ok(""))),
+            entry("dock/b", HistoryAction::Apply, Ok(CommandOutput::failed(2, ""))),
+            entry("dock/c", HistoryAction::Apply, Ok(timed_out)),
+            entry("dock/d", HistoryAction::Apply, Err(anyhow::anyhow!("could not start"))),
+        ];
+        let matching = |filter: OutcomeFilter| -> Vec<&str> {
+            entries.iter().filter(|e| filter.matches(e)).map(|e| e.tweak_id.as_str()).collect()
+        };
+        assert_eq!(matching(OutcomeFilter::All), ["dock/a", "dock/b", "dock/c", "dock/d"]);
+        assert_eq!(matching(OutcomeFilter::Succeeded), ["dock/a"]);
+        assert_eq!(matching(OutcomeFilter::Failed), ["dock/b", "dock/c", "dock/d"]);
+        assert_eq!(entries[2].outcome_label(), "timed out");
+        assert_eq!(entries[3].outcome_label(), "failed");
+    }
+
+    #[test]
+    fn tracks_applied_tweaks() {
+        let history = history();
+        for (id, action, result) in [
+            ("dock/size/a", HistoryAction::Apply, CommandOutput::ok("")),
+            ("finder/bar/b", HistoryAction::Apply, CommandOutput::ok("")),
+            ("dock/size/a", HistoryAction::Revert, CommandOutput::ok("")),
+            ("finder/bar/b", HistoryAction::Revert, CommandOutput::failed(1, "")),
+            ("dock/size/c", HistoryAction::Apply, CommandOutput::failed(1, "")),
+        ] {
+            history.record(&entry(id, action, Ok(result))).unwrap();
+        }
+
+        let entries = history.entries().unwrap();
+        let dock: Vec<&str> = entries.iter().filter(|e| e.category() == "dock").map(|e| e.tweak_id.as_str()).collect();
+        assert_eq!(dock, ["dock/size/a", "dock/size/a", "dock/size/c"]);
+        assert_eq!(history.applied_ids().unwrap(), ["finder/bar/b"]);
+        let last = history.last_applied().unwrap();
+        assert!(last.contains_key("dock/size/a") && last.contains_key("finder/bar/b"));
+        assert!(!last.contains_key("dock/size/c"));
+    }
+}
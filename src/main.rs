@@ -1,12 +1,32 @@
 mod app;
+mod batch;
+mod brew;
+mod brewfile;
 mod ui;
 mod tweaks;
 mod utils;
 mod config;
+mod drift;
+mod catalog;
+mod history;
+mod output;
+mod param;
+mod probe;
+mod profile;
+mod runner;
+mod search;
+mod snapshot;
+#[cfg(test)]
+mod testing;
 
 use anyhow::Result;
-use app::App;
+use app::{App, PackageOperation};
+use batch::BatchStep;
+use drift::DriftState;
+use profile::Profile;
 use clap::Parser;
+use tweaks::{BuiltinKind, ListSelectAction, NodeKind, Tweak, TweakAction, TweakKind};
+use brewfile::{Brewfile, Entry, EntryKind};
 use crossterm::{
     event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode},
     execute,
@@ -16,80 +36,152 @@ use ratatui::{
     backend::{Backend, CrosstermBackend},
     Terminal,
 };
-use std::io;
+use history::{HistoryAction, HistoryEntry, OutcomeFilter};
+use output::{
+    CliError, CommandResult, DiffResult, DriftInfo, ExportedBrewfile, NodeInfo, Output, OutputFormat, ProfileInfo, Record,
+    RunResult, SavedProfile, SearchResult, Setting, StepInfo, TweakInfo,
+};
+use runner::{CommandOutput, CommandRequest, CommandRunner, SystemRunner};
+use anyhow::Context;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
     #[command(subcommand)]
     command: Option<Commands>,
+    /// Show the commands that would run without executing them
+    #[arg(long, global = true)]
+    dry_run: bool,
+    /// Apply destructive tweaks without asking for confirmation
+    #[arg(short, long, global = true)]
+    yes: bool,
+    /// Whether tweaks that need sudo may run
+    #[arg(long, global = true, default_value_t = true, action = clap::ArgAction::Set, value_name = "BOOL")]
+    allow_sudo: bool,
+    /// How to print results of subcommands
+    #[arg(long, global = true, value_enum, default_value_t)]
+    output: OutputFormat,
 }
 
 #[derive(clap::Subcommand)]
 enum Commands {
     /// Lists all available, runnable tweaks
     List,
-    /// Applies a specific tweak by name
+    /// Applies one tweak, or several in order as a batch that is rolled
+    /// back if any of them fails
     Apply {
-        /// The name of the tweak to apply
-        name: String,
+        /// The names of the tweaks to apply, as NAME or NAME=VALUE
+        #[arg(required = true)]
+        names: Vec<String>,
+        /// Value for a single tweak that takes one, e.g. an icon size
+        #[arg(long)]
+        value: Option<String>,
+    },
+    /// Finds tweaks by fuzzy-matching their names and descriptions
+    Search {
+        /// Words to look for, e.g. "path bar"
+        #[arg(required = true)]
+        query: Vec<String>,
     },
     /// Reverts a specific tweak by name
     Revert {
         /// The name of the tweak to revert
         name: String,
     },
+    /// Shows which settings are currently in effect on this machine
+    Status,
+    /// Compares this machine with a profile; exits with status 8 on drift
+    Diff {
+        /// Profile name, or path to a profile file
+        profile: String,
+    },
+    /// Applies, shows and saves profiles: named sets of tweaks
+    #[command(subcommand)]
+    Profile(ProfileCommand),
+    /// Exports and imports Brewfiles
+    #[command(subcommand)]
+    Brew(BrewCommand),
+    /// Shows the commands run for tweaks in earlier sessions
+    History {
+        /// Only show tweaks in this category (id or name)
+        #[arg(long)]
+        category: Option<String>,
+        /// Only show commands with this outcome
+        #[arg(long, value_enum, default_value_t)]
+        outcome: OutcomeFilter,
+        /// Show at most this many of the most recent entries
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+}
+
+#[derive(clap::Subcommand)]
+enum ProfileCommand {
+    /// Lists the saved profiles
+    List,
+    /// Shows the tweaks a profile applies, with its includes expanded
+    Show {
+        /// Profile name, or path to a profile file
+        name: String,
+    },
+    /// Applies every tweak in a profile as a batch
+    Apply {
+        /// Profile name, or path to a profile file
+        name: String,
+    },
+    /// Saves the currently applied tweaks as a new profile
+    Save {
+        /// Name for the new profile
+        name: String,
+    },
+}
+
+#[derive(clap::Subcommand)]
+enum BrewCommand {
+    /// Writes the installed taps, formulae, casks and App Store apps to a Brewfile
+    Export {
+        /// Where to write it; - prints it instead
+        #[arg(default_value = "Brewfile")]
+        path: PathBuf,
+        /// Replace the file if it already exists
+        #[arg(long)]
+        force: bool,
+    },
+    /// Installs what a Brewfile lists that is not installed yet
+    Import {
+        /// The Brewfile to read
+        #[arg(default_value = "Brewfile")]
+        path: PathBuf,
+        /// Also uninstall formulae and casks the Brewfile does not list
+        #[arg(long)]
+        uninstall: bool,
+    },
 }
 
 fn main() -> Result<()> {
     let cli = Cli::parse();
 
+    let runner: Arc<dyn CommandRunner> = Arc::new(SystemRunner);
+    runner::handle_ctrl_c()?;
+
     if let Some(command) = cli.command {
-        let app = App::new();
-        match command {
-            Commands::List => {
-                println!("Available tweaks:");
-                for category in &app.categories {
-                    let runnable_tweaks: Vec<_> = category
-                        .tweaks
-                        .iter()
-                        .filter(|t| !t.enable_command.is_empty() && !t.enable_command.starts_with("__"))
-                        .collect();
-
-                    if !runnable_tweaks.is_empty() {
-                        println!("\n{}:", category.name);
-                        for tweak in runnable_tweaks {
-                            println!("  - {}", tweak.name.trim());
-                        }
-                    }
-                }
-            }
-            Commands::Apply { name } => {
-                if let Some(tweak) = app.find_tweak_by_name(&name) {
-                    if tweak.enable_command.is_empty() || tweak.enable_command.starts_with("__") {
-                        println!("Tweak '{}' is a category or not directly runnable.", name);
-                    } else {
-                        println!("Applying tweak: '{}'", name);
-                        utils::execute_command(&tweak.enable_command, true)?;
-                        println!("Successfully applied tweak: '{}'", name);
-                    }
-                } else {
-                    eprintln!("Tweak not found: '{}'", name);
-                }
-            }
-            Commands::Revert { name } => {
-                if let Some(tweak) = app.find_tweak_by_name(&name) {
-                    if tweak.disable_command.is_empty() {
-                        eprintln!("Revert command not available for tweak: '{}'", name);
-                    } else {
-                        println!("Reverting tweak: '{}'", name);
-                        utils::execute_command(&tweak.disable_command, true)?;
-                        println!("Successfully reverted tweak: '{}'", name);
-                    }
-                } else {
-                    eprintln!("Tweak not found: '{}'", name);
-                }
+        let out = Output::new(cli.output);
+        let result = match App::new(runner) {
+            Ok(mut app) => {
+                app.dry_run = cli.dry_run;
+                app.allow_sudo = cli.allow_sudo;
+                app.assume_yes = cli.yes;
+                run_command(&app, &out, command)
             }
+            Err(e) => Err(e.into()),
+        };
+        if let Err(e) = result {
+            out.error(&e);
+            std::process::exit(e.exit_code());
         }
         return Ok(());
     }
@@ -102,7 +194,10 @@ fn main() -> Result<()> {
     let mut terminal = Terminal::new(backend)?;
 
     // Create app and run it
-    let mut app = App::new();
+    let mut app = App::new(runner)?;
+    app.dry_run = cli.dry_run;
+    app.allow_sudo = cli.allow_sudo;
+    app.refresh_probes();
     let res = run_app(&mut terminal, &mut app);
 
     // Restore terminal
@@ -121,8 +216,730 @@ fn main() -> Result<()> {
     Ok(())
 }
 
+/// Runs a CLI subcommand against the loaded catalog.
+fn run_command(app: &App, out: &Output, command: Commands) -> Result<(), CliError> {
+    match command {
+        Commands::List => list_tweaks(app, out),
+        Commands::Apply { names, value } if names.len() > 1 => {
+            if value.is_some() {
+                return Err(CliError::InvalidArgument(
+                    "--value only applies to a single tweak; pass NAME=VALUE for each tweak instead.".to_string(),
+                ));
+            }
+            let steps = names.iter().map(|arg| batch_step(app, arg)).collect::<Result<Vec<_>, _>>()?;
+            apply_batch(app, out, &steps)?;
+        }
+        Commands::Apply { mut names, value } => {
+            let (name, value) = match (names.pop().unwrap_or_default(), value) {
+                (name, Some(value)) => (name, Some(value)),
+                (name, None) => match split_value(app, &name) {
+                    Some((name, value)) => (name.to_string(), value.map(str::to_string)),
+                    None => (name, None),
+                },
+            };
+            apply_one(app, out, &name, value.as_deref())?;
+        }
+        Commands::Search { query } => search_tweaks(app, out, &query.join(" ")),
+        Commands::Revert { name } => {
+            let tweak = find_tweak(app, &name)?;
+            check_allowed(app, &tweak)?;
+            let requests = app
+                .revert_requests(&tweak)
+                .map_err(|reason| CliError::NotRunnable(format!("Cannot revert '{}': {}", name, reason)))?;
+            run_tweak_commands(app, out, &tweak, &requests, HistoryAction::Revert)?;
+        }
+        Commands::Status => {
+            if out.is_text() {
+                println!("Live state of settings:");
+            }
+            let mut settings = Vec::new();
+            let mut category = String::new();
+            let mut category_shown = true;
+            for id in app.tree.walk() {
+                let node = app.tree.node(id);
+                if let NodeKind::Category = node.kind {
+                    category = node.name.clone();
+                    category_shown = false;
+                    continue;
+                }
+                let Some(tweak) = node.tweak() else { continue };
+                let Some(probe) = &tweak.probe else { continue };
+                let state = probe::check(app.runner.as_ref(), probe);
+                if out.is_text() {
+                    if !category_shown {
+                        println!("\n{}:", category);
+                        category_shown = true;
+                    }
+                    println!("  - {} [{}]: {}", tweak.name, tweak.id, state.label());
+                    continue;
+                }
+                let setting = Setting::new(tweak, &category, &state);
+                out.record(Record::Setting(&setting));
+                settings.push(setting);
+            }
+            if out.format == OutputFormat::Json {
+                out.list("settings", &settings);
+            }
+        }
+        Commands::Profile(command) => run_profile_command(app, out, command)?,
+        Commands::Brew(command) => run_brew_command(app, out, command)?,
+        Commands::Diff { profile } => {
+            let steps = app.profile_steps(&profile).map_err(invalid_profile)?;
+            if out.is_text() {
+                println!("Comparing this machine with profile '{}':", profile);
+            }
+            let mut result = DiffResult {
+                profile,
+                tweaks: Vec::new(),
+                matching: 0,
+                drifted: 0,
+                unknown: 0,
+            };
+            for step in &steps {
+                let state = drift::check(app.runner.as_ref(), step);
+                match &state {
+                    DriftState::Matches => result.matching += 1,
+                    DriftState::Drifted { .. } => result.drifted += 1,
+                    DriftState::Unknown(_) => result.unknown += 1,
+                }
+                if out.is_text() {
+                    let state = match state {
+                        DriftState::Matches => "matches".to_string(),
+                        DriftState::Drifted { expected, found } => format!("drifted (expected {}, found {})", expected, found),
+                        DriftState::Unknown(reason) => format!("unknown ({})", reason),
+                    };
+                    println!("  - {} [{}]: {}", step.label(), step.tweak.id, state);
+                    continue;
+                }
+                let info = DriftInfo::new(step, state);
+                out.record(Record::Drift(&info));
+                if out.format == OutputFormat::Json {
+                    result.tweaks.push(info);
+                }
+            }
+            match out.format {
+                OutputFormat::Text => println!(
+                    "\n{} matching, {} drifted, {} unknown",
+                    result.matching, result.drifted, result.unknown
+                ),
+                OutputFormat::Json => out.document(&result),
+                OutputFormat::Ndjson => out.record(Record::Diff(&result)),
+            }
+            if result.drifted > 0 {
+                out.error_in_result();
+                return Err(CliError::Drifted(format!(
+                    "{} of {} tweaks have drifted from profile '{}'",
+                    result.drifted,
+                    steps.len(),
+                    result.profile
+                )));
+            }
+        }
+        Commands::History { category, outcome, limit } => {
+            let entries: Vec<_> = app
+                .history
+                .entries()?
+                .into_iter()
+                .filter(|entry| {
+                    category.as_deref().is_none_or(|c| {
+                        entry.category() == c || app.category_name(entry.category()).eq_ignore_ascii_case(c)
+                    })
+                })
+                .filter(|entry| outcome.matches(entry))
+                .collect();
+            let skip = limit.map_or(0, |limit| entries.len().saturating_sub(limit));
+            let entries = &entries[skip..];
+            match out.format {
+                OutputFormat::Json => out.list("entries", entries),
+                OutputFormat::Ndjson => entries.iter().for_each(|entry| out.record(Record::HistoryEntry(entry))),
+                OutputFormat::Text => print_history(entries),
+            }
+        }
+    }
+    Ok(())
+}
+
+fn list_tweaks(app: &App, out: &Output) {
+    match out.format {
+        OutputFormat::Text => {
+            println!("Available tweaks:");
+            for id in app.tree.walk() {
+                let node = app.tree.node(id);
+                let indent = "  ".repeat(app.tree.depth(id));
+                match &node.kind {
+                    NodeKind::Category => println!("\n{}:", node.name),
+                    NodeKind::Group => println!("{}{}:", indent, node.name),
+                    NodeKind::Tweak(tweak) => {
+                        if tweak.action.runs_in_cli() {
+                            let mut line = format!("{}- {} [{}]", indent, tweak.name, tweak.id);
+                            if let Some(param) = tweak.action.param() {
+                                line.push_str(&format!(" --value <{}>", param.hint()));
+                            }
+                            if let Some(flags) = tweak.flags_label() {
+                                line.push_str(&format!(" ({})", flags));
+                            }
+                            println!("{}", line);
+                        }
+                    }
+                }
+            }
+        }
+        OutputFormat::Json => {
+            let categories: Vec<NodeInfo> = app.tree.children(None).iter().map(|&id| NodeInfo::new(&app.tree, id)).collect();
+            out.list("categories", &categories);
+        }
+        OutputFormat::Ndjson => {
+            for id in app.tree.walk() {
+                let node = app.tree.node(id);
+                let parent = node.parent.map_or("", |parent| app.tree.node(parent).id.as_str());
+                match &node.kind {
+                    NodeKind::Category => out.record(Record::Category { id: &node.id, name: &node.name }),
+                    NodeKind::Group => out.record(Record::Group { id: &node.id, name: &node.name, parent }),
+                    NodeKind::Tweak(tweak) if tweak.action.runs_in_cli() => out.record(Record::Tweak {
+                        parent,
+                        tweak: TweakInfo::from(&**tweak),
+                    }),
+                    NodeKind::Tweak(_) => {}
+                }
+            }
+        }
+    }
+}
+
+fn search_tweaks(app: &App, out: &Output, query: &str) {
+    let results: Vec<SearchResult> = search::search(&app.tree, query)
+        .into_iter()
+        .filter_map(|hit| {
+            let tweak = app.tree.node(hit.node).tweak().filter(|tweak| tweak.action.runs_in_cli())?;
+            Some(SearchResult {
+                breadcrumbs: app.tree.breadcrumbs(hit.node),
+                score: hit.score,
+                tweak: TweakInfo::from(tweak),
+            })
+        })
+        .collect();
+    match out.format {
+        OutputFormat::Text if results.is_empty() => println!("No tweaks match '{}'.", query),
+        OutputFormat::Text => {
+            for result in &results {
+                println!("{} [{}]", result.breadcrumbs, result.tweak.id);
+            }
+        }
+        OutputFormat::Json => out.list("results", &results),
+        OutputFormat::Ndjson => results.iter().for_each(|result| out.record(Record::SearchResult(result))),
+    }
+}
+
+fn print_history(entries: &[HistoryEntry]) {
+    if entries.is_empty() {
+        println!("No history recorded yet.");
+    }
+    for entry in entries {
+        let action = match entry.action {
+            HistoryAction::Apply => "apply",
+            HistoryAction::Revert => "revert",
+        };
+        let sudo = if entry.sudo { " (sudo)" } else { "" };
+        println!("{}  {:<6} {} [{}]: {}{}", entry.local_time(), action, entry.tweak_name, entry.tweak_id, entry.outcome_label(), sudo);
+        println!("    $ {}", entry.command);
+        if !entry.succeeded() && !entry.stderr.is_empty() {
+            println!("    {}", entry.stderr.lines().next().unwrap_or_default());
+        }
+    }
+}
+
+fn find_tweak(app: &App, name: &str) -> Result<Tweak, CliError> {
+    Ok(app.lookup_tweak(name)?)
+}
+
+fn invalid_profile(e: anyhow::Error) -> CliError {
+    CliError::classify(e, CliError::InvalidProfile)
+}
+
+/// Applies the tweak called `name` on its own.
+fn apply_one(app: &App, out: &Output, name: &str, value: Option<&str>) -> Result<(), CliError> {
+    let tweak = find_tweak(app, name)?;
+    check_allowed(app, &tweak)?;
+    if value.is_some() && tweak.action.param().is_none() {
+        return Err(CliError::InvalidArgument(format!("Tweak '{}' does not take a value.", name)));
+    }
+    match &tweak.action {
+        TweakAction::Shell { command } => {
+            run_tweak_commands(app, out, &tweak, &[CommandRequest::shell(command)], HistoryAction::Apply)
+        }
+        TweakAction::Builtin { builtin } => {
+            let message = match builtin {
+                BuiltinKind::ShowVersion => app::version_message(),
+                BuiltinKind::CheckBrew => app::check_brew_message(app.runner.as_ref()).to_string(),
+                BuiltinKind::Sokoban => {
+                    return Err(CliError::NotRunnable(format!(
+                        "Tweak '{}' is only available in the interactive TUI.",
+                        name
+                    )));
+                }
+            };
+            report_output(out, &tweak, format!("{}\n", message));
+            Ok(())
+        }
+        TweakAction::PromptThenRun { param, run, .. } => match value.map(|v| param.parse(v)) {
+            Some(Ok(value)) => {
+                let requests = param::commands_for(run, &value);
+                run_tweak_commands(app, out, &tweak, &requests, HistoryAction::Apply)
+            }
+            Some(Err(problem)) => Err(CliError::InvalidArgument(format!("Invalid value for '{}': {}", name, problem))),
+            None => Err(CliError::InvalidArgument(format!("Tweak '{}' needs --value <{}>.", name, param.hint()))),
+        },
+        TweakAction::OpenList { source, .. } => {
+            let Some(command) = source.command() else {
+                return Err(CliError::NotRunnable(format!(
+                    "Tweak '{}' is only available in the interactive TUI; use brew search and brew install instead.",
+                    name
+                )));
+            };
+            let request = app.with_timeout(&tweak, &CommandRequest::shell(command));
+            let listing = CliError::check_command(app.runner.capture(&request))?;
+            let packages = source.parse(&listing.stdout).map_err(|e| CliError::Internal(format!("{:#}", e)))?;
+            if out.is_text() {
+                print_packages(&packages);
+                return Ok(());
+            }
+            let mut result = RunResult::new(HistoryAction::Apply, false, vec![tweak.id.clone()]);
+            result.packages = packages;
+            out.run_result(result);
+            Ok(())
+        }
+    }
+}
+
+/// Prints packages as a table with a column per field.
+fn print_packages(packages: &[brew::Package]) {
+    let rows: Vec<[String; 7]> = packages.iter().map(brew::Package::columns).collect();
+    let mut widths = brew::COLUMNS.map(|heading| heading.chars().count());
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let headings = brew::COLUMNS.map(String::from);
+    for row in std::iter::once(&headings).chain(&rows) {
+        let cells: Vec<String> = row.iter().zip(widths).map(|(cell, width)| format!("{:<width$}", cell)).collect();
+        println!("{}", cells.join("  ").trim_end());
+    }
+}
+
+/// Prints what a tweak that shows information produced.
+fn report_output(out: &Output, tweak: &Tweak, output: String) {
+    if out.is_text() {
+        print!("{}", output);
+        return;
+    }
+    let mut result = RunResult::new(HistoryAction::Apply, false, vec![tweak.id.clone()]);
+    result.output = Some(output);
+    out.run_result(result);
+}
+
+/// Runs `tweak`'s commands in order and records them in the history, or
+/// only reports them during a dry run.
+fn run_tweak_commands(
+    app: &App,
+    out: &Output,
+    tweak: &Tweak,
+    requests: &[CommandRequest],
+    action: HistoryAction,
+) -> Result<(), CliError> {
+    let mut result = RunResult::new(action, app.dry_run, vec![tweak.id.clone()]);
+    if app.dry_run {
+        if out.is_text() {
+            let runner = app.action_runner();
+            for request in requests {
+                runner.run_interactive(request)?;
+            }
+            print!("{}", app.take_dry_run_report(Some(tweak)));
+        } else {
+            for request in requests {
+                out.command(CommandResult::planned(tweak, action, request));
+            }
+            out.run_result(result);
+        }
+        return Ok(());
+    }
+    let (progress, done) = match action {
+        HistoryAction::Apply => ("Applying", "applied"),
+        HistoryAction::Revert => ("Reverting", "reverted"),
+    };
+    if action == HistoryAction::Apply {
+        confirm_destructive(app, &[tweak])?;
+    }
+    if out.is_text() {
+        println!("{} tweak: '{}'", progress, tweak.name);
+        if tweak.requires_root {
+            println!("This tweak runs with sudo; you may be asked for your password.");
+        }
+    }
+    if tweak.requires_root {
+        authenticate_sudo(app)?;
+    }
+    if action == HistoryAction::Apply {
+        if let Err(e) = app.take_snapshot(tweak) {
+            out.warning(format!("could not save undo snapshot: {}", e));
+        }
+    }
+    let outcome = requests.iter().try_for_each(|request| execute(app, out, tweak, action, request));
+    if outcome.is_ok() && action == HistoryAction::Revert {
+        if let Err(e) = app.snapshots.remove(&tweak.id) {
+            out.warning(format!("could not update snapshots: {}", e));
+        }
+    }
+    if out.is_text() {
+        if outcome.is_ok() {
+            println!("Successfully {} tweak: '{}'", done, tweak.name);
+        }
+    } else {
+        result.succeeded = outcome.is_ok();
+        result.error = outcome.clone().err();
+        out.run_result(result);
+    }
+    outcome
+}
+
+fn run_profile_command(app: &App, out: &Output, command: ProfileCommand) -> Result<(), CliError> {
+    match command {
+        ProfileCommand::List => {
+            let names = Profile::list()?;
+            if names.is_empty() && out.is_text() {
+                println!("No profiles saved in {}", Profile::dir().display());
+            }
+            let mut profiles = Vec::new();
+            for name in names {
+                let info = match Profile::load(&name) {
+                    Ok(profile) => ProfileInfo { name, description: profile.description, error: None },
+                    Err(e) => ProfileInfo { name, description: None, error: Some(format!("{:#}", e)) },
+                };
+                match (&info, out.format) {
+                    (ProfileInfo { error: Some(error), .. }, OutputFormat::Text) => println!("{} (invalid: {})", info.name, error),
+                    (ProfileInfo { description: Some(description), .. }, OutputFormat::Text) => {
+                        println!("{} - {}", info.name, description)
+                    }
+                    (_, OutputFormat::Text) => println!("{}", info.name),
+                    _ => out.record(Record::Profile(&info)),
+                }
+                profiles.push(info);
+            }
+            if out.format == OutputFormat::Json {
+                out.list("profiles", &profiles);
+            }
+        }
+        ProfileCommand::Show { name } => {
+            let steps: Vec<StepInfo> = app
+                .profile_steps(&name)
+                .map_err(invalid_profile)?
+                .into_iter()
+                .map(|step| StepInfo { id: step.tweak.id, name: step.tweak.name, value: step.value })
+                .collect();
+            match out.format {
+                OutputFormat::Text => {
+                    for step in &steps {
+                        match &step.value {
+                            Some(value) => println!("- {} = {} [{}]", step.name, value, step.id),
+                            None => println!("- {} [{}]", step.name, step.id),
+                        }
+                    }
+                }
+                OutputFormat::Json => out.list("tweaks", &steps),
+                OutputFormat::Ndjson => steps.iter().for_each(|step| out.record(Record::Step(step))),
+            }
+        }
+        ProfileCommand::Apply { name } => {
+            let steps = app.profile_steps(&name).map_err(invalid_profile)?;
+            if steps.is_empty() && out.is_text() {
+                println!("Profile '{}' has no tweaks.", name);
+                return Ok(());
+            }
+            apply_batch(app, out, &steps)?;
+        }
+        ProfileCommand::Save { name } => {
+            let (profile, skipped) = app.profile_from_applied();
+            if profile.tweaks.is_empty() {
+                return Err(CliError::InvalidArgument("No tweaks are applied, so there is nothing to save.".to_string()));
+            }
+            let path = profile.save(&name).map_err(|e| CliError::classify(e, CliError::InvalidArgument))?;
+            for name in &skipped {
+                out.warning(format!("left out '{}' because its current value could not be read", name));
+            }
+            if out.is_text() {
+                println!("Saved {} tweaks to {}", profile.tweaks.len(), path.display());
+            } else {
+                out.document(&SavedProfile {
+                    profile: &name,
+                    path: &path,
+                    tweaks: &profile.tweaks,
+                    skipped: &skipped,
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+fn run_brew_command(app: &App, out: &Output, command: BrewCommand) -> Result<(), CliError> {
+    match command {
+        BrewCommand::Export { path, force } => {
+            let (installed, _) = installed_brewfile(app, out)?;
+            if path == Path::new("-") {
+                match out.format {
+                    OutputFormat::Text => print!("{}", installed),
+                    _ => out.document(&ExportedBrewfile { path: None, entries: &installed.entries }),
+                }
+                return Ok(());
+            }
+            if path.exists() && !force {
+                return Err(CliError::InvalidArgument(format!(
+                    "{} already exists; pass --force to replace it.",
+                    path.display()
+                )));
+            }
+            fs::write(&path, installed.to_string())
+                .with_context(|| format!("{}: failed to write Brewfile", path.display()))?;
+            if out.is_text() {
+                println!(
+                    "Wrote {} entries to {} (taps: {}, formulae: {}, casks: {}, App Store apps: {})",
+                    installed.entries.len(),
+                    path.display(),
+                    installed.count(EntryKind::Tap),
+                    installed.count(EntryKind::Brew),
+                    installed.count(EntryKind::Cask),
+                    installed.count(EntryKind::Mas)
+                );
+            } else {
+                out.document(&ExportedBrewfile { path: Some(&path), entries: &installed.entries });
+            }
+        }
+        BrewCommand::Import { path, uninstall } => {
+            let brewfile = Brewfile::load(&path).map_err(|e| CliError::classify(e, CliError::InvalidArgument))?;
+            for (line, text) in &brewfile.unsupported {
+                out.warning(format!("{}:{}: skipped unsupported line: {}", path.display(), line, text));
+            }
+            let (installed, packages) = installed_brewfile(app, out)?;
+            let mut plan = brewfile.plan(&installed, &packages);
+            if plan.missing.iter().any(|entry| entry.kind == EntryKind::Mas) && !utils::check_command_exists(app.runner.as_ref(), "mas") {
+                out.warning("mas is not installed, so App Store apps are skipped".to_string());
+                plan.missing.retain(|entry| entry.kind != EntryKind::Mas);
+            }
+            let mut requests = plan.install_requests();
+            if uninstall {
+                requests.extend(plan.uninstall_requests());
+            }
+            if out.is_text() {
+                let names = |entries: &[Entry]| entries.iter().map(|e| e.name.as_str()).collect::<Vec<_>>().join(", ");
+                if !plan.missing.is_empty() {
+                    println!("Missing from this machine: {}", names(&plan.missing));
+                }
+                match (uninstall, plan.extra.is_empty()) {
+                    (_, true) => {}
+                    (true, false) => println!("Not in {}, to be uninstalled: {}", path.display(), names(&plan.extra)),
+                    (false, false) => println!("Not in {} (pass --uninstall to remove): {}", path.display(), names(&plan.extra)),
+                }
+                if requests.is_empty() {
+                    println!("Everything in {} is installed.", path.display());
+                    return Ok(());
+                }
+            }
+            let tweak = brewfile_tweak(&path, uninstall && !plan.uninstall_requests().is_empty());
+            check_allowed(app, &tweak)?;
+            run_tweak_commands(app, out, &tweak, &requests, HistoryAction::Apply)?;
+        }
+    }
+    Ok(())
+}
+
+/// What is installed, as a Brewfile, along with the installed packages.
+fn installed_brewfile(app: &App, out: &Output) -> Result<(Brewfile, Vec<brew::Package>), CliError> {
+    if !utils::check_command_exists(app.runner.as_ref(), "brew") {
+        return Err(CliError::NotRunnable("Homebrew is not installed or not in your PATH.".to_string()));
+    }
+    let capture = |program: &str, args: &[&str]| {
+        let mut request = CommandRequest::exec(program, args.iter().copied());
+        request.timeout = app.default_timeout();
+        CliError::check_command(app.runner.capture(&request)).map(|output| output.stdout)
+    };
+    let packages = brew::parse_installed(&capture("brew", &["info", "--json=v2", "--installed"])?)
+        .map_err(|e| CliError::Internal(format!("{:#}", e)))?;
+    let taps: Vec<String> = capture("brew", &["tap"])?.lines().map(str::trim).filter(|l| !l.is_empty()).map(String::from).collect();
+    let apps = if utils::check_command_exists(app.runner.as_ref(), "mas") {
+        brewfile::parse_mas_list(&capture("mas", &["list"])?)
+    } else {
+        out.warning("mas is not installed, so App Store apps are not included".to_string());
+        Vec::new()
+    };
+    Ok((Brewfile::from_installed(&taps, &packages, &apps), packages))
+}
+
+/// Stands in for a catalog tweak so Brewfile imports are checked against
+/// `forbidden_tweaks`, confirmed when they uninstall and recorded in the
+/// history under Brew Management.
+fn brewfile_tweak(path: &Path, uninstalls: bool) -> Tweak {
+    Tweak {
+        id: "brew-management/brewfile-import".to_string(),
+        name: format!("Import {}", path.display()),
+        description: "Installs what a Brewfile lists".to_string(),
+        // The commands depend on the Brewfile, so they are passed separately
+        action: TweakAction::Shell { command: String::new() },
+        disable_command: String::new(),
+        kind: TweakKind::Action,
+        destructive: uninstalls,
+        requires_root: false,
+        interactive: false,
+        repeatable: true,
+        probe: None,
+        timeout_secs: Some(3600),
+    }
+}
+
+/// Runs one command for `tweak`, attached to the terminal in text mode and
+/// captured otherwise, and records it.
+fn execute(app: &App, out: &Output, tweak: &Tweak, action: HistoryAction, request: &CommandRequest) -> Result<(), CliError> {
+    let request = &app.with_timeout(tweak, request);
+    let result = if out.is_text() {
+        app.runner.interactive(request)
+    } else {
+        app.runner.capture(request)
+    };
+    if let Err(e) = app.record(tweak, action, request, &result) {
+        out.warning(format!("could not save history: {}", e));
+    }
+    out.command(CommandResult::new(tweak, action, request, &result));
+    CliError::check_command(result).map(|_| ())
+}
+
+fn check_allowed(app: &App, tweak: &Tweak) -> Result<(), CliError> {
+    app.forbidden_reason(tweak).map_or(Ok(()), |reason| Err(CliError::Forbidden(reason)))
+}
+
+/// Destructive tweaks only run with `--yes`, or once the user has typed
+/// "yes" when the CLI is run from a terminal.
+fn confirm_destructive(app: &App, tweaks: &[&Tweak]) -> Result<(), CliError> {
+    let names: Vec<&str> = tweaks.iter().filter(|t| t.destructive).map(|t| t.name.as_str()).collect();
+    if names.is_empty() || app.dry_run || app.assume_yes {
+        return Ok(());
+    }
+    let names = names.join("', '");
+    if !io::stdin().is_terminal() {
+        return Err(CliError::Canceled(format!("'{}' is destructive; pass --yes to apply it", names)));
+    }
+    eprint!("DESTRUCTIVE ACTION: '{}'\nType 'yes' to confirm: ", names);
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer).map_err(anyhow::Error::from)?;
+    if answer.trim().eq_ignore_ascii_case("yes") {
+        Ok(())
+    } else {
+        Err(CliError::Canceled("Action canceled.".to_string()))
+    }
+}
+
+/// Asks for the sudo password before running anything, so a wrong or
+/// missing password is reported as such rather than as a failed command.
+fn authenticate_sudo(app: &App) -> Result<(), CliError> {
+    match app.runner.interactive(&CommandRequest::exec("sudo", ["-v"])) {
+        Ok(output) if output.success() => Ok(()),
+        Ok(output) if output.interrupted() => Err(CliError::Canceled("Canceled at the sudo password prompt".to_string())),
+        _ => Err(CliError::PermissionDenied(
+            "This tweak needs administrator rights, and sudo could not authenticate.".to_string(),
+        )),
+    }
+}
+
+/// Splits `NAME=VALUE` into the tweak name and value. A whole argument that
+/// names a tweak is taken as the name, in case the name contains `=`.
+fn split_value<'a>(app: &App, arg: &'a str) -> Option<(&'a str, Option<&'a str>)> {
+    if app.find_tweak_by_name(arg).is_some() {
+        return Some((arg, None));
+    }
+    arg.split_once('=').map(|(name, value)| (name, Some(value)))
+}
+
+fn batch_step(app: &App, arg: &str) -> Result<BatchStep, CliError> {
+    let (name, value) = split_value(app, arg).unwrap_or((arg, None));
+    let tweak = find_tweak(app, name)?;
+    BatchStep::new(&tweak, value).map_err(CliError::InvalidArgument)
+}
+
+fn apply_batch(app: &App, out: &Output, steps: &[BatchStep]) -> Result<(), CliError> {
+    for step in steps {
+        check_allowed(app, &step.tweak)?;
+    }
+    confirm_destructive(app, &steps.iter().map(|step| &step.tweak).collect::<Vec<_>>())?;
+    if !app.dry_run && out.is_text() {
+        println!("Applying {} tweaks:", steps.len());
+        for step in steps {
+            println!("  - {}", step.label());
+        }
+        if steps.iter().any(|step| step.tweak.requires_root) {
+            println!("Some of these run with sudo; you may be asked for your password.");
+        }
+    }
+    if !app.dry_run && steps.iter().any(|step| step.tweak.requires_root) {
+        authenticate_sudo(app)?;
+    }
+    let runner = app.action_runner();
+    // A batch stopped by Ctrl-C or a timeout exits as such, not as failed
+    let mut stopped: Option<CliError> = None;
+    let report = batch::run(app, steps, &mut |tweak, action, request| {
+        if !app.dry_run {
+            execute(app, out, tweak, action, request).inspect_err(|e| {
+                if matches!(e, CliError::Canceled(_) | CliError::TimedOut(_)) {
+                    stopped.get_or_insert_with(|| e.clone());
+                }
+            })?;
+        } else if out.is_text() {
+            runner.run(request)?;
+        } else {
+            out.command(CommandResult::planned(tweak, action, request));
+        }
+        Ok(())
+    });
+    if app.dry_run && out.is_text() {
+        print!("{}", app.take_dry_run_report(None));
+        return Ok(());
+    }
+    if out.is_text() {
+        println!("{}", report.summary(steps.len()));
+        if !report.succeeded() {
+            return Err(batch_error("batch was not applied".to_string(), stopped));
+        }
+        return Ok(());
+    }
+    let error = report
+        .failure
+        .as_ref()
+        .map(|(label, error)| batch_error(format!("failed at '{}': {}", label, error), stopped));
+    let mut result = RunResult::new(
+        HistoryAction::Apply,
+        app.dry_run,
+        steps.iter().map(|step| step.tweak.id.clone()).collect(),
+    );
+    result.succeeded = report.succeeded();
+    result.rolled_back = report.rolled_back;
+    result.warnings = report.warnings;
+    result.error = error.clone();
+    out.run_result(result);
+    error.map_or(Ok(()), Err)
+}
+
+/// The error for a batch that was not applied: canceled or timed out if
+/// that is what `stopped` it, otherwise failed.
+fn batch_error(message: String, stopped: Option<CliError>) -> CliError {
+    match stopped {
+        Some(CliError::Canceled(_)) => CliError::Canceled(message),
+        Some(CliError::TimedOut(_)) => CliError::TimedOut(message),
+        _ => CliError::BatchFailed(message),
+    }
+}
+
 fn run_app<B: Backend + std::io::Write>(terminal: &mut Terminal<B>, app: &mut App) -> Result<()> {
     loop {
+        app.poll_probes();
+        app.poll_output();
+        app.poll_search();
         terminal.draw(|f| ui::ui(f, app))?;
 
         if app.should_quit {
@@ -136,8 +953,32 @@ fn run_app<B: Backend + std::io::Write>(terminal: &mut Terminal<B>, app: &mut Ap
                         handle_sokoban_game(app, key.code)?;
                         continue;
                     }
+                    if let Some(view) = &mut app.output_view {
+                        match key.code {
+                            KeyCode::Up => view.scroll(1),
+                            KeyCode::Down => view.scroll(-1),
+                            KeyCode::PageUp => view.scroll(10),
+                            KeyCode::PageDown => view.scroll(-10),
+                            KeyCode::End => view.scroll_back = 0,
+                            KeyCode::Char('c') if view.is_running() => view.cancel(),
+                            _ if view.is_running() => {}
+                            _ => app.output_view = None,
+                        }
+                        continue;
+                    }
+                    if let Some(view) = &mut app.history_view {
+                        match key.code {
+                            KeyCode::Up => view.move_selection(false),
+                            KeyCode::Down => view.move_selection(true),
+                            KeyCode::Char('c') => view.next_category(),
+                            KeyCode::Char('o') => view.next_outcome(),
+                            KeyCode::Esc | KeyCode::Char('q') => app.history_view = None,
+                            _ => {}
+                        }
+                        continue;
+                    }
                     if app.fullscreen_list.is_some() {
-                        handle_fullscreen_list_nav(app, key.code, terminal, |t, cmd| run_interactive_command(t, cmd))?;
+                        handle_fullscreen_list_nav(app, key.code, terminal, run_interactive_command)?;
                         continue;
                     }
                     if app.fullscreen_output.is_some() {
@@ -151,32 +992,36 @@ fn run_app<B: Backend + std::io::Write>(terminal: &mut Terminal<B>, app: &mut Ap
                         }
                         continue;
                     }
+                    if app.search.is_some() {
+                        match key.code {
+                            KeyCode::Char(c) => app.edit_search(|query| query.push(c)),
+                            KeyCode::Backspace => app.edit_search(|query| {
+                                query.pop();
+                            }),
+                            KeyCode::Up => app.search.iter_mut().for_each(|view| view.move_selection(false)),
+                            KeyCode::Down => app.search.iter_mut().for_each(|view| view.move_selection(true)),
+                            KeyCode::Tab => app.jump_to_search_hit(),
+                            KeyCode::Enter => app.apply_search_hit(terminal, run_interactive_command)?,
+                            KeyCode::Esc => app.search = None,
+                            _ => {}
+                        }
+                        continue;
+                    }
                     if app.text_input_prompt.is_some() {
                         match key.code {
-                            KeyCode::Char(c) => app.input_buffer.push(c),
-                            KeyCode::Backspace => { app.input_buffer.pop(); },
-                            KeyCode::Enter => {
-                                if let Some(template) = app.text_input_command_template.clone() {
-                                    let command = template.replace("{}", &app.input_buffer);
-                                    match utils::execute_command(&command, false) {
-                                        Ok(_) => {
-                                            app.status_message = Some("Successfully applied custom text.".to_string());
-                                            app.status_timer = 50;
-                                        }
-                                        Err(e) => {
-                                            app.status_message = Some(format!("Error: {}", e));
-                                            app.status_timer = 80;
-                                        }
-                                    }
-                                }
-                                app.text_input_prompt = None;
-                                app.text_input_command_template = None;
-                                app.input_buffer.clear();
+                            KeyCode::Char(c) => {
+                                app.input_buffer.push(c);
+                                app.text_input_error = None;
+                            },
+                            KeyCode::Backspace => {
+                                app.input_buffer.pop();
+                                app.text_input_error = None;
                             },
+                            KeyCode::Left => app.step_text_input(false),
+                            KeyCode::Right => app.step_text_input(true),
+                            KeyCode::Enter => app.submit_text_input(terminal, run_interactive_command)?,
                             KeyCode::Esc => {
-                                app.text_input_prompt = None;
-                                app.text_input_command_template = None;
-                                app.input_buffer.clear();
+                                app.cancel_text_input();
                                 app.status_message = Some("Action canceled.".to_string());
                                 app.status_timer = 50;
                             },
@@ -190,11 +1035,11 @@ fn run_app<B: Backend + std::io::Write>(terminal: &mut Terminal<B>, app: &mut Ap
                             KeyCode::Backspace => { app.input_buffer.pop(); },
                             KeyCode::Enter => {
                                 let input = app.input_buffer.clone();
-                                app.handle_confirmation(&input, terminal, |t, cmd| run_interactive_command(t, cmd))?;
+                                app.handle_confirmation(&input, terminal, run_interactive_command)?;
                                 app.input_buffer.clear();
                             },
                             KeyCode::Esc => {
-                                app.handle_confirmation("no", terminal, |t, cmd| run_interactive_command(t, cmd))?;
+                                app.handle_confirmation("no", terminal, run_interactive_command)?;
                                 app.input_buffer.clear();
                             },
                             _ => {}
@@ -215,7 +1060,20 @@ fn run_app<B: Backend + std::io::Write>(terminal: &mut Terminal<B>, app: &mut Ap
 fn handle_main_tab<B: Backend + std::io::Write>(app: &mut App, key_code: KeyCode, terminal: &mut Terminal<B>) -> Result<()> {
     match key_code {
         KeyCode::Char('q') => app.should_quit = true,
-        KeyCode::Enter => app.apply_selected_tweak(terminal, |t, cmd| run_interactive_command(t, cmd))?,
+        KeyCode::Char('d') => app.toggle_dry_run(),
+        KeyCode::Char('h') => app.open_history(),
+        KeyCode::Char('/') => app.open_search(),
+        KeyCode::Char('r') => {
+            app.refresh_probes();
+            app.status_message = Some("Refreshing live state...".to_string());
+            app.status_timer = 20;
+        }
+        KeyCode::Enter => app.apply_selected_tweak(terminal, run_interactive_command)?,
+        KeyCode::Char('u') => app.revert_selected_tweak(terminal, run_interactive_command)?,
+        KeyCode::Char(' ') => app.toggle_mark(),
+        KeyCode::Char('a') => app.apply_marked_tweaks(terminal, run_interactive_command)?,
+        KeyCode::Char('p') => app.prompt_save_profile(),
+        KeyCode::Char('c') => app.prompt_compare_profile(),
         KeyCode::Right => app.handle_right_key(),
         KeyCode::Left => app.handle_left_key(),
         KeyCode::Up => app.previous_item(),
@@ -225,62 +1083,93 @@ fn handle_main_tab<B: Backend + std::io::Write>(app: &mut App, key_code: KeyCode
     Ok(())
 }
 
-fn run_interactive_command<B: Backend + std::io::Write>(terminal: &mut Terminal<B>, command: &str) -> Result<()> {
+/// Hands the terminal to `request` for its duration, restoring the TUI afterwards.
+fn run_interactive_command<B: Backend + std::io::Write>(
+    terminal: &mut Terminal<B>,
+    runner: &dyn CommandRunner,
+    request: &CommandRequest,
+) -> Result<CommandOutput> {
     disable_raw_mode()?;
     execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
     terminal.show_cursor()?;
 
-    let result = utils::execute_command(command, true);
+    let result = runner.interactive(request);
 
     enable_raw_mode()?;
     execute!(terminal.backend_mut(), EnterAlternateScreen)?;
     terminal.hide_cursor()?;
     terminal.clear()?;
 
-    result.map(|_| ())
+    result
 }
 
-fn handle_fullscreen_list_nav<B: Backend + std::io::Write>(
+fn handle_fullscreen_list_nav<B: Backend>(
     app: &mut App,
     key_code: KeyCode,
     terminal: &mut Terminal<B>,
-    run_interactive: impl Fn(&mut Terminal<B>, &str) -> Result<()>,
+    run_interactive: impl Fn(&mut Terminal<B>, &dyn CommandRunner, &CommandRequest) -> Result<CommandOutput>,
 ) -> Result<()> {
-    if let Some(list) = &app.fullscreen_list {
-        let count = list.len();
-        if count == 0 {
-            return Ok(());
+    let Some(list) = &mut app.fullscreen_list else { return Ok(()) };
+    if let Some(search) = &mut list.search {
+        if matches!(key_code, KeyCode::Esc | KeyCode::Char('c')) {
+            search.cancel();
         }
-        let selected = app.fullscreen_list_state.selected().unwrap_or(0);
-
+        return Ok(());
+    }
+    if list.confirming.is_some() {
         match key_code {
-            KeyCode::Up => {
-                let new_selected = if selected == 0 { count - 1 } else { selected - 1 };
-                app.fullscreen_list_state.select(Some(new_selected));
-            }
-            KeyCode::Down => {
-                let new_selected = (selected + 1) % count;
-                app.fullscreen_list_state.select(Some(new_selected));
-            }
-            KeyCode::Enter => {
-                if let (Some(list), Some(selected_index)) =
-                    (app.fullscreen_list.clone(), app.fullscreen_list_state.selected())
-                {
-                    let selected_item = &list[selected_index];
-                    let command = if app.fullscreen_list_title.contains("Outdated") {
-                        format!("brew upgrade {}", selected_item)
-                    } else {
-                        format!("brew info {}", selected_item)
-                    };
-                    app.fullscreen_list = None;
-                    run_interactive(terminal, &command)?;
-                }
-            }
-            KeyCode::Esc | KeyCode::Char('q') => {
-                app.fullscreen_list = None;
+            KeyCode::Enter | KeyCode::Char('y') => app.start_operation(),
+            KeyCode::Esc | KeyCode::Char('n') | KeyCode::Char('q') => list.confirming = None,
+            _ => {}
+        }
+        return Ok(());
+    }
+    if list.editing_query {
+        let query = list.query.get_or_insert_with(String::new);
+        match key_code {
+            KeyCode::Char(c) => query.push(c),
+            KeyCode::Backspace => {
+                query.pop();
             }
+            KeyCode::Enter if !query.trim().is_empty() => app.search_packages(),
+            KeyCode::Esc if list.packages.is_empty() => app.fullscreen_list = None,
+            KeyCode::Esc => list.editing_query = false,
             _ => {}
         }
+        return Ok(());
+    }
+    match key_code {
+        KeyCode::Up => list.move_selection(false),
+        KeyCode::Down => list.move_selection(true),
+        KeyCode::Char('/') if list.query.is_some() => list.editing_query = true,
+        KeyCode::Char(' ') if list.multi_select() => list.toggle_mark(),
+        KeyCode::Char('a') if list.multi_select() => list.mark_all(),
+        KeyCode::Char('i') if list.multi_select() => list.invert_marks(),
+        KeyCode::Enter if list.on_select == ListSelectAction::BrewUpgrade => app.confirm_operation(PackageOperation::Upgrade),
+        KeyCode::Enter if list.on_select == ListSelectAction::BrewInstall => app.confirm_operation(PackageOperation::Install),
+        KeyCode::Char('u') if list.on_select == ListSelectAction::BrewInstall => {
+            app.confirm_operation(PackageOperation::Uninstall)
+        }
+        KeyCode::Enter => {
+            let Some(package) = list.selected() else { return Ok(()) };
+            let mut request = list.on_select.command_for(package.kind, &package.full_name);
+            request.timeout = app.default_timeout();
+            let list = app.fullscreen_list.take();
+            if app.dry_run {
+                app.action_runner().run_interactive(&request)?;
+                app.fullscreen_output = Some(app.take_dry_run_report(None));
+                app.fullscreen_output_scroll = 0;
+            } else if let Err(e) = run_interactive(terminal, app.runner.as_ref(), &request).and_then(CommandOutput::into_result) {
+                // Back to the list, to pick another package
+                app.fullscreen_list = list;
+                app.status_message = Some(format!("Error executing '{}': {}", request.display(), e));
+                app.status_timer = 80;
+            }
+        }
+        KeyCode::Esc | KeyCode::Char('q') => {
+            app.fullscreen_list = None;
+        }
+        _ => {}
     }
     Ok(())
 }
@@ -301,3 +1190,258 @@ fn handle_sokoban_game(app: &mut App, key_code: KeyCode) -> Result<()> {
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use runner::{CommandLine, ScriptedRunner};
+    use testing::{shell_tweak, value_tweak};
+
+    const WRITE: &str = "defaults write com.apple.finder ShowPathbar -bool true";
+
+    fn runner() -> Arc<ScriptedRunner> {
+        Arc::new(ScriptedRunner::new().always("read-type", CommandOutput::failed(1, "does not exist")))
+    }
+
+    fn apply(name: &str, value: Option<&str>) -> Commands {
+        Commands::Apply { names: vec![name.to_string()], value: value.map(str::to_string) }
+    }
+
+    /// The commands run for tweaks, leaving out snapshot reads.
+    fn commands(runner: &ScriptedRunner) -> Vec<CommandLine> {
+        runner
+            .invocations()
+            .into_iter()
+            .map(|invocation| invocation.request.line)
+            .filter(|line| !matches!(line, CommandLine::Exec { args, .. } if args.iter().any(|arg| arg == "read-type")))
+            .collect()
+    }
+
+    #[test]
+    fn apply_runs_the_tweak_and_records_it() {
+        let runner = runner();
+        let app = testing::app(&runner, vec![shell_tweak("Show Path Bar", WRITE)]);
+        run_command(&app, &Output::new(OutputFormat::Json), apply("show-path-bar", None)).unwrap();
+
+        assert_eq!(commands(&runner), [CommandLine::Shell(WRITE.to_string())]);
+        let entries = app.history.entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].action, HistoryAction::Apply);
+        assert!(entries[0].succeeded());
+        assert!(app.snapshots.get("test/show-path-bar").unwrap().is_some());
+    }
+
+    #[test]
+    fn apply_attaches_commands_to_the_terminal_in_text_mode() {
+        let runner = runner();
+        let app = testing::app(&runner, vec![shell_tweak("Show Path Bar", WRITE)]);
+        run_command(&app, &Output::new(OutputFormat::Text), apply("Show Path Bar", None)).unwrap();
+
+        let invocations = runner.invocations();
+        let write = invocations.iter().find(|i| i.request.display() == WRITE).unwrap();
+        assert!(write.interactive);
+        assert_eq!(write.request.timeout, app.default_timeout());
+    }
+
+    #[test]
+    fn apply_passes_the_value_as_an_argument() {
+        let runner = runner();
+        let tweak = value_tweak("Dock Size", &[&["defaults", "write", "com.apple.dock", "tilesize", "-int", "{}"]]);
+        let app = testing::app(&runner, vec![tweak]);
+        let out = Output::new(OutputFormat::Json);
+        run_command(&app, &out, apply("dock-size", Some("64"))).unwrap();
+        run_command(&app, &out, apply("Dock Size=48", None)).unwrap();
+
+        let expected = |value: &str| CommandLine::Exec {
+            program: "defaults".to_string(),
+            args: ["write", "com.apple.dock", "tilesize", "-int", value].map(String::from).to_vec(),
+        };
+        assert_eq!(commands(&runner), [expected("64"), expected("48")]);
+    }
+
+    #[test]
+    fn apply_rejects_missing_and_invalid_values() {
+        let runner = runner();
+        let tweak = value_tweak("Dock Size", &[&["defaults", "write", "com.apple.dock", "tilesize", "-int", "{}"]]);
+        let app = testing::app(&runner, vec![tweak, shell_tweak("Show Path Bar", WRITE)]);
+        let out = Output::new(OutputFormat::Json);
+
+        let missing = run_command(&app, &out, apply("dock-size", None)).unwrap_err();
+        assert!(matches!(missing, CliError::InvalidArgument(_)), "{:?}", missing);
+        let invalid = run_command(&app, &out, apply("dock-size", Some("500"))).unwrap_err();
+        assert!(matches!(invalid, CliError::InvalidArgument(_)), "{:?}", invalid);
+        let unexpected = run_command(&app, &out, apply("show-path-bar", Some("1"))).unwrap_err();
+        assert!(matches!(unexpected, CliError::InvalidArgument(_)), "{:?}", unexpected);
+        assert!(runner.invocations().is_empty());
+    }
+
+    #[test]
+    fn apply_reports_the_exit_code_of_a_failed_command() {
+        let runner = Arc::new(ScriptedRunner::new().once("defaults write", CommandOutput::failed(3, "boom")));
+        let app = testing::app(&runner, vec![shell_tweak("Show Path Bar", WRITE)]);
+        let error = run_command(&app, &Output::new(OutputFormat::Json), apply("show-path-bar", None)).unwrap_err();
+
+        assert!(matches!(error, CliError::CommandFailed { exit_code: Some(3), .. }), "{:?}", error);
+        assert_eq!(error.exit_code(), 3);
+        let entries = app.history.entries().unwrap();
+        assert_eq!(entries[0].exit_status, Some(3));
+    }
+
+    #[test]
+    fn apply_unknown_tweak_is_not_found() {
+        let runner = runner();
+        let app = testing::app(&runner, vec![shell_tweak("Show Path Bar", WRITE)]);
+        let error = run_command(&app, &Output::new(OutputFormat::Json), apply("hide-path-bar", None)).unwrap_err();
+        assert!(matches!(error, CliError::NotFound(_)));
+        assert_eq!(error.exit_code(), 3);
+    }
+
+    #[test]
+    fn dry_run_apply_runs_nothing() {
+        let runner = runner();
+        let mut app = testing::app(&runner, vec![shell_tweak("Show Path Bar", WRITE)]);
+        app.dry_run = true;
+        run_command(&app, &Output::new(OutputFormat::Json), apply("show-path-bar", None)).unwrap();
+        assert!(runner.invocations().is_empty());
+        assert!(app.history.entries().unwrap().is_empty());
+    }
+
+    #[test]
+    fn revert_restores_the_snapshot_taken_by_apply() {
+        let runner = runner();
+        let app = testing::app(&runner, vec![shell_tweak("Show Path Bar", WRITE)]);
+        let out = Output::new(OutputFormat::Json);
+        run_command(&app, &out, apply("show-path-bar", None)).unwrap();
+        runner.take_invocations();
+        run_command(&app, &out, Commands::Revert { name: "show-path-bar".to_string() }).unwrap();
+
+        assert_eq!(
+            commands(&runner),
+            [CommandLine::Exec {
+                program: "defaults".to_string(),
+                args: ["delete", "com.apple.finder", "ShowPathbar"].map(String::from).to_vec(),
+            }]
+        );
+        assert!(app.snapshots.get("test/show-path-bar").unwrap().is_none());
+        let entries = app.history.entries().unwrap();
+        assert_eq!(entries.last().unwrap().action, HistoryAction::Revert);
+    }
+
+    #[test]
+    fn revert_falls_back_to_the_disable_command() {
+        let runner = runner();
+        let mut tweak = shell_tweak("Show Path Bar", WRITE);
+        tweak.disable_command = "defaults write com.apple.finder ShowPathbar -bool false".to_string();
+        let app = testing::app(&runner, vec![tweak.clone()]);
+        run_command(&app, &Output::new(OutputFormat::Json), Commands::Revert { name: "show-path-bar".to_string() }).unwrap();
+
+        assert_eq!(commands(&runner), [CommandLine::Shell(tweak.disable_command)]);
+    }
+
+    #[test]
+    fn revert_without_snapshot_or_disable_command_is_not_runnable() {
+        let runner = runner();
+        let app = testing::app(&runner, vec![shell_tweak("Show Path Bar", WRITE)]);
+        let error = run_command(&app, &Output::new(OutputFormat::Json), Commands::Revert { name: "show-path-bar".to_string() })
+            .unwrap_err();
+        assert!(matches!(error, CliError::NotRunnable(_)), "{:?}", error);
+        assert!(runner.invocations().is_empty());
+    }
+
+    #[test]
+    fn forbidden_tweak_is_refused() {
+        let runner = runner();
+        let mut app = testing::app(&runner, vec![shell_tweak("Show Path Bar", WRITE)]);
+        app.config.forbidden_tweaks = vec!["test/show-path-bar".to_string()];
+        let error = run_command(&app, &Output::new(OutputFormat::Json), apply("show-path-bar", None)).unwrap_err();
+        assert!(matches!(error, CliError::Forbidden(_)));
+        assert!(runner.invocations().is_empty());
+    }
+
+    #[test]
+    fn diff_reports_drift_with_its_own_exit_code() {
+        let profile = testing::temp_dir().join("profile.toml");
+        fs::write(&profile, "[[tweaks]]\ntweak = \"show-path-bar\"\n").unwrap();
+        let diff = |found: &str| {
+            let runner = Arc::new(ScriptedRunner::new().always("defaults read", CommandOutput::ok(found)));
+            let mut tweak = shell_tweak("Show Path Bar", WRITE);
+            tweak.probe = Some(tweaks::Probe {
+                domain: "com.apple.finder".to_string(),
+                key: "ShowPathbar".to_string(),
+                expected: Some("1".to_string()),
+                default: None,
+                current_host: false,
+            });
+            let app = testing::app(&runner, vec![tweak]);
+            run_command(&app, &Output::new(OutputFormat::Json), Commands::Diff { profile: profile.display().to_string() })
+        };
+
+        assert!(diff("1\n").is_ok());
+        let error = diff("0\n").unwrap_err();
+        assert!(matches!(error, CliError::Drifted(_)), "{:?}", error);
+        assert_eq!(error.exit_code(), 8);
+    }
+
+    #[test]
+    fn failed_package_info_stays_in_the_list() {
+        let installed = r#"{"formulae": [{"name": "wget", "full_name": "wget", "installed": [{"version": "1.25.0"}]}]}"#;
+        let runner = Arc::new(
+            ScriptedRunner::new()
+                .always("brew info --json=v2 --installed", CommandOutput::ok(installed))
+                .always("brew info", CommandOutput::failed(1, "Error: No available formula")),
+        );
+        let mut tweak = testing::tweak(
+            "Installed Packages",
+            TweakAction::OpenList { source: tweaks::ListSource::BrewInstalled, on_select: ListSelectAction::BrewInfo },
+        );
+        tweak.kind = TweakKind::Query;
+        let mut app = testing::app(&runner, vec![tweak]);
+        testing::select(&mut app, "Installed Packages");
+        let mut terminal = Terminal::new(ratatui::backend::TestBackend::new(80, 24)).unwrap();
+        let run_interactive = |_: &mut Terminal<_>, runner: &dyn CommandRunner, request: &CommandRequest| runner.interactive(request);
+        app.apply_selected_tweak(&mut terminal, run_interactive).unwrap();
+        handle_fullscreen_list_nav(&mut app, KeyCode::Enter, &mut terminal, run_interactive).unwrap();
+
+        assert!(app.fullscreen_list.is_some());
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("Error executing 'brew info wget': Command failed with exit code 1")
+        );
+    }
+
+    #[test]
+    fn apply_ambiguous_name_is_its_own_error() {
+        let runner = runner();
+        let app = testing::grouped_app(
+            &runner,
+            vec![
+                ("computer-sleep", vec![shell_tweak("Sleep After", "pmset sleep 30")]),
+                ("display-sleep", vec![shell_tweak("Sleep After", "pmset displaysleep 30")]),
+            ],
+        );
+        let error = run_command(&app, &Output::new(OutputFormat::Json), apply("Sleep After", None)).unwrap_err();
+        assert!(matches!(error, CliError::Ambiguous(_)), "{:?}", error);
+        assert_eq!(error.exit_code(), 9);
+        assert!(error.to_string().contains("test/display-sleep/sleep-after"), "{}", error);
+        assert!(runner.invocations().is_empty());
+    }
+
+    #[test]
+    fn unknown_tweak_in_an_included_profile_names_that_file() {
+        let dir = testing::temp_dir();
+        let base = dir.join("base.toml");
+        std::fs::write(&base, "tweaks = [{ tweak = \"no-such-tweak\" }]").unwrap();
+        let main = dir.join("main.toml");
+        std::fs::write(&main, "include = [\"base.toml\"]\ntweaks = [{ tweak = \"Show Path Bar\" }]").unwrap();
+        let app = testing::app(&runner(), vec![shell_tweak("Show Path Bar", WRITE)]);
+
+        let show = Commands::Profile(ProfileCommand::Show { name: main.display().to_string() });
+        let error = run_command(&app, &Output::new(OutputFormat::Json), show).unwrap_err();
+        assert!(
+            matches!(&error, CliError::InvalidProfile(message) if *message == format!("{}: Tweak not found: 'no-such-tweak'", base.display())),
+            "{:?}",
+            error
+        );
+        assert_eq!(error.exit_code(), 5);
+    }
+}
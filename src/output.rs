@@ -0,0 +1,656 @@
+use crate::app::LookupError;
+use crate::batch::BatchStep;
+use crate::brew::Package;
+use crate::brewfile::Entry;
+use crate::drift::DriftState;
+use crate::history::{HistoryAction, HistoryEntry};
+use crate::param::Param;
+use crate::probe::ProbeState;
+use crate::profile::ProfileTweak;
+use crate::runner::{CommandOutput, CommandRequest};
+use crate::tweaks::{NodeId, NodeKind, Tweak, TweakKind, TweakTree};
+use serde::Serialize;
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+
+/// How the CLI prints its results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Human-readable text.
+    #[default]
+    Text,
+    /// One JSON document per invocation.
+    Json,
+    /// One JSON object per line, printed as results become available.
+    Ndjson,
+}
+
+/// Stable identifiers for the ways a CLI command can fail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    NotFound,
+    InvalidArgument,
+    NotRunnable,
+    InvalidProfile,
+    CommandFailed,
+    BatchFailed,
+    Forbidden,
+    Canceled,
+    TimedOut,
+    PermissionDenied,
+    Drifted,
+    Ambiguous,
+    Internal,
+}
+
+/// An error reported by a CLI command. Each kind has a stable [`ErrorCode`]
+/// for the structured formats and a documented exit status.
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum CliError {
+    /// No tweak by that name.
+    #[error("Tweak not found: '{0}'")]
+    NotFound(String),
+    /// A value or combination of arguments that is not accepted.
+    #[error("{0}")]
+    InvalidArgument(String),
+    /// The tweak exists but cannot be run this way, e.g. has no revert.
+    #[error("{0}")]
+    NotRunnable(String),
+    /// A profile that cannot be read or refers to unknown tweaks.
+    #[error("{0}")]
+    InvalidProfile(String),
+    /// A command run for a tweak exited unsuccessfully, with its exit code
+    /// when it has one.
+    #[error("{message}")]
+    CommandFailed { message: String, exit_code: Option<i32> },
+    /// A batch stopped at a failing tweak and was rolled back.
+    #[error("{0}")]
+    BatchFailed(String),
+    /// Policy does not allow the tweak on this machine.
+    #[error("{0}")]
+    Forbidden(String),
+    /// The user stopped a command or did not confirm a destructive tweak.
+    #[error("{0}")]
+    Canceled(String),
+    /// A command ran past its timeout and was killed.
+    #[error("{0}")]
+    TimedOut(String),
+    /// sudo could not authenticate, or the system refused access.
+    #[error("{0}")]
+    PermissionDenied(String),
+    /// A name that matches more than one tweak.
+    #[error("{0}")]
+    Ambiguous(String),
+    /// `diff` found tweaks that differ from the profile.
+    #[error("{0}")]
+    Drifted(String),
+    /// Anything else, such as an unreadable catalog or history.
+    #[error("{0}")]
+    Internal(String),
+}
+
+impl CliError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            CliError::NotFound(_) => ErrorCode::NotFound,
+            CliError::InvalidArgument(_) => ErrorCode::InvalidArgument,
+            CliError::NotRunnable(_) => ErrorCode::NotRunnable,
+            CliError::InvalidProfile(_) => ErrorCode::InvalidProfile,
+            CliError::CommandFailed { .. } => ErrorCode::CommandFailed,
+            CliError::BatchFailed(_) => ErrorCode::BatchFailed,
+            CliError::Forbidden(_) => ErrorCode::Forbidden,
+            CliError::Canceled(_) => ErrorCode::Canceled,
+            CliError::TimedOut(_) => ErrorCode::TimedOut,
+            CliError::PermissionDenied(_) => ErrorCode::PermissionDenied,
+            CliError::Drifted(_) => ErrorCode::Drifted,
+            CliError::Ambiguous(_) => ErrorCode::Ambiguous,
+            CliError::Internal(_) => ErrorCode::Internal,
+        }
+    }
+
+    /// The process exit status for this error. A failed command passes its
+    /// own exit code through.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CliError::Internal(_) => 1,
+            CliError::InvalidArgument(_) => 2,
+            CliError::NotFound(_) => 3,
+            CliError::NotRunnable(_) => 4,
+            CliError::InvalidProfile(_) => 5,
+            CliError::BatchFailed(_) => 6,
+            CliError::Forbidden(_) => 7,
+            CliError::Drifted(_) => 8,
+            CliError::Ambiguous(_) => 9,
+            CliError::CommandFailed { exit_code, .. } => exit_code.filter(|&code| code != 0).unwrap_or(1),
+            CliError::PermissionDenied(_) => 77,
+            CliError::TimedOut(_) => 124,
+            CliError::Canceled(_) => 130,
+        }
+    }
+
+    /// `e` as a [`CliError::PermissionDenied`] if the system refused access
+    /// to a file or program, otherwise as `kind`.
+    pub fn classify(e: anyhow::Error, kind: fn(String) -> CliError) -> Self {
+        let message = format!("{:#}", e);
+        let denied = e
+            .chain()
+            .filter_map(|cause| cause.downcast_ref::<io::Error>())
+            .any(|cause| cause.kind() == io::ErrorKind::PermissionDenied);
+        if denied {
+            CliError::PermissionDenied(message)
+        } else {
+            kind(message)
+        }
+    }
+
+    /// Checks the outcome of a command run for a tweak.
+    pub fn check_command(result: anyhow::Result<CommandOutput>) -> Result<CommandOutput, Self> {
+        let output = match result {
+            Ok(output) if output.success() => return Ok(output),
+            Ok(output) => output,
+            Err(e) => {
+                return Err(Self::classify(e, |message| CliError::CommandFailed { message, exit_code: None }));
+            }
+        };
+        let (exit_code, interrupted, timed_out) = (output.status, output.interrupted(), output.timed_out.is_some());
+        let denied = ["Operation not permitted", "Permission denied"]
+            .iter()
+            .any(|text| output.stderr.contains(text));
+        let message = output.into_result().err().map(|e| e.to_string()).unwrap_or_default();
+        Err(if timed_out {
+            CliError::TimedOut(message)
+        } else if interrupted {
+            CliError::Canceled(message)
+        } else if denied {
+            CliError::PermissionDenied(message)
+        } else {
+            CliError::CommandFailed { message, exit_code }
+        })
+    }
+}
+
+impl Serialize for CliError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Fields {
+            code: ErrorCode,
+            message: String,
+            exit_code: i32,
+        }
+        Fields {
+            code: self.code(),
+            message: self.to_string(),
+            exit_code: self.exit_code(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl From<LookupError> for CliError {
+    fn from(e: LookupError) -> Self {
+        match e {
+            LookupError::NotFound(name) => CliError::NotFound(name),
+            LookupError::Ambiguous { .. } => CliError::Ambiguous(e.to_string()),
+        }
+    }
+}
+
+impl From<anyhow::Error> for CliError {
+    fn from(e: anyhow::Error) -> Self {
+        Self::classify(e, CliError::Internal)
+    }
+}
+
+/// A tweak as listed by `list`.
+#[derive(Debug, Serialize)]
+pub struct TweakInfo {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    pub kind: TweakKind,
+    pub destructive: bool,
+    pub requires_root: bool,
+    pub interactive: bool,
+    pub repeatable: bool,
+    /// Whether the tweak has a probe, so `status` and `diff` can read it.
+    pub probed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub param: Option<Param>,
+}
+
+impl From<&Tweak> for TweakInfo {
+    fn from(tweak: &Tweak) -> Self {
+        TweakInfo {
+            id: tweak.id.clone(),
+            name: tweak.name.clone(),
+            description: tweak.description.clone(),
+            kind: tweak.kind,
+            destructive: tweak.destructive,
+            requires_root: tweak.requires_root,
+            interactive: tweak.interactive,
+            repeatable: tweak.repeatable,
+            probed: tweak.probe.is_some(),
+            param: tweak.action.param().cloned(),
+        }
+    }
+}
+
+/// A tweak found by `search`.
+#[derive(Debug, Serialize)]
+pub struct SearchResult {
+    /// Where the tweak is, e.g. `Finder › Finder Appearance › Show Path Bar`.
+    pub breadcrumbs: String,
+    /// Higher is a better match.
+    pub score: i64,
+    #[serde(flatten)]
+    pub tweak: TweakInfo,
+}
+
+/// A category or group in `list`, with what it contains.
+#[derive(Debug, Serialize)]
+pub struct NodeInfo {
+    pub id: String,
+    pub name: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<NodeInfo>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tweaks: Vec<TweakInfo>,
+}
+
+impl NodeInfo {
+    /// The container `id` with everything below it that runs from the CLI.
+    pub fn new(tree: &TweakTree, id: NodeId) -> Self {
+        let node = tree.node(id);
+        let mut info = NodeInfo {
+            id: node.id.clone(),
+            name: node.name.clone(),
+            groups: Vec::new(),
+            tweaks: Vec::new(),
+        };
+        for &child in tree.children(Some(id)) {
+            match &tree.node(child).kind {
+                NodeKind::Tweak(tweak) if tweak.action.runs_in_cli() => info.tweaks.push(TweakInfo::from(&**tweak)),
+                NodeKind::Tweak(_) => {}
+                _ => info.groups.push(NodeInfo::new(tree, child)),
+            }
+        }
+        info
+    }
+}
+
+/// One command run by `apply` or `revert`.
+#[derive(Debug, Clone, Serialize)]
+pub struct CommandResult {
+    pub tweak: String,
+    pub action: HistoryAction,
+    pub command: String,
+    /// Exit code; `None` if the command could not be started, was killed by
+    /// a signal, or was not run because of `--dry-run`.
+    pub exit_code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub timed_out: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub canceled: bool,
+}
+
+impl CommandResult {
+    pub fn new(tweak: &Tweak, action: HistoryAction, request: &CommandRequest, result: &anyhow::Result<CommandOutput>) -> Self {
+        let (exit_code, stdout, stderr) = match result {
+            Ok(output) => (output.status, output.stdout.clone(), output.stderr.clone()),
+            Err(e) => (None, String::new(), e.to_string()),
+        };
+        let output = result.as_ref().ok();
+        CommandResult {
+            tweak: tweak.id.clone(),
+            action,
+            command: request.display(),
+            exit_code,
+            stdout,
+            stderr,
+            timed_out: output.is_some_and(|output| output.timed_out.is_some()),
+            canceled: output.is_some_and(CommandOutput::interrupted),
+        }
+    }
+
+    /// A command that a dry run would have executed.
+    pub fn planned(tweak: &Tweak, action: HistoryAction, request: &CommandRequest) -> Self {
+        CommandResult {
+            tweak: tweak.id.clone(),
+            action,
+            command: request.display(),
+            exit_code: None,
+            stdout: String::new(),
+            stderr: String::new(),
+            timed_out: false,
+            canceled: false,
+        }
+    }
+}
+
+/// Outcome of `apply`, `revert` or `profile apply`.
+#[derive(Debug, Serialize)]
+pub struct RunResult {
+    pub action: HistoryAction,
+    pub dry_run: bool,
+    pub succeeded: bool,
+    /// Ids of the tweaks that were to be applied or reverted, in order.
+    pub tweaks: Vec<String>,
+    /// Every command run, unless they were already streamed as `ndjson`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub commands: Vec<CommandResult>,
+    /// Output of tweaks that print information rather than run commands.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    /// Homebrew packages listed by tweaks that show them.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub packages: Vec<Package>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub rolled_back: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<CliError>,
+}
+
+impl RunResult {
+    pub fn new(action: HistoryAction, dry_run: bool, tweaks: Vec<String>) -> Self {
+        RunResult {
+            action,
+            dry_run,
+            succeeded: true,
+            tweaks,
+            commands: Vec::new(),
+            output: None,
+            packages: Vec::new(),
+            rolled_back: Vec::new(),
+            warnings: Vec::new(),
+            error: None,
+        }
+    }
+}
+
+/// One line of `ndjson` output.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Record<'a> {
+    Category { id: &'a str, name: &'a str },
+    Group { id: &'a str, name: &'a str, parent: &'a str },
+    Tweak {
+        parent: &'a str,
+        #[serde(flatten)]
+        tweak: TweakInfo,
+    },
+    Setting(&'a Setting),
+    SearchResult(&'a SearchResult),
+    Command(&'a CommandResult),
+    Result(&'a RunResult),
+    Drift(&'a DriftInfo),
+    Diff(&'a DiffResult),
+    Step(&'a StepInfo),
+    Profile(&'a ProfileInfo),
+    HistoryEntry(&'a HistoryEntry),
+    Error(&'a CliError),
+}
+
+/// A probed setting as reported by `status`.
+#[derive(Debug, Serialize)]
+pub struct Setting {
+    pub id: String,
+    pub name: String,
+    pub category: String,
+    /// `on`, `off`, `value` or `unknown`.
+    pub state: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+}
+
+impl Setting {
+    pub fn new(tweak: &Tweak, category: &str, state: &ProbeState) -> Self {
+        let (state, value) = match state {
+            ProbeState::On => ("on", None),
+            ProbeState::Off => ("off", None),
+            ProbeState::Value(value) => ("value", Some(value.clone())),
+            ProbeState::Unknown => ("unknown", None),
+        };
+        Setting {
+            id: tweak.id.clone(),
+            name: tweak.name.clone(),
+            category: category.to_string(),
+            state,
+            value,
+        }
+    }
+}
+
+/// A tweak of a profile, for `profile show`.
+#[derive(Debug, Serialize)]
+pub struct StepInfo {
+    pub id: String,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+}
+
+/// How one tweak of a profile compares with the machine, for `diff`.
+#[derive(Debug, Serialize)]
+pub struct DriftInfo {
+    pub id: String,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    /// `matches`, `drifted` or `unknown`.
+    pub state: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expected: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub found: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl DriftInfo {
+    pub fn new(step: &BatchStep, state: DriftState) -> Self {
+        let mut info = DriftInfo {
+            id: step.tweak.id.clone(),
+            name: step.tweak.name.clone(),
+            value: step.value.clone(),
+            state: "matches",
+            expected: None,
+            found: None,
+            reason: None,
+        };
+        match state {
+            DriftState::Matches => {}
+            DriftState::Drifted { expected, found } => {
+                info.state = "drifted";
+                info.expected = Some(expected);
+                info.found = Some(found);
+            }
+            DriftState::Unknown(reason) => {
+                info.state = "unknown";
+                info.reason = Some(reason);
+            }
+        }
+        info
+    }
+}
+
+/// Outcome of `diff`.
+#[derive(Debug, Serialize)]
+pub struct DiffResult {
+    pub profile: String,
+    /// Every tweak of the profile, unless they were already streamed as
+    /// `ndjson`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tweaks: Vec<DriftInfo>,
+    pub matching: usize,
+    pub drifted: usize,
+    pub unknown: usize,
+}
+
+/// A profile written by `profile save`.
+#[derive(Debug, Serialize)]
+pub struct SavedProfile<'a> {
+    pub profile: &'a str,
+    pub path: &'a Path,
+    pub tweaks: &'a [ProfileTweak],
+    /// Names of applied tweaks left out because their value could not be read.
+    pub skipped: &'a [String],
+}
+
+/// A Brewfile written by `brew export`.
+#[derive(Debug, Serialize)]
+pub struct ExportedBrewfile<'a> {
+    /// Where it was written; left out when it was printed instead.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<&'a Path>,
+    pub entries: &'a [Entry],
+}
+
+/// A saved profile, for `profile list`.
+#[derive(Debug, Serialize)]
+pub struct ProfileInfo {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Prints CLI results in the chosen format. In `ndjson` mode each record is
+/// printed as soon as it is emitted; in `json` mode the command prints one
+/// document at the end.
+#[derive(Debug, Default)]
+pub struct Output {
+    pub format: OutputFormat,
+    commands: RefCell<Vec<CommandResult>>,
+    warnings: RefCell<Vec<String>>,
+    error_reported: Cell<bool>,
+}
+
+impl Output {
+    pub fn new(format: OutputFormat) -> Self {
+        Output {
+            format,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_text(&self) -> bool {
+        self.format == OutputFormat::Text
+    }
+
+    /// Prints `value` as a JSON document: pretty in `json` mode, on one line
+    /// in `ndjson` mode.
+    pub fn document(&self, value: &impl Serialize) {
+        println!("{}", self.render(value));
+    }
+
+    /// `value` as JSON, with the warnings reported since the last document
+    /// added as a `warnings` field.
+    fn render(&self, value: &impl Serialize) -> String {
+        #[derive(Serialize)]
+        struct WithWarnings<'a, T> {
+            #[serde(flatten)]
+            value: &'a T,
+            warnings: Vec<String>,
+        }
+        let warnings = self.warnings.take();
+        let json = match (self.format, warnings.is_empty()) {
+            (OutputFormat::Json, true) => serde_json::to_string_pretty(value),
+            (OutputFormat::Json, false) => serde_json::to_string_pretty(&WithWarnings { value, warnings }),
+            (_, true) => serde_json::to_string(value),
+            (_, false) => serde_json::to_string(&WithWarnings { value, warnings }),
+        };
+        json.unwrap_or_default()
+    }
+
+    /// Prints `{"<key>": items}` as a JSON document.
+    pub fn list<T: Serialize>(&self, key: &str, items: &[T]) {
+        self.document(&BTreeMap::from([(key, items)]));
+    }
+
+    /// Prints `record` on its own line in `ndjson` mode.
+    pub fn record(&self, record: Record) {
+        if self.format == OutputFormat::Ndjson {
+            self.document(&record);
+        }
+    }
+
+    /// Notes a command that was run, streaming it in `ndjson` mode.
+    pub fn command(&self, result: CommandResult) {
+        match self.format {
+            OutputFormat::Text => {}
+            OutputFormat::Json => self.commands.borrow_mut().push(result),
+            OutputFormat::Ndjson => self.record(Record::Command(&result)),
+        }
+    }
+
+    /// Reports a problem that does not stop the command: on stderr as text,
+    /// otherwise in the next document printed.
+    pub fn warning(&self, warning: String) {
+        match self.format {
+            OutputFormat::Text => eprintln!("Warning: {}", warning),
+            _ => self.warnings.borrow_mut().push(warning),
+        }
+    }
+
+    /// Prints the outcome of running tweaks, with the commands and warnings
+    /// collected since the last call. Only used in the structured formats.
+    pub fn run_result(&self, mut result: RunResult) {
+        result.commands = self.commands.take();
+        result.warnings.splice(0..0, self.warnings.take());
+        self.error_reported.set(result.error.is_some());
+        match self.format {
+            OutputFormat::Text => {}
+            OutputFormat::Json => self.document(&result),
+            OutputFormat::Ndjson => self.record(Record::Result(&result)),
+        }
+    }
+
+    /// Notes that the result just printed already reports the error the
+    /// command is about to return, so [`Output::error`] adds nothing.
+    pub fn error_in_result(&self) {
+        self.error_reported.set(true);
+    }
+
+    /// Reports an error, unless a result carrying it was already printed.
+    pub fn error(&self, error: &CliError) {
+        if self.error_reported.get() {
+            return;
+        }
+        match self.format {
+            OutputFormat::Text if error.code() == ErrorCode::Internal => eprintln!("Error: {}", error),
+            OutputFormat::Text => eprintln!("{}", error),
+            OutputFormat::Json => {
+                #[derive(Serialize)]
+                struct ErrorDocument<'a> {
+                    error: &'a CliError,
+                }
+                self.document(&ErrorDocument { error });
+            }
+            OutputFormat::Ndjson => self.record(Record::Error(error)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn warnings_go_into_the_next_document() {
+        let out = Output::new(OutputFormat::Ndjson);
+        out.warning("mas is not installed".to_string());
+        assert_eq!(
+            out.render(&BTreeMap::from([("profiles", Vec::<String>::new())])),
+            r#"{"profiles":[],"warnings":["mas is not installed"]}"#
+        );
+        assert_eq!(out.render(&BTreeMap::from([("profiles", Vec::<String>::new())])), r#"{"profiles":[]}"#);
+    }
+}
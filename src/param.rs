@@ -0,0 +1,369 @@
+use crate::runner::CommandRequest;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Placeholder replaced by the chosen value in a tweak's `run` arguments.
+pub const PLACEHOLDER: &str = "{}";
+
+/// The type of value a tweak asks for, and how it is validated.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Param {
+    /// A single line of text.
+    String,
+    /// A whole number between `min` and `max`, inclusive.
+    Int {
+        min: i64,
+        max: i64,
+        /// Increment used by the picker; defaults to 1.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        step: Option<i64>,
+    },
+    /// A decimal number between `min` and `max`, inclusive.
+    Float {
+        min: f64,
+        max: f64,
+        /// Increment used by the picker; defaults to a twentieth of the range.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        step: Option<f64>,
+    },
+    /// A length of time, passed on as a whole number of `unit`s. Accepts
+    /// plain numbers in `unit` or suffixed values such as `90s`, `5m`, `1h30m`.
+    Duration {
+        unit: TimeUnit,
+        min: u64,
+        max: u64,
+        /// Increment used by the picker, in `unit`s; defaults to 1.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        step: Option<u64>,
+    },
+    /// One of a fixed set of values.
+    Enum { values: Vec<String> },
+    /// A file system path; `~/` is expanded to the home directory.
+    Path {
+        #[serde(default)]
+        must_exist: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeUnit {
+    Seconds,
+    Minutes,
+}
+
+impl TimeUnit {
+    fn seconds(&self) -> u64 {
+        match self {
+            TimeUnit::Seconds => 1,
+            TimeUnit::Minutes => 60,
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            TimeUnit::Seconds => "seconds",
+            TimeUnit::Minutes => "minutes",
+        }
+    }
+}
+
+impl Param {
+    /// Short description of what is accepted, shown next to the prompt.
+    pub fn hint(&self) -> String {
+        match self {
+            Param::String => "text".to_string(),
+            Param::Int { min, max, .. } => format!("{}-{}", min, max),
+            Param::Float { min, max, .. } => format!("{}-{}", min, max),
+            Param::Duration { unit, min, max, .. } => format!("{}-{} {}", min, max, unit.label()),
+            Param::Enum { values } => values.join(" | "),
+            Param::Path { .. } => "path".to_string(),
+        }
+    }
+
+    /// Validates `input`, returning the value to substitute or a message
+    /// saying why it was rejected.
+    pub fn parse(&self, input: &str) -> Result<String, String> {
+        let input = input.trim();
+        if input.is_empty() {
+            return Err("A value is required.".to_string());
+        }
+        if input.chars().any(char::is_control) {
+            return Err("The value must not contain control characters.".to_string());
+        }
+        match self {
+            Param::String => Ok(input.to_string()),
+            Param::Int { min, max, .. } => match input.parse::<i64>() {
+                Ok(value) if (*min..=*max).contains(&value) => Ok(value.to_string()),
+                Ok(_) => Err(format!("Enter a number from {} to {}.", min, max)),
+                Err(_) => Err(format!("'{}' is not a whole number.", input)),
+            },
+            Param::Float { min, max, .. } => match input.parse::<f64>() {
+                Ok(value) if value >= *min && value <= *max => Ok(value.to_string()),
+                Ok(_) => Err(format!("Enter a number from {} to {}.", min, max)),
+                Err(_) => Err(format!("'{}' is not a number.", input)),
+            },
+            Param::Duration { unit, min, max, .. } => match parse_duration(input, *unit) {
+                Some(value) if (*min..=*max).contains(&value) => Ok(value.to_string()),
+                Some(_) => Err(format!("Enter a duration from {} to {} {}.", min, max, unit.label())),
+                None => Err(format!("'{}' is not a duration in whole {}.", input, unit.label())),
+            },
+            Param::Enum { values } => values
+                .iter()
+                .find(|value| value.eq_ignore_ascii_case(input))
+                .cloned()
+                .ok_or_else(|| format!("Choose one of: {}.", values.join(", "))),
+            Param::Path { must_exist } => {
+                let path = expand_home(input);
+                if *must_exist && !Path::new(&path).exists() {
+                    Err(format!("'{}' does not exist.", path))
+                } else {
+                    Ok(path)
+                }
+            }
+        }
+    }
+
+    /// Whether the picker can move through the accepted values with ←/→.
+    pub fn is_steppable(&self) -> bool {
+        !matches!(self, Param::String | Param::Path { .. })
+    }
+
+    /// The value one step up (`forward`) or down from `current`, clamped to
+    /// the range. Enums wrap around. Starts from the minimum or first value
+    /// when `current` is not valid.
+    pub fn step(&self, current: &str, forward: bool) -> Option<String> {
+        match self {
+            Param::Int { min, max, step } => {
+                let value = match current.trim().parse::<i64>() {
+                    Ok(v) if forward => v.saturating_add(step.unwrap_or(1)),
+                    Ok(v) => v.saturating_sub(step.unwrap_or(1)),
+                    Err(_) => *min,
+                };
+                Some(value.clamp(*min, *max).to_string())
+            }
+            Param::Float { min, max, step } => {
+                let step = step.unwrap_or((max - min) / 20.0);
+                let sign = if forward { 1.0 } else { -1.0 };
+                let value = current.trim().parse::<f64>().map_or(*min, |v| v + sign * step);
+                // Round away floating point noise from repeated steps
+                Some(((value.clamp(*min, *max) * 1e6).round() / 1e6).to_string())
+            }
+            Param::Duration { unit, min, max, step } => {
+                let value = match parse_duration(current.trim(), *unit) {
+                    Some(v) if forward => v.saturating_add(step.unwrap_or(1)),
+                    Some(v) => v.saturating_sub(step.unwrap_or(1)),
+                    None => *min,
+                };
+                Some(value.clamp(*min, *max).to_string())
+            }
+            Param::Enum { values } => {
+                let index = values.iter().position(|v| v.eq_ignore_ascii_case(current.trim()));
+                let next = match index {
+                    Some(i) if forward => (i + 1) % values.len(),
+                    Some(i) => (i + values.len() - 1) % values.len(),
+                    None => 0,
+                };
+                values.get(next).cloned()
+            }
+            Param::String | Param::Path { .. } => None,
+        }
+    }
+
+    /// Where `current` sits within a numeric range, from 0.0 to 1.0.
+    pub fn position(&self, current: &str) -> Option<f64> {
+        let (value, min, max) = match self {
+            Param::Int { min, max, .. } => (current.trim().parse::<f64>().ok()?, *min as f64, *max as f64),
+            Param::Float { min, max, .. } => (current.trim().parse::<f64>().ok()?, *min, *max),
+            Param::Duration { unit, min, max, .. } => {
+                (parse_duration(current.trim(), *unit)? as f64, *min as f64, *max as f64)
+            }
+            _ => return None,
+        };
+        if max <= min {
+            return Some(1.0);
+        }
+        Some(((value - min) / (max - min)).clamp(0.0, 1.0))
+    }
+
+    /// Problem with the declaration itself, for catalog validation.
+    pub fn check(&self) -> Option<String> {
+        match self {
+            Param::Int { min, max, .. } if min > max => Some(format!("param min {} is greater than max {}", min, max)),
+            Param::Float { min, max, .. } if min > max => Some(format!("param min {} is greater than max {}", min, max)),
+            Param::Duration { min, max, .. } if min > max => {
+                Some(format!("param min {} is greater than max {}", min, max))
+            }
+            Param::Int { step: Some(step), .. } if *step <= 0 => Some("param step must be positive".to_string()),
+            Param::Float { step: Some(step), .. } if *step <= 0.0 => Some("param step must be positive".to_string()),
+            Param::Duration { step: Some(0), .. } => Some("param step must be positive".to_string()),
+            Param::Enum { values } if values.is_empty() => Some("enum param needs at least one value".to_string()),
+            Param::Enum { values } if values.iter().any(|v| v.trim().is_empty()) => {
+                Some("enum param values must not be empty".to_string())
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Parses `90`, `90s`, `5m`, `1h30m` and the like into whole `unit`s.
+/// Returns `None` for malformed input or a duration that is not a whole
+/// number of `unit`s.
+fn parse_duration(input: &str, unit: TimeUnit) -> Option<u64> {
+    if let Ok(value) = input.parse::<u64>() {
+        return Some(value);
+    }
+    let mut seconds: u64 = 0;
+    let mut digits = String::new();
+    for c in input.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        let multiplier = match c.to_ascii_lowercase() {
+            's' => 1,
+            'm' => 60,
+            'h' => 3600,
+            _ => return None,
+        };
+        let amount: u64 = digits.parse().ok()?;
+        seconds = seconds.checked_add(amount.checked_mul(multiplier)?)?;
+        digits.clear();
+    }
+    if !digits.is_empty() || !seconds.is_multiple_of(unit.seconds()) {
+        return None;
+    }
+    Some(seconds / unit.seconds())
+}
+
+fn expand_home(path: &str) -> String {
+    match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest).display().to_string(),
+        _ => path.to_string(),
+    }
+}
+
+/// Builds the commands for `run` with `value` substituted. Each entry is a
+/// program and its arguments, run directly without a shell, so the value is
+/// always passed as (part of) a single argument.
+pub fn commands_for(run: &[Vec<String>], value: &str) -> Vec<CommandRequest> {
+    run.iter()
+        .filter_map(|argv| argv.split_first())
+        .map(|(program, args)| {
+            CommandRequest::exec(
+                program.clone(),
+                args.iter().map(|arg| arg.replace(PLACEHOLDER, value)),
+            )
+        })
+        .collect()
+}
+
+/// Problem with a `run` list, for catalog validation.
+pub fn check_run(run: &[Vec<String>]) -> Option<String> {
+    if run.is_empty() {
+        return Some("run must list at least one command".to_string());
+    }
+    if run.iter().any(|argv| argv.first().is_none_or(|program| program.trim().is_empty())) {
+        return Some("every run command needs a program".to_string());
+    }
+    if run.iter().any(|argv| argv[0].contains(PLACEHOLDER)) {
+        return Some("the value may only be substituted into arguments, not the program".to_string());
+    }
+    if !run.iter().flatten().any(|arg| arg.contains(PLACEHOLDER)) {
+        return Some("run must contain a '{}' placeholder".to_string());
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn int(min: i64, max: i64) -> Param {
+        Param::Int { min, max, step: Some(10) }
+    }
+
+    fn minutes(min: u64, max: u64) -> Param {
+        Param::Duration { unit: TimeUnit::Minutes, min, max, step: Some(5) }
+    }
+
+    #[test]
+    fn parses_durations_with_units() {
+        assert_eq!(parse_duration("90", TimeUnit::Seconds), Some(90));
+        assert_eq!(parse_duration("90s", TimeUnit::Seconds), Some(90));
+        assert_eq!(parse_duration("5m", TimeUnit::Seconds), Some(300));
+        assert_eq!(parse_duration("1h30m", TimeUnit::Minutes), Some(90));
+        assert_eq!(parse_duration("2H", TimeUnit::Minutes), Some(120));
+        assert_eq!(parse_duration("15", TimeUnit::Minutes), Some(15));
+    }
+
+    #[test]
+    fn rejects_malformed_durations() {
+        assert_eq!(parse_duration("90s", TimeUnit::Minutes), None, "not a whole number of minutes");
+        assert_eq!(parse_duration("5x", TimeUnit::Seconds), None);
+        assert_eq!(parse_duration("m", TimeUnit::Seconds), None);
+        assert_eq!(parse_duration("1h30", TimeUnit::Minutes), None);
+        assert_eq!(parse_duration("-5m", TimeUnit::Minutes), None);
+        assert_eq!(parse_duration("99999999999999999999h", TimeUnit::Seconds), None);
+    }
+
+    #[test]
+    fn parse_checks_bounds() {
+        assert_eq!(int(16, 128).parse(" 64 "), Ok("64".to_string()));
+        assert_eq!(int(16, 128).parse("128"), Ok("128".to_string()));
+        assert_eq!(int(16, 128).parse("129"), Err("Enter a number from 16 to 128.".to_string()));
+        assert_eq!(int(16, 128).parse("big"), Err("'big' is not a whole number.".to_string()));
+        assert_eq!(minutes(1, 180).parse("1h"), Ok("60".to_string()));
+        assert_eq!(minutes(1, 180).parse("4h"), Err("Enter a duration from 1 to 180 minutes.".to_string()));
+        assert_eq!(minutes(1, 180).parse("30s"), Err("'30s' is not a duration in whole minutes.".to_string()));
+
+        let float = Param::Float { min: 0.0, max: 1.0, step: None };
+        assert_eq!(float.parse("0.25"), Ok("0.25".to_string()));
+        assert_eq!(float.parse("1.5"), Err("Enter a number from 0 to 1.".to_string()));
+    }
+
+    #[test]
+    fn parse_rejects_empty_and_control_input() {
+        assert_eq!(Param::String.parse("  "), Err("A value is required.".to_string()));
+        assert_eq!(
+            Param::String.parse("a\u{1b}b"),
+            Err("The value must not contain control characters.".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_matches_enum_values_ignoring_case() {
+        let edge = Param::Enum { values: vec!["left".to_string(), "bottom".to_string()] };
+        assert_eq!(edge.parse("Left"), Ok("left".to_string()));
+        assert_eq!(edge.parse("top"), Err("Choose one of: left, bottom.".to_string()));
+    }
+
+    #[test]
+    fn step_clamps_to_the_range() {
+        assert_eq!(int(16, 128).step("124", true), Some("128".to_string()));
+        assert_eq!(int(16, 128).step("20", false), Some("16".to_string()));
+        assert_eq!(int(16, 128).step("nonsense", true), Some("16".to_string()));
+        assert_eq!(minutes(1, 180).step("3h", true), Some("180".to_string()));
+        assert_eq!(minutes(1, 180).step("3", false), Some("1".to_string()));
+    }
+
+    #[test]
+    fn step_does_not_overflow_at_the_limits() {
+        let full = Param::Int { min: i64::MIN, max: i64::MAX, step: Some(10) };
+        assert_eq!(full.step(&(i64::MAX - 1).to_string(), true), Some(i64::MAX.to_string()));
+        assert_eq!(full.step(&(i64::MIN + 1).to_string(), false), Some(i64::MIN.to_string()));
+
+        let long = Param::Duration { unit: TimeUnit::Seconds, min: 0, max: u64::MAX, step: Some(10) };
+        assert_eq!(long.step(&u64::MAX.to_string(), true), Some(u64::MAX.to_string()));
+        assert_eq!(long.step("3", false), Some("0".to_string()));
+    }
+
+    #[test]
+    fn enum_steps_wrap_around() {
+        let edge = Param::Enum { values: vec!["left".to_string(), "bottom".to_string(), "right".to_string()] };
+        assert_eq!(edge.step("right", true), Some("left".to_string()));
+        assert_eq!(edge.step("left", false), Some("right".to_string()));
+        assert_eq!(edge.step("top", true), Some("left".to_string()));
+    }
+}
@@ -0,0 +1,200 @@
+use crate::runner::{CommandRequest, CommandRunner};
+use crate::tweaks::{Probe, Tweak};
+use serde::Serialize;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// How long a single `defaults read` may take before the state is unknown.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Whether a setting is currently in effect on this machine, or its current
+/// value for tweaks that take one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProbeState {
+    On,
+    Off,
+    Value(String),
+    Unknown,
+}
+
+impl ProbeState {
+    pub fn label(&self) -> &str {
+        match self {
+            ProbeState::On => "on",
+            ProbeState::Off => "off",
+            ProbeState::Value(value) => value,
+            ProbeState::Unknown => "unknown",
+        }
+    }
+}
+
+/// Reads the current value of the probed key with `defaults read`.
+///
+/// Returns `Ok(None)` when the key is not set and `Err` with a short reason
+/// when the value could not be read at all.
+pub fn read_value(runner: &dyn CommandRunner, probe: &Probe) -> Result<Option<String>, String> {
+    let mut args = Vec::new();
+    if probe.current_host {
+        args.push("-currentHost".to_string());
+    }
+    args.extend(["read".to_string(), probe.domain.clone(), probe.key.clone()]);
+
+    let output = runner
+        .capture(&CommandRequest::exec("defaults", args).timeout(READ_TIMEOUT))
+        .map_err(|e| e.to_string())?;
+    if output.success() {
+        Ok(Some(output.stdout.trim().to_string()))
+    } else if output.stderr.contains("does not exist") {
+        Ok(None)
+    } else {
+        Err(output.stderr.trim().to_string())
+    }
+}
+
+/// Current value of the probed key, falling back to its documented default
+/// when it is not set.
+pub fn current_value(runner: &dyn CommandRunner, probe: &Probe) -> Result<Option<String>, String> {
+    Ok(read_value(runner, probe)?.or_else(|| probe.default.clone()))
+}
+
+/// Checks whether the probed key currently holds the expected value, or
+/// reports the value itself when the probe does not expect one.
+pub fn check(runner: &dyn CommandRunner, probe: &Probe) -> ProbeState {
+    match (current_value(runner, probe), &probe.expected) {
+        (Ok(Some(value)), Some(expected)) => state_for(&value, expected),
+        (Ok(None), Some(_)) => ProbeState::Off,
+        (Ok(Some(value)), None) => ProbeState::Value(value),
+        (Ok(None), None) | (Err(_), _) => ProbeState::Unknown,
+    }
+}
+
+fn state_for(value: &str, expected: &str) -> ProbeState {
+    if values_match(value, expected) {
+        ProbeState::On
+    } else {
+        ProbeState::Off
+    }
+}
+
+/// Compares two `defaults` values, treating `1`/`true`/`YES` alike and
+/// numbers by value so `0` matches `0.0`.
+pub fn values_match(a: &str, b: &str) -> bool {
+    let (a, b) = (normalize(a), normalize(b));
+    if let (Ok(x), Ok(y)) = (a.parse::<f64>(), b.parse::<f64>()) {
+        return (x - y).abs() < 1e-9;
+    }
+    a == b
+}
+
+fn normalize(value: &str) -> String {
+    let value = value.trim().trim_matches('"');
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "yes" => "1".to_string(),
+        "false" | "no" => "0".to_string(),
+        _ => value.to_string(),
+    }
+}
+
+/// Probes every tweak that has a probe on a background thread, sending
+/// `(tweak_id, state)` pairs as they complete.
+pub fn spawn_all(runner: Arc<dyn CommandRunner>, tweaks: Vec<Tweak>) -> Receiver<(String, ProbeState)> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for tweak in tweaks {
+            if let Some(probe) = &tweak.probe {
+                let state = check(runner.as_ref(), probe);
+                if sender.send((tweak.id.clone(), state)).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+    receiver
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::{CommandLine, CommandOutput, ScriptedRunner};
+
+    fn probe(expected: Option<&str>, default: Option<&str>) -> Probe {
+        Probe {
+            domain: "com.apple.dock".to_string(),
+            key: "autohide".to_string(),
+            expected: expected.map(str::to_string),
+            default: default.map(str::to_string),
+            current_host: false,
+        }
+    }
+
+    fn missing() -> CommandOutput {
+        CommandOutput::failed(1, "The domain/default pair of (com.apple.dock, autohide) does not exist")
+    }
+
+    #[test]
+    fn check_compares_the_value_read() {
+        let runner = ScriptedRunner::new().always("defaults read", CommandOutput::ok("1\n"));
+        assert_eq!(check(&runner, &probe(Some("1"), None)), ProbeState::On);
+        assert_eq!(check(&runner, &probe(Some("0"), None)), ProbeState::Off);
+        assert_eq!(check(&runner, &probe(None, None)), ProbeState::Value("1".to_string()));
+    }
+
+    #[test]
+    fn missing_key_falls_back_to_the_default() {
+        let runner = ScriptedRunner::new().always("defaults read", missing());
+        assert_eq!(current_value(&runner, &probe(Some("1"), Some("0"))), Ok(Some("0".to_string())));
+        assert_eq!(check(&runner, &probe(Some("1"), Some("0"))), ProbeState::Off);
+        assert_eq!(check(&runner, &probe(Some("0"), Some("false"))), ProbeState::On);
+        assert_eq!(check(&runner, &probe(None, Some("48"))), ProbeState::Value("48".to_string()));
+    }
+
+    #[test]
+    fn missing_key_without_a_default() {
+        let runner = ScriptedRunner::new().always("defaults read", missing());
+        assert_eq!(read_value(&runner, &probe(Some("1"), None)), Ok(None));
+        assert_eq!(check(&runner, &probe(Some("1"), None)), ProbeState::Off);
+        assert_eq!(check(&runner, &probe(None, None)), ProbeState::Unknown);
+    }
+
+    #[test]
+    fn unreadable_value_is_unknown() {
+        let runner = ScriptedRunner::new().always("defaults read", CommandOutput::failed(1, "Could not read domain"));
+        assert_eq!(read_value(&runner, &probe(Some("1"), Some("0"))), Err("Could not read domain".to_string()));
+        assert_eq!(check(&runner, &probe(Some("1"), Some("0"))), ProbeState::Unknown);
+    }
+
+    #[test]
+    fn current_host_probe_passes_the_flag_first() {
+        let runner = ScriptedRunner::new().always("-currentHost read", CommandOutput::ok("1"));
+        let mut probe = probe(Some("1"), None);
+        probe.current_host = true;
+        assert_eq!(check(&runner, &probe), ProbeState::On);
+
+        let invocations = runner.invocations();
+        assert_eq!(invocations.len(), 1);
+        let CommandLine::Exec { program, args } = &invocations[0].request.line else {
+            panic!("expected defaults to run without a shell");
+        };
+        assert_eq!(program, "defaults");
+        assert_eq!(args, &["-currentHost", "read", "com.apple.dock", "autohide"]);
+        assert_eq!(invocations[0].request.timeout, Some(READ_TIMEOUT));
+    }
+
+    #[test]
+    fn values_match_normalizes_bools_and_numbers() {
+        assert!(values_match("1", "true"));
+        assert!(values_match("TRUE", "1"));
+        assert!(values_match("YES", "true"));
+        assert!(values_match("0", "false"));
+        assert!(values_match("no", "0"));
+        assert!(values_match("0", "0.0"));
+        assert!(values_match("\"Dark\"", "Dark"));
+        assert!(values_match(" 48\n", "48"));
+        assert!(!values_match("1", "false"));
+        assert!(!values_match("0.5", "0.25"));
+        assert!(!values_match("Dark", "dark"));
+    }
+}
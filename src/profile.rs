@@ -0,0 +1,182 @@
+use crate::config::Config;
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A named set of tweaks, stored as TOML in the profiles directory.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Profiles whose tweaks are applied first, in order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    #[serde(default)]
+    pub tweaks: Vec<ProfileTweak>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProfileTweak {
+    /// Tweak id, id segment or name, as accepted by `apply`.
+    pub tweak: String,
+    /// Value for tweaks that take one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+}
+
+impl Profile {
+    pub fn dir() -> PathBuf {
+        Config::config_dir().join("profiles")
+    }
+
+    /// Path of the profile called `name`, or `name` itself when it is a
+    /// path to a `.toml` file, so shared profiles can be used in place.
+    pub fn path(name: &str) -> PathBuf {
+        if is_path(name) {
+            PathBuf::from(name)
+        } else {
+            Self::dir().join(format!("{}.toml", name))
+        }
+    }
+
+    pub fn load(name: &str) -> Result<Self> {
+        let path = Self::path(name);
+        let contents = fs::read_to_string(&path).with_context(|| format!("{}: failed to read profile", path.display()))?;
+        toml::from_str(&contents).with_context(|| format!("{}: invalid profile", path.display()))
+    }
+
+    /// Writes the profile as `name`, refusing to replace an existing one.
+    pub fn save(&self, name: &str) -> Result<PathBuf> {
+        if name.trim().is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+            bail!("'{}' is not a valid profile name", name);
+        }
+        let path = Self::path(name);
+        if path.exists() {
+            bail!("profile '{}' already exists", name);
+        }
+        fs::create_dir_all(Self::dir())?;
+        fs::write(&path, toml::to_string_pretty(self)?)
+            .with_context(|| format!("{}: failed to write profile", path.display()))?;
+        Ok(path)
+    }
+
+    /// Names of the profiles in the profiles directory, sorted.
+    pub fn list() -> Result<Vec<String>> {
+        let dir = Self::dir();
+        if !dir.is_dir() {
+            return Ok(Vec::new());
+        }
+        let mut names: Vec<String> = fs::read_dir(&dir)
+            .with_context(|| format!("{}: failed to read profiles", dir.display()))?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+            .filter_map(|path| path.file_stem().map(|stem| stem.to_string_lossy().to_string()))
+            .collect();
+        names.sort();
+        Ok(names)
+    }
+
+    /// The tweaks of `name` with its includes expanded in place, includes
+    /// first, each with the file that lists it. Tweaks listed by more than
+    /// one profile appear more than once.
+    pub fn resolve(name: &str) -> Result<Vec<(PathBuf, ProfileTweak)>> {
+        let mut tweaks = Vec::new();
+        resolve_into(name, &mut Vec::new(), &mut tweaks)?;
+        Ok(tweaks)
+    }
+}
+
+fn resolve_into(name: &str, stack: &mut Vec<PathBuf>, tweaks: &mut Vec<(PathBuf, ProfileTweak)>) -> Result<()> {
+    let path = Profile::path(name);
+    let key = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+    if stack.contains(&key) {
+        let chain: Vec<String> = stack.iter().chain([&key]).map(|p| display_name(p)).collect();
+        return Err(anyhow!("profile include cycle: {}", chain.join(" -> ")));
+    }
+    let profile = Profile::load(name)?;
+    stack.push(key);
+    for include in &profile.include {
+        // Includes given as relative paths are relative to the including file
+        let include = match path.parent() {
+            Some(parent) if is_path(include) && Path::new(include).is_relative() => {
+                parent.join(include).display().to_string()
+            }
+            _ => include.clone(),
+        };
+        resolve_into(&include, stack, tweaks)?;
+    }
+    stack.pop();
+
+    tweaks.extend(profile.tweaks.into_iter().map(|tweak| (path.clone(), tweak)));
+    Ok(())
+}
+
+/// Whether a profile is given as a file path rather than by name.
+fn is_path(name: &str) -> bool {
+    name.ends_with(".toml") || name.contains('/')
+}
+
+fn display_name(path: &Path) -> String {
+    path.file_stem().map_or_else(|| path.display().to_string(), |stem| stem.to_string_lossy().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::{CommandOutput, ScriptedRunner};
+    use crate::testing::{self, shell_tweak, temp_dir, value_tweak};
+    use std::sync::Arc;
+
+    /// Writes `contents` to `name` below `dir` and returns its path.
+    fn write(dir: &Path, name: &str, contents: &str) -> String {
+        let path = dir.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, contents).unwrap();
+        path.display().to_string()
+    }
+
+    fn tweaks(name: &str) -> Vec<String> {
+        Profile::resolve(name).unwrap().into_iter().map(|(_, entry)| entry.tweak).collect()
+    }
+
+    #[test]
+    fn includes_resolve_relative_to_the_including_file() {
+        let dir = temp_dir();
+        write(&dir, "shared.toml", "tweaks = [{ tweak = \"shared\" }]");
+        write(&dir, "team/base.toml", "include = [\"../shared.toml\"]\ntweaks = [{ tweak = \"base\" }]");
+        let main = write(&dir, "main.toml", "include = [\"team/base.toml\"]\ntweaks = [{ tweak = \"main\" }]");
+
+        assert_eq!(tweaks(&main), ["shared", "base", "main"]);
+        let sources: Vec<PathBuf> = Profile::resolve(&main).unwrap().into_iter().map(|(path, _)| path).collect();
+        assert_eq!(sources, [dir.join("team/../shared.toml"), dir.join("team/base.toml"), dir.join("main.toml")]);
+    }
+
+    #[test]
+    fn include_cycles_are_found_by_canonical_path() {
+        let dir = temp_dir();
+        fs::create_dir(dir.join("nested")).unwrap();
+        let first = write(&dir, "first.toml", "include = [\"nested/../second.toml\"]");
+        write(&dir, "second.toml", "include = [\"./first.toml\"]");
+
+        let error = Profile::resolve(&first).unwrap_err().to_string();
+        assert_eq!(error, "profile include cycle: first -> second -> first");
+    }
+
+    #[test]
+    fn later_profiles_override_values() {
+        let dir = temp_dir();
+        let runner = Arc::new(ScriptedRunner::new().always("read-type", CommandOutput::failed(1, "does not exist")));
+        let size = value_tweak("Dock Size", &[&["defaults", "write", "com.apple.dock", "tilesize", "-int", "{}"]]);
+        let app = testing::app(&runner, vec![size, shell_tweak("Show Path Bar", "true")]);
+        write(&dir, "base.toml", "tweaks = [{ tweak = \"dock-size\", value = \"32\" }, { tweak = \"Show Path Bar\" }]");
+        let main = write(&dir, "main.toml", "include = [\"base.toml\"]\ntweaks = [{ tweak = \"Dock Size\", value = \"64\" }]");
+
+        let steps = app.profile_steps(&main).unwrap();
+        let values: Vec<(&str, Option<&str>)> =
+            steps.iter().map(|step| (step.tweak.id.as_str(), step.value.as_deref())).collect();
+        assert_eq!(values, [("test/dock-size", Some("64")), ("test/show-path-bar", None)]);
+    }
+}
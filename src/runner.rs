@@ -0,0 +1,822 @@
+use anyhow::{anyhow, Result};
+use std::collections::VecDeque;
+use std::fmt::Debug;
+use std::io::Read;
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::PathBuf;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::runtime::Runtime;
+use tokio::sync::oneshot;
+
+/// What to run: a shell command line or a program with its arguments.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandLine {
+    /// Run through `zsh -c`.
+    Shell(String),
+    /// Run `program` directly, without a shell.
+    Exec { program: String, args: Vec<String> },
+}
+
+/// A command together with the environment it runs in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandRequest {
+    pub line: CommandLine,
+    /// Extra environment variables on top of the inherited ones.
+    pub env: Vec<(String, String)>,
+    pub cwd: Option<PathBuf>,
+    /// Kill the command if it has not finished after this long.
+    pub timeout: Option<Duration>,
+}
+
+impl CommandRequest {
+    pub fn shell(command: impl Into<String>) -> Self {
+        Self::from_line(CommandLine::Shell(command.into()))
+    }
+
+    pub fn exec<I, S>(program: impl Into<String>, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self::from_line(CommandLine::Exec {
+            program: program.into(),
+            args: args.into_iter().map(Into::into).collect(),
+        })
+    }
+
+    fn from_line(line: CommandLine) -> Self {
+        CommandRequest {
+            line,
+            env: Vec::new(),
+            cwd: None,
+            timeout: None,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env.push((key.into(), value.into()));
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn cwd(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.cwd = Some(dir.into());
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// The command as a user would type it.
+    pub fn display(&self) -> String {
+        match &self.line {
+            CommandLine::Shell(command) => command.clone(),
+            CommandLine::Exec { program, args } => {
+                let parts: Vec<String> = std::iter::once(program).chain(args).map(|arg| shell_quote(arg)).collect();
+                parts.join(" ")
+            }
+        }
+    }
+}
+
+/// `arg` as a POSIX shell word: as it is when no character in it is special
+/// to the shell, otherwise in single quotes with each `'` written as `'\''`.
+fn shell_quote(arg: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "@%+=:,./_-".contains(c);
+    if !arg.is_empty() && arg.chars().all(plain) {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+/// Captured result of a finished command.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommandOutput {
+    /// Exit code, or `None` if the process was killed by a signal.
+    pub status: Option<i32>,
+    /// The signal that killed the process, if any.
+    pub signal: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+    /// The timeout the command was killed for running past.
+    pub timed_out: Option<Duration>,
+    /// Stopped by Ctrl-C or a cancel key.
+    pub canceled: bool,
+}
+
+impl CommandOutput {
+    pub fn success(&self) -> bool {
+        self.status == Some(0)
+    }
+
+    /// Whether the process was canceled or stopped by Ctrl-C.
+    pub fn interrupted(&self) -> bool {
+        self.canceled || self.signal == Some(libc::SIGINT)
+    }
+
+    /// The stdout of a successful command, or an error describing the failure.
+    pub fn into_result(self) -> Result<String> {
+        if let Some(timeout) = self.timed_out {
+            return Err(anyhow!("Command timed out after {}", format_timeout(timeout)));
+        }
+        match self.status {
+            _ if self.canceled => Err(anyhow!("Command was canceled")),
+            Some(0) => Ok(self.stdout),
+            Some(code) if self.stderr.trim().is_empty() => Err(anyhow!("Command failed with exit code {}", code)),
+            Some(_) => Err(anyhow!("Command failed: {}", self.stderr.trim())),
+            None if self.interrupted() => Err(anyhow!("Command was canceled")),
+            None => Err(anyhow!("Command was terminated by a signal")),
+        }
+    }
+
+    /// A successful result with `stdout`, e.g. for [`ScriptedRunner`].
+    pub fn ok(stdout: &str) -> Self {
+        CommandOutput {
+            status: Some(0),
+            stdout: stdout.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// A failed result with exit `code` and `stderr`.
+    #[cfg(test)]
+    pub fn failed(code: i32, stderr: &str) -> Self {
+        CommandOutput {
+            status: Some(code),
+            stderr: stderr.to_string(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Something that happened to a command started with
+/// [`CommandRunner::stream`].
+#[derive(Debug)]
+pub enum StreamEvent {
+    /// A line the command printed, without its line ending.
+    Line { text: String, stderr: bool },
+    /// The command has exited; the output holds everything it printed.
+    Finished(Result<CommandOutput>),
+}
+
+/// A command running in the background. Its output arrives as
+/// [`StreamEvent`]s, ending with [`StreamEvent::Finished`].
+#[derive(Debug)]
+pub struct RunningCommand {
+    events: Receiver<StreamEvent>,
+    cancel: Option<oneshot::Sender<()>>,
+}
+
+impl RunningCommand {
+    /// The events that have arrived since the last call.
+    pub fn poll(&self) -> Vec<StreamEvent> {
+        let mut events = Vec::new();
+        loop {
+            match self.events.try_recv() {
+                Ok(event) => events.push(event),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    if !events.iter().any(|e| matches!(e, StreamEvent::Finished(_))) {
+                        events.push(StreamEvent::Finished(Err(anyhow!("Command stopped reporting"))));
+                    }
+                    break;
+                }
+            }
+        }
+        events
+    }
+
+    /// Kills the command. It still finishes with the output printed so far.
+    pub fn cancel(&mut self) {
+        if let Some(cancel) = self.cancel.take() {
+            let _ = cancel.send(());
+        }
+    }
+
+    /// A command that has already finished with `result`, replayed line by
+    /// line.
+    fn finished(result: Result<CommandOutput>) -> Self {
+        let (sender, events) = mpsc::channel();
+        if let Ok(output) = &result {
+            for (text, stderr) in [(&output.stdout, false), (&output.stderr, true)] {
+                for line in text.lines() {
+                    let _ = sender.send(StreamEvent::Line { text: line.to_string(), stderr });
+                }
+            }
+        }
+        let _ = sender.send(StreamEvent::Finished(result));
+        RunningCommand { events, cancel: None }
+    }
+}
+
+/// Runs external programs on behalf of the app, so callers can be pointed at
+/// a fake instead of the real system.
+pub trait CommandRunner: Send + Sync + Debug {
+    /// Runs the command and captures its output.
+    fn capture(&self, request: &CommandRequest) -> Result<CommandOutput>;
+
+    /// Runs the command attached to the terminal. Only the exit status is
+    /// meaningful in the returned output.
+    fn interactive(&self, request: &CommandRequest) -> Result<CommandOutput>;
+
+    /// Starts the command in the background, streaming its output. Runners
+    /// that cannot do so run it to completion first.
+    fn stream(&self, request: &CommandRequest) -> RunningCommand {
+        RunningCommand::finished(self.capture(request))
+    }
+
+    /// Runs the command and returns its stdout, failing on a non-zero exit.
+    fn run(&self, request: &CommandRequest) -> Result<String> {
+        self.capture(request)?.into_result()
+    }
+
+    /// Runs the command attached to the terminal, failing on a non-zero exit.
+    fn run_interactive(&self, request: &CommandRequest) -> Result<()> {
+        self.interactive(request)?.into_result().map(|_| ())
+    }
+}
+
+/// Runs commands on the local machine.
+#[derive(Debug, Default)]
+pub struct SystemRunner;
+
+impl SystemRunner {
+    fn command(request: &CommandRequest) -> Command {
+        let mut command = match &request.line {
+            CommandLine::Shell(line) => {
+                let mut command = Command::new("zsh");
+                command.arg("-c").arg(line);
+                command
+            }
+            CommandLine::Exec { program, args } => {
+                let mut command = Command::new(program);
+                command.args(args);
+                command
+            }
+        };
+        command.envs(request.env.iter().map(|(k, v)| (k, v)));
+        if let Some(cwd) = &request.cwd {
+            command.current_dir(cwd);
+        }
+        command
+    }
+}
+
+impl CommandRunner for SystemRunner {
+    fn capture(&self, request: &CommandRequest) -> Result<CommandOutput> {
+        let interrupts = INTERRUPTS.load(Ordering::SeqCst);
+        let mut child = Self::command(request)
+            .process_group(0)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let _running = Running::register(child.id());
+        let stdout = read_in_background(child.stdout.take());
+        let stderr = read_in_background(child.stderr.take());
+        let (status, timed_out) = wait(&mut child, request, interrupts)?;
+        Ok(CommandOutput {
+            status: status.code(),
+            signal: status.signal(),
+            stdout: stdout.join().unwrap_or_default(),
+            stderr: stderr.join().unwrap_or_default(),
+            timed_out,
+            canceled: INTERRUPTS.load(Ordering::SeqCst) != interrupts,
+        })
+    }
+
+    fn interactive(&self, request: &CommandRequest) -> Result<CommandOutput> {
+        let interrupts = INTERRUPTS.load(Ordering::SeqCst);
+        let mut child = spawn_in_foreground(&mut Self::command(request))?;
+        let _running = Running::register(child.id());
+        let result = wait(&mut child, request, interrupts);
+        take_back_terminal();
+        let (status, timed_out) = result?;
+        Ok(CommandOutput {
+            status: status.code(),
+            signal: status.signal(),
+            timed_out,
+            canceled: INTERRUPTS.load(Ordering::SeqCst) != interrupts,
+            ..Default::default()
+        })
+    }
+
+    fn stream(&self, request: &CommandRequest) -> RunningCommand {
+        let (sender, events) = mpsc::channel();
+        let (cancel, canceled) = oneshot::channel();
+        let mut command = tokio::process::Command::from(Self::command(request));
+        command
+            .process_group(0)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        let request = request.clone();
+        runtime().spawn(async move {
+            let result = stream_child(command, &request, canceled, &sender).await;
+            let _ = sender.send(StreamEvent::Finished(result));
+        });
+        RunningCommand {
+            events,
+            cancel: Some(cancel),
+        }
+    }
+}
+
+/// The runtime streamed commands run on, started when first needed.
+fn runtime() -> &'static Runtime {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| {
+        tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(2)
+            .enable_all()
+            .build()
+            .expect("failed to start the tokio runtime")
+    })
+}
+
+/// Runs `command`, forwarding its output line by line, until it exits, is
+/// canceled or runs past the request's timeout.
+async fn stream_child(
+    mut command: tokio::process::Command,
+    request: &CommandRequest,
+    canceled_signal: oneshot::Receiver<()>,
+    sender: &Sender<StreamEvent>,
+) -> Result<CommandOutput> {
+    let interrupts = INTERRUPTS.load(Ordering::SeqCst);
+    let mut child = command.spawn()?;
+    let _running = child.id().map(Running::register);
+    let stdout = Arc::new(Mutex::new(String::new()));
+    let stderr = Arc::new(Mutex::new(String::new()));
+    let readers = [
+        forward_lines(child.stdout.take(), false, stdout.clone(), sender.clone()),
+        forward_lines(child.stderr.take(), true, stderr.clone(), sender.clone()),
+    ];
+    let deadline = async {
+        match request.timeout {
+            Some(timeout) => tokio::time::sleep(timeout).await,
+            None => std::future::pending().await,
+        }
+    };
+    // Ctrl-C has already sent SIGTERM; this makes sure the group goes away
+    let interrupted = async {
+        while INTERRUPTS.load(Ordering::SeqCst) == interrupts {
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    };
+
+    let (mut canceled, mut timed_out) = (false, None);
+    let status = tokio::select! {
+        status = child.wait() => Some(status?),
+        // Also reached when the RunningCommand is dropped
+        _ = canceled_signal => {
+            canceled = true;
+            None
+        }
+        _ = deadline => {
+            timed_out = request.timeout;
+            None
+        }
+        _ = interrupted => None,
+    };
+    let status = match status {
+        Some(status) => {
+            for reader in readers {
+                let _ = reader.await;
+            }
+            status
+        }
+        None => {
+            if let Some(group) = child.id() {
+                terminate_group(group);
+            }
+            let status = match tokio::time::timeout(KILL_GRACE, child.wait()).await {
+                Ok(status) => status?,
+                Err(_) => {
+                    // `id` is only `None` once the child has been waited
+                    // for, so its group id cannot have been reused yet
+                    if let Some(group) = child.id() {
+                        kill_group(group);
+                    }
+                    child.wait().await?
+                }
+            };
+            // Anything that left the group may still hold the pipes open
+            for reader in readers {
+                let _ = tokio::time::timeout(Duration::from_millis(200), reader).await;
+            }
+            status
+        }
+    };
+    let take = |text: &Arc<Mutex<String>>| std::mem::take(&mut *text.lock().unwrap());
+    Ok(CommandOutput {
+        status: status.code(),
+        signal: status.signal(),
+        stdout: take(&stdout),
+        stderr: take(&stderr),
+        timed_out,
+        canceled: canceled || INTERRUPTS.load(Ordering::SeqCst) != interrupts,
+    })
+}
+
+/// Reads `pipe` on the runtime, sending each line as it arrives and keeping
+/// all of it in `text`.
+fn forward_lines<R: AsyncRead + Unpin + Send + 'static>(
+    pipe: Option<R>,
+    stderr: bool,
+    text: Arc<Mutex<String>>,
+    sender: Sender<StreamEvent>,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let Some(pipe) = pipe else { return };
+        let mut reader = BufReader::new(pipe);
+        let mut line = Vec::new();
+        while let Ok(read) = reader.read_until(b'\n', &mut line).await {
+            if read == 0 {
+                break;
+            }
+            let chunk = String::from_utf8_lossy(&line).to_string();
+            text.lock().unwrap().push_str(&chunk);
+            let _ = sender.send(StreamEvent::Line {
+                text: chunk.trim_end_matches(['\n', '\r']).to_string(),
+                stderr,
+            });
+            line.clear();
+        }
+    })
+}
+
+fn read_in_background<R: Read + Send + 'static>(pipe: Option<R>) -> thread::JoinHandle<String> {
+    thread::spawn(move || {
+        let mut buffer = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buffer);
+        }
+        String::from_utf8_lossy(&buffer).to_string()
+    })
+}
+
+/// Waits for the child, terminating its process group once the request's
+/// timeout has passed and killing it if the child is still there
+/// [`KILL_GRACE`] after that or after Ctrl-C. Returns the timeout when it
+/// was hit.
+fn wait(child: &mut Child, request: &CommandRequest, interrupts: usize) -> Result<(ExitStatus, Option<Duration>)> {
+    let deadline = request.timeout.map(|timeout| Instant::now() + timeout);
+    let (mut terminated, mut timed_out) = (None, None);
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok((status, timed_out));
+        }
+        let now = Instant::now();
+        match terminated {
+            Some(at) if now >= at + KILL_GRACE => {
+                // Not waited for yet, so its group id cannot have been reused
+                kill_group(child.id());
+                return Ok((child.wait()?, timed_out));
+            }
+            Some(_) => {}
+            None if deadline.is_some_and(|deadline| now >= deadline) => {
+                terminate_group(child.id());
+                (terminated, timed_out) = (Some(now), request.timeout);
+            }
+            // Ctrl-C has already sent SIGTERM to the group
+            None if INTERRUPTS.load(Ordering::SeqCst) != interrupts => terminated = Some(now),
+            None => {}
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Whether this process is in the terminal's foreground process group.
+fn owns_terminal() -> bool {
+    // SAFETY: plain queries on stdin and this process
+    unsafe { libc::isatty(libc::STDIN_FILENO) == 1 && libc::tcgetpgrp(libc::STDIN_FILENO) == libc::getpgrp() }
+}
+
+/// Starts `command` in a process group of its own and, when this process
+/// owns the terminal, hands the terminal to that group. Ctrl-C then reaches
+/// everything the command starts, and a timeout can kill all of it.
+fn spawn_in_foreground(command: &mut Command) -> std::io::Result<Child> {
+    command.process_group(0);
+    if owns_terminal() {
+        // SAFETY: only async-signal-safe calls between fork and exec
+        unsafe {
+            command.pre_exec(|| {
+                // A background group taking the terminal would be stopped
+                libc::signal(libc::SIGTTOU, libc::SIG_IGN);
+                libc::tcsetpgrp(libc::STDIN_FILENO, libc::getpid());
+                libc::signal(libc::SIGTTOU, libc::SIG_DFL);
+                Ok(())
+            });
+        }
+    }
+    command.spawn()
+}
+
+/// Makes this process's group the terminal's foreground group again after
+/// [`spawn_in_foreground`].
+fn take_back_terminal() {
+    // SAFETY: plain calls on stdin and this process; SIGTTOU is ignored
+    // while this process is still in the background
+    unsafe {
+        if libc::isatty(libc::STDIN_FILENO) == 1 {
+            let previous = libc::signal(libc::SIGTTOU, libc::SIG_IGN);
+            libc::tcsetpgrp(libc::STDIN_FILENO, libc::getpgrp());
+            libc::signal(libc::SIGTTOU, previous);
+        }
+    }
+}
+
+/// How long a terminated command has to exit before it is killed.
+const KILL_GRACE: Duration = Duration::from_secs(1);
+
+/// How often a waiting command is checked on.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Times Ctrl-C was pressed while [`handle_ctrl_c`] was in effect.
+static INTERRUPTS: AtomicUsize = AtomicUsize::new(0);
+
+/// Process groups of the commands running now.
+static RUNNING: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+/// Registers a running command's process group for Ctrl-C until dropped.
+struct Running(u32);
+
+impl Running {
+    fn register(group: u32) -> Self {
+        RUNNING.lock().unwrap().push(group);
+        Running(group)
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        let mut running = RUNNING.lock().unwrap();
+        if let Some(index) = running.iter().position(|group| *group == self.0) {
+            running.remove(index);
+        }
+    }
+}
+
+/// Makes Ctrl-C cancel the commands that are running instead of killing
+/// the app, terminating their whole process groups (and killing them if
+/// they are still there a second later); they finish as canceled. With
+/// nothing running, Ctrl-C exits as usual. Commands given
+/// the terminal get Ctrl-C from it directly instead.
+pub fn handle_ctrl_c() -> Result<()> {
+    let _runtime = runtime().enter();
+    let mut interrupts = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::interrupt())?;
+    runtime().spawn(async move {
+        while interrupts.recv().await.is_some() {
+            let running = RUNNING.lock().unwrap();
+            if running.is_empty() {
+                std::process::exit(130);
+            }
+            INTERRUPTS.fetch_add(1, Ordering::SeqCst);
+            for group in running.iter() {
+                terminate_group(*group);
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Asks every process in `group` to exit.
+fn terminate_group(group: u32) {
+    // SAFETY: kill(2) with a negative pid only signals that process group
+    unsafe { libc::kill(-(group as libc::pid_t), libc::SIGTERM) };
+}
+
+/// Kills every process in `group`. Only call this while the group's leader
+/// has not been waited for, so the id still belongs to that group.
+fn kill_group(group: u32) {
+    // SAFETY: kill(2) with a negative pid only signals that process group
+    unsafe { libc::kill(-(group as libc::pid_t), libc::SIGKILL) };
+}
+
+/// A timeout as `90s`, `5m` or `1h 30m`.
+pub fn format_timeout(timeout: Duration) -> String {
+    let secs = timeout.as_secs();
+    match (secs / 3600, secs % 3600 / 60, secs % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, 0) => format!("{}m", m),
+        (0, m, s) => format!("{}m {}s", m, s),
+        (h, 0, _) => format!("{}h", h),
+        (h, m, _) => format!("{}h {}m", h, m),
+    }
+}
+
+/// A command a [`ScriptedRunner`] was asked to run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Invocation {
+    pub request: CommandRequest,
+    pub interactive: bool,
+}
+
+/// Fake runner that records every invocation instead of running it.
+///
+/// Responses are matched in the order they were added: the first one whose
+/// pattern occurs in the displayed command is used, and removed if it was
+/// added with [`ScriptedRunner::once`]. Anything unmatched succeeds with no
+/// output, which also makes it the no-op runner behind dry runs.
+#[derive(Debug, Default)]
+pub struct ScriptedRunner {
+    responses: Mutex<VecDeque<Response>>,
+    invocations: Mutex<Vec<Invocation>>,
+}
+
+#[derive(Debug)]
+struct Response {
+    pattern: String,
+    output: CommandOutput,
+    once: bool,
+}
+
+impl ScriptedRunner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answers every command containing `pattern` with `output`.
+    #[cfg(test)]
+    pub fn always(self, pattern: &str, output: CommandOutput) -> Self {
+        self.push(pattern, output, false)
+    }
+
+    /// Answers the next command containing `pattern` with `output`.
+    #[cfg(test)]
+    pub fn once(self, pattern: &str, output: CommandOutput) -> Self {
+        self.push(pattern, output, true)
+    }
+
+    #[cfg(test)]
+    fn push(self, pattern: &str, output: CommandOutput, once: bool) -> Self {
+        self.responses.lock().unwrap().push_back(Response {
+            pattern: pattern.to_string(),
+            output,
+            once,
+        });
+        self
+    }
+
+    /// Everything run so far, oldest first.
+    #[cfg(test)]
+    pub fn invocations(&self) -> Vec<Invocation> {
+        self.invocations.lock().unwrap().clone()
+    }
+
+    /// Returns and forgets everything run so far.
+    pub fn take_invocations(&self) -> Vec<Invocation> {
+        std::mem::take(&mut *self.invocations.lock().unwrap())
+    }
+
+    fn respond(&self, request: &CommandRequest, interactive: bool) -> CommandOutput {
+        self.invocations.lock().unwrap().push(Invocation {
+            request: request.clone(),
+            interactive,
+        });
+        let command = request.display();
+        let mut responses = self.responses.lock().unwrap();
+        let Some(index) = responses.iter().position(|r| command.contains(&r.pattern)) else {
+            return CommandOutput::ok("");
+        };
+        if responses[index].once {
+            responses.remove(index).map(|r| r.output).unwrap_or_default()
+        } else {
+            responses[index].output.clone()
+        }
+    }
+}
+
+impl CommandRunner for ScriptedRunner {
+    fn capture(&self, request: &CommandRequest) -> Result<CommandOutput> {
+        Ok(self.respond(request, false))
+    }
+
+    fn interactive(&self, request: &CommandRequest) -> Result<CommandOutput> {
+        let mut output = self.respond(request, true);
+        output.stdout.clear();
+        output.stderr.clear();
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::CliError;
+
+    /// `script` run by `sh` in a process group of its own, timing out after
+    /// `timeout`.
+    fn script(script: &str, timeout: Duration) -> CommandRequest {
+        CommandRequest::exec("sh", ["-c", script]).timeout(timeout)
+    }
+
+    /// Whether process `pid` is gone, or only waiting to be reaped, within
+    /// a second.
+    fn is_gone(pid: &str) -> bool {
+        let started = Instant::now();
+        while started.elapsed() < Duration::from_secs(1) {
+            let state = Command::new("ps").args(["-o", "stat=", "-p", pid]).output().unwrap();
+            let state = String::from_utf8_lossy(&state.stdout);
+            if state.trim().is_empty() || state.trim().starts_with('Z') {
+                return true;
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+        false
+    }
+
+    /// Polls `running` until it finishes, returning the lines it printed.
+    fn finish(running: &mut RunningCommand, mut on_line: impl FnMut(&mut RunningCommand)) -> (Vec<String>, CommandOutput) {
+        let started = Instant::now();
+        let mut lines = Vec::new();
+        while started.elapsed() < Duration::from_secs(10) {
+            for event in running.poll() {
+                match event {
+                    StreamEvent::Line { text, .. } => {
+                        lines.push(text);
+                        on_line(running);
+                    }
+                    StreamEvent::Finished(result) => return (lines, result.unwrap()),
+                }
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+        panic!("the command did not finish; printed {:?}", lines);
+    }
+
+    #[test]
+    fn timeout_kills_the_whole_group() {
+        let started = Instant::now();
+        let request = script("sleep 30 & echo $!; wait", Duration::from_millis(300));
+        let output = SystemRunner.capture(&request).unwrap();
+
+        assert!(started.elapsed() < Duration::from_secs(5), "took {:?}", started.elapsed());
+        assert_eq!(output.timed_out, Some(Duration::from_millis(300)));
+        assert_eq!(output.signal, Some(libc::SIGTERM));
+        assert!(is_gone(output.stdout.trim()), "sleep {} survived", output.stdout.trim());
+        assert_eq!(CliError::check_command(Ok(output)).unwrap_err().exit_code(), 124);
+    }
+
+    #[test]
+    fn timeout_kills_a_command_that_ignores_sigterm() {
+        let started = Instant::now();
+        let request = script("trap '' TERM; echo ready; while :; do sleep 0.1; done", Duration::from_millis(200));
+        let output = SystemRunner.capture(&request).unwrap();
+
+        assert!(started.elapsed() >= Duration::from_millis(200) + KILL_GRACE);
+        assert!(started.elapsed() < Duration::from_secs(5), "took {:?}", started.elapsed());
+        assert_eq!(output.signal, Some(libc::SIGKILL));
+        assert_eq!(output.stdout, "ready\n");
+        assert!(output.timed_out.is_some());
+    }
+
+    #[test]
+    fn streamed_timeout_keeps_the_output_so_far() {
+        let request = script("echo one; echo two; echo oops >&2; sleep 30", Duration::from_millis(300));
+        let (mut lines, output) = finish(&mut SystemRunner.stream(&request), |_| {});
+
+        // stdout and stderr are read separately, so only each keeps its order
+        lines.sort();
+        assert_eq!(lines, ["one", "oops", "two"]);
+        assert_eq!((output.stdout.as_str(), output.stderr.as_str()), ("one\ntwo\n", "oops\n"));
+        assert_eq!(output.timed_out, Some(Duration::from_millis(300)));
+        assert_eq!(CliError::check_command(Ok(output)).unwrap_err().exit_code(), 124);
+    }
+
+    #[test]
+    fn canceled_stream_kills_the_whole_group() {
+        let request = CommandRequest::exec("sh", ["-c", "sleep 30 & echo $!; wait"]);
+        let (lines, output) = finish(&mut SystemRunner.stream(&request), RunningCommand::cancel);
+
+        assert!(output.canceled);
+        assert_eq!(output.timed_out, None);
+        assert!(is_gone(&lines[0]), "sleep {} survived", lines[0]);
+        assert_eq!(CliError::check_command(Ok(output)).unwrap_err().exit_code(), 130);
+    }
+
+    #[test]
+    fn display_quotes_arguments_for_the_shell() {
+        let request = CommandRequest::exec(
+            "defaults",
+            ["write", "com.apple.screencapture", "location", "~/My Shots", "it's", "$HOME;rm", "*", "", "-int", "48"],
+        );
+        assert_eq!(
+            request.display(),
+            r#"defaults write com.apple.screencapture location '~/My Shots' 'it'\''s' '$HOME;rm' '*' '' -int 48"#
+        );
+        assert_eq!(CommandRequest::exec("brew", ["info", "--json=v2", "homebrew/cask/font-fira-code"]).display(), "brew info --json=v2 homebrew/cask/font-fira-code");
+        assert_eq!(CommandRequest::shell("killall Dock && echo $HOME").display(), "killall Dock && echo $HOME");
+    }
+}
@@ -0,0 +1,177 @@
+use crate::tweaks::{NodeId, TweakTree};
+
+/// A tweak matching a search query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchHit {
+    pub node: NodeId,
+    pub score: i64,
+    /// Character positions in the tweak's name that matched, for
+    /// highlighting.
+    pub name_matches: Vec<usize>,
+}
+
+/// Tweaks whose name fuzzy-matches every word of `query`, or whose
+/// description contains it, best matches first: a name equal to a word,
+/// then names starting with it, then names with it at the start of a later
+/// word, then looser matches. Names count for more than descriptions; ties
+/// keep the catalog order.
+pub fn search(tree: &TweakTree, query: &str) -> Vec<SearchHit> {
+    let terms: Vec<Vec<char>> = query.split_whitespace().map(lowercase).collect();
+    if terms.is_empty() {
+        return Vec::new();
+    }
+    let mut hits: Vec<SearchHit> = tree
+        .walk()
+        .into_iter()
+        .filter_map(|id| {
+            let tweak = tree.node(id).tweak()?;
+            let name: Vec<char> = tweak.name.chars().collect();
+            let description = lowercase(&tweak.description);
+            let mut hit = SearchHit {
+                node: id,
+                score: 0,
+                name_matches: Vec::new(),
+            };
+            for term in &terms {
+                let in_name = fuzzy_match(term, &name);
+                let in_description = contains(&description, term).then_some(term.len() as i64 * DESCRIPTION_CHAR);
+                match in_name {
+                    Some((score, positions)) if in_description.is_none_or(|d| score >= d) => {
+                        hit.score += score;
+                        hit.name_matches.extend(positions);
+                    }
+                    _ => hit.score += in_description?,
+                }
+            }
+            hit.name_matches.sort_unstable();
+            hit.name_matches.dedup();
+            Some(hit)
+        })
+        .collect();
+    hits.sort_by_key(|hit| -hit.score);
+    hits
+}
+
+const MATCHED_CHAR: i64 = 10;
+const CONSECUTIVE_BONUS: i64 = 15;
+const WORD_START_BONUS: i64 = 10;
+/// For a match that starts at the beginning of the name.
+const NAME_START_BONUS: i64 = 15;
+/// For a match that covers the whole name.
+const EXACT_BONUS: i64 = 20;
+/// Per character of a description that contains the term; below a name
+/// match of the same length, so names rank first.
+const DESCRIPTION_CHAR: i64 = 8;
+
+/// Scores `term` (lowercase) as a subsequence of `text`, rewarding runs of
+/// consecutive characters and matches at the start of words and penalising
+/// gaps. Every starting position is tried and the best is kept. Returns
+/// `None` if the characters of `term` do not all appear in order.
+fn fuzzy_match(term: &[char], text: &[char]) -> Option<(i64, Vec<usize>)> {
+    let lower = lowercase_chars(text);
+    let first = *term.first()?;
+    (0..lower.len())
+        .filter(|&start| lower[start] == first)
+        .filter_map(|start| {
+            let mut positions = vec![start];
+            let mut next = start + 1;
+            for &c in &term[1..] {
+                let offset = lower[next..].iter().position(|&t| t == c)?;
+                positions.push(next + offset);
+                next += offset + 1;
+            }
+            Some((score(text, &positions), positions))
+        })
+        .max_by_key(|(score, positions)| (*score, std::cmp::Reverse(positions[0])))
+}
+
+fn score(text: &[char], positions: &[usize]) -> i64 {
+    let mut score = 0;
+    if positions.first() == Some(&0) {
+        score += NAME_START_BONUS;
+    }
+    if positions.len() == text.len() {
+        score += EXACT_BONUS;
+    }
+    for (i, &position) in positions.iter().enumerate() {
+        score += MATCHED_CHAR;
+        if is_word_start(text, position) {
+            score += WORD_START_BONUS;
+        }
+        if i > 0 {
+            let gap = position - positions[i - 1] - 1;
+            if gap == 0 {
+                score += CONSECUTIVE_BONUS;
+            } else {
+                score -= gap.min(10) as i64;
+            }
+        }
+    }
+    score
+}
+
+fn is_word_start(text: &[char], position: usize) -> bool {
+    position == 0 || !text[position - 1].is_alphanumeric() || (text[position - 1].is_lowercase() && text[position].is_uppercase())
+}
+
+fn contains(text: &[char], term: &[char]) -> bool {
+    text.windows(term.len()).any(|window| window == term)
+}
+
+fn lowercase(text: &str) -> Vec<char> {
+    lowercase_chars(&text.chars().collect::<Vec<_>>())
+}
+
+/// Lowercases character by character, keeping positions aligned with the
+/// original text.
+fn lowercase_chars(text: &[char]) -> Vec<char> {
+    text.iter().map(|c| c.to_lowercase().next().unwrap_or(*c)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::shell_tweak;
+    use crate::tweaks::NodeKind;
+
+    fn tree(names: &[&str]) -> TweakTree {
+        let mut tree = TweakTree::default();
+        let category = tree.add(None, "test", "Test", NodeKind::Category);
+        for name in names {
+            let tweak = shell_tweak(name, "true");
+            tree.add(Some(category), &name.to_lowercase().replace(' ', "-"), name, NodeKind::Tweak(Box::new(tweak)));
+        }
+        tree
+    }
+
+    fn names(tree: &TweakTree, query: &str) -> Vec<String> {
+        search(tree, query).iter().map(|hit| tree.node(hit.node).name.clone()).collect()
+    }
+
+    #[test]
+    fn ranks_exact_then_prefix_then_word_start_then_subsequence() {
+        let tree = tree(&["Dismiss Zoom Effect", "Dock Size", "Size Up", "Size"]);
+        assert_eq!(names(&tree, "size"), ["Size", "Size Up", "Dock Size", "Dismiss Zoom Effect"]);
+    }
+
+    #[test]
+    fn matching_ignores_case() {
+        let tree = tree(&["Show Path Bar"]);
+        assert_eq!(names(&tree, "PATH bar"), ["Show Path Bar"]);
+        assert_eq!(search(&tree, "spb")[0].name_matches, [0, 5, 10]);
+    }
+
+    #[test]
+    fn drops_tweaks_that_do_not_match() {
+        let tree = tree(&["Autohide", "Show Path Bar", "Dock Size"]);
+        assert_eq!(names(&tree, "path"), ["Show Path Bar"]);
+        assert_eq!(names(&tree, "path size"), Vec::<String>::new());
+        assert!(search(&tree, "  ").is_empty());
+    }
+
+    #[test]
+    fn ties_keep_catalog_order() {
+        let tree = tree(&["Icon Size", "Dock Size", "Font Size"]);
+        assert_eq!(names(&tree, "size"), ["Icon Size", "Dock Size", "Font Size"]);
+    }
+}
@@ -0,0 +1,409 @@
+use crate::batch;
+use crate::config::Config;
+use crate::runner::{CommandRequest, CommandRunner};
+use crate::tweaks::TweakAction;
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// How long a single `defaults` read may take while snapshotting.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A key written with `defaults write` or removed with `defaults delete`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DefaultsKey {
+    pub domain: String,
+    pub key: String,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub current_host: bool,
+    /// Written with `sudo`, so restoring it needs `sudo` too.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub sudo: bool,
+}
+
+impl DefaultsKey {
+    fn args(&self, verb: &str) -> Vec<String> {
+        let mut args = Vec::new();
+        if self.current_host {
+            args.push("-currentHost".to_string());
+        }
+        args.extend([verb.to_string(), self.domain.clone(), self.key.clone()]);
+        args
+    }
+
+    /// `defaults <verb> <domain> <key> <extra>`, under `sudo` if the key
+    /// was written with it.
+    fn request(&self, verb: &str, extra: &[String]) -> CommandRequest {
+        let mut args = self.args(verb);
+        args.extend_from_slice(extra);
+        if self.sudo {
+            args.insert(0, "defaults".to_string());
+            CommandRequest::exec("sudo", args)
+        } else {
+            CommandRequest::exec("defaults", args)
+        }
+    }
+}
+
+/// The value a key held before a tweak was applied.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum SavedValue {
+    /// The key was not set.
+    Absent,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+    /// A type that cannot be written back with `defaults write`, such as a
+    /// dictionary or array.
+    Unsupported(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedKey {
+    #[serde(flatten)]
+    pub key: DefaultsKey,
+    pub previous: SavedValue,
+}
+
+/// The prior state of every key a tweak writes, taken before it is applied.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub taken_at: DateTime<Utc>,
+    pub keys: Vec<SavedKey>,
+    /// Processes the tweak restarts, restarted again after restoring.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub restarts: Vec<String>,
+}
+
+impl Snapshot {
+    /// Reads the current value of each key in `keys`.
+    pub fn take(runner: &dyn CommandRunner, keys: &[DefaultsKey], restarts: Vec<String>) -> Result<Self> {
+        let keys = keys
+            .iter()
+            .map(|key| {
+                Ok(SavedKey {
+                    key: key.clone(),
+                    previous: read_saved_value(runner, key)?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Snapshot {
+            taken_at: Utc::now(),
+            keys,
+            restarts,
+        })
+    }
+
+    /// Commands that put every key back the way it was, followed by the
+    /// restarts. Fails if any key held a value that cannot be written back.
+    pub fn restore_requests(&self) -> Result<Vec<CommandRequest>, String> {
+        let mut requests = Vec::new();
+        for saved in &self.keys {
+            let key = &saved.key;
+            let (flag, value) = match &saved.previous {
+                SavedValue::Absent => {
+                    requests.push(key.request("delete", &[]));
+                    continue;
+                }
+                SavedValue::Bool(value) => ("-bool", value.to_string()),
+                SavedValue::Int(value) => ("-int", value.to_string()),
+                SavedValue::Float(value) => ("-float", value.to_string()),
+                SavedValue::String(value) => ("-string", value.clone()),
+                SavedValue::Unsupported(kind) => {
+                    return Err(format!("{} {} held a {} value, which cannot be restored", key.domain, key.key, kind));
+                }
+            };
+            requests.push(key.request("write", &[flag.to_string(), value]));
+        }
+        requests.extend(self.restarts.iter().map(|process| CommandRequest::exec("killall", [process.as_str()])));
+        Ok(requests)
+    }
+}
+
+fn read_saved_value(runner: &dyn CommandRunner, key: &DefaultsKey) -> Result<SavedValue> {
+    let output = runner
+        .capture(&CommandRequest::exec("defaults", key.args("read-type")).timeout(READ_TIMEOUT))
+        .with_context(|| format!("could not read {} {}", key.domain, key.key))?;
+    if !output.success() {
+        if output.stderr.contains("does not exist") {
+            return Ok(SavedValue::Absent);
+        }
+        return Err(anyhow!("could not read {} {}: {}", key.domain, key.key, output.stderr.trim()));
+    }
+    let kind = output.stdout.trim().trim_start_matches("Type is ").to_string();
+    if !matches!(kind.as_str(), "boolean" | "integer" | "float" | "string") {
+        return Ok(SavedValue::Unsupported(kind));
+    }
+
+    let value = runner
+        .capture(&CommandRequest::exec("defaults", key.args("read")).timeout(READ_TIMEOUT))?
+        .into_result()?;
+    let value = value.strip_suffix('\n').unwrap_or(&value);
+    let parsed = match kind.as_str() {
+        "boolean" => value.trim().parse::<i64>().ok().map(|v| SavedValue::Bool(v != 0)),
+        "integer" => value.trim().parse().ok().map(SavedValue::Int),
+        "float" => value.trim().parse().ok().map(SavedValue::Float),
+        _ => Some(SavedValue::String(value.to_string())),
+    };
+    parsed.ok_or_else(|| anyhow!("unexpected {} value for {} {}: {}", kind, key.domain, key.key, value))
+}
+
+/// The `defaults` keys an action writes or deletes. Whole-domain deletes
+/// and writes to other hosts are not included.
+pub fn written_keys(action: &TweakAction) -> Vec<DefaultsKey> {
+    let mut keys: Vec<DefaultsKey> = Vec::new();
+    for words in command_words(action) {
+        let mut words = words.iter().map(String::as_str).peekable();
+        let sudo = words.next_if_eq(&"sudo").is_some();
+        if words.next() != Some("defaults") {
+            continue;
+        }
+        let mut current_host = false;
+        let mut verb = words.next();
+        if verb == Some("-currentHost") {
+            current_host = true;
+            verb = words.next();
+        }
+        if !matches!(verb, Some("write") | Some("delete")) {
+            continue;
+        }
+        let (Some(domain), Some(key)) = (words.next(), words.next()) else {
+            continue;
+        };
+        let domain = match domain {
+            "-g" | "-globalDomain" => "NSGlobalDomain",
+            other => other,
+        };
+        let key = DefaultsKey {
+            domain: domain.to_string(),
+            key: key.to_string(),
+            current_host,
+            sudo,
+        };
+        if !keys.contains(&key) {
+            keys.push(key);
+        }
+    }
+    keys
+}
+
+/// Processes an action restarts with a plain `killall`.
+pub fn restarts(action: &TweakAction) -> Vec<String> {
+    let mut processes: Vec<String> = Vec::new();
+    for words in command_words(action) {
+        let Some(("killall", args)) = words.split_first().map(|(first, rest)| (first.as_str(), rest)) else {
+            continue;
+        };
+        for process in batch::restart_targets(args).unwrap_or_default() {
+            if !processes.contains(&process) {
+                processes.push(process);
+            }
+        }
+    }
+    processes
+}
+
+/// The simple commands an action runs, split into words.
+fn command_words(action: &TweakAction) -> Vec<Vec<String>> {
+    match action {
+        TweakAction::Shell { command } => split_shell(command),
+        TweakAction::PromptThenRun { run, .. } => run.clone(),
+        TweakAction::Builtin { .. } | TweakAction::OpenList { .. } => Vec::new(),
+    }
+}
+
+/// Splits a shell command line into its simple commands (separated by `&&`,
+/// `||`, `;` or `|`) and those into words, honouring quotes. Enough for the
+/// one-liners in the catalog, not a full shell parser.
+fn split_shell(line: &str) -> Vec<Vec<String>> {
+    let mut commands = Vec::new();
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                in_word = true;
+                word.extend(chars.by_ref().take_while(|&c| c != '\''));
+            }
+            '"' => {
+                in_word = true;
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => word.extend(chars.next()),
+                        c => word.push(c),
+                    }
+                }
+            }
+            '\\' => {
+                in_word = true;
+                word.extend(chars.next());
+            }
+            ';' | '&' | '|' | '\n' => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+                if !words.is_empty() {
+                    commands.push(std::mem::take(&mut words));
+                }
+            }
+            c if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            c => {
+                in_word = true;
+                word.push(c);
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    if !words.is_empty() {
+        commands.push(words);
+    }
+    commands
+}
+
+/// Snapshots of tweaks that have been applied and not yet reverted, keyed by
+/// tweak id and stored as JSON.
+#[derive(Debug, Clone)]
+pub struct SnapshotStore {
+    path: PathBuf,
+}
+
+impl SnapshotStore {
+    pub fn new(path: PathBuf) -> Self {
+        SnapshotStore { path }
+    }
+
+    pub fn default_path() -> PathBuf {
+        Config::config_dir().join("snapshots.json")
+    }
+
+    fn load(&self) -> Result<BTreeMap<String, Snapshot>> {
+        match fs::read_to_string(&self.path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .with_context(|| format!("{}: invalid snapshot file", self.path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+            Err(e) => Err(e).with_context(|| format!("{}: failed to read snapshots", self.path.display())),
+        }
+    }
+
+    fn save(&self, snapshots: &BTreeMap<String, Snapshot>) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&self.path, serde_json::to_string_pretty(snapshots)?)
+            .with_context(|| format!("{}: failed to write snapshots", self.path.display()))
+    }
+
+    pub fn get(&self, tweak_id: &str) -> Result<Option<Snapshot>> {
+        Ok(self.load()?.remove(tweak_id))
+    }
+
+    /// Stores `snapshot` unless one is already held for the tweak, so that
+    /// applying a tweak twice still reverts to the state before the first.
+    pub fn insert_if_absent(&self, tweak_id: &str, snapshot: Snapshot) -> Result<()> {
+        let mut snapshots = self.load()?;
+        if snapshots.contains_key(tweak_id) {
+            return Ok(());
+        }
+        snapshots.insert(tweak_id.to_string(), snapshot);
+        self.save(&snapshots)
+    }
+
+    pub fn remove(&self, tweak_id: &str) -> Result<()> {
+        let mut snapshots = self.load()?;
+        if snapshots.remove(tweak_id).is_some() {
+            self.save(&snapshots)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shell(command: &str) -> TweakAction {
+        TweakAction::Shell { command: command.to_string() }
+    }
+
+    fn key(domain: &str, key: &str) -> DefaultsKey {
+        DefaultsKey { domain: domain.to_string(), key: key.to_string(), current_host: false, sudo: false }
+    }
+
+    #[test]
+    fn splits_chains_into_commands() {
+        assert_eq!(
+            split_shell("defaults write a b -bool true && killall Dock; echo done || true | cat"),
+            [
+                vec!["defaults", "write", "a", "b", "-bool", "true"],
+                vec!["killall", "Dock"],
+                vec!["echo", "done"],
+                vec!["true"],
+                vec!["cat"],
+            ]
+        );
+    }
+
+    #[test]
+    fn split_honours_quotes_and_escapes() {
+        assert_eq!(
+            split_shell(r#"defaults write com.apple.dock persistent-apps -array-add '{"tile-type"="spacer-tile";}'"#),
+            [vec!["defaults", "write", "com.apple.dock", "persistent-apps", "-array-add", r#"{"tile-type"="spacer-tile";}"#]]
+        );
+        assert_eq!(split_shell(r#"echo "a \"b\" && c" d\ e ''"#), [vec!["echo", r#"a "b" && c"#, "d e", ""]]);
+    }
+
+    #[test]
+    fn finds_written_and_deleted_keys() {
+        let action = shell(
+            "defaults write com.apple.finder ShowPathbar -bool true && defaults delete -g AppleShowScrollBars; killall Finder",
+        );
+        assert_eq!(
+            written_keys(&action),
+            [key("com.apple.finder", "ShowPathbar"), key("NSGlobalDomain", "AppleShowScrollBars")]
+        );
+    }
+
+    #[test]
+    fn written_keys_note_current_host_and_sudo() {
+        let action = shell("defaults -currentHost write com.apple.screensaver idleTime -int 0 && sudo defaults write /Library/Preferences/com.apple.loginwindow GuestEnabled -bool false");
+        let keys = written_keys(&action);
+        assert_eq!(keys.len(), 2);
+        assert!(keys[0].current_host && !keys[0].sudo);
+        assert_eq!(keys[0].key, "idleTime");
+        assert!(keys[1].sudo && !keys[1].current_host);
+        assert_eq!(keys[1].domain, "/Library/Preferences/com.apple.loginwindow");
+    }
+
+    #[test]
+    fn written_keys_ignore_other_commands() {
+        let action = shell("defaults read com.apple.dock tilesize; defaults delete com.apple.dock; echo defaults write a b; chflags nohidden ~/Library");
+        assert!(written_keys(&action).is_empty());
+
+        let run = TweakAction::PromptThenRun {
+            prompt: "Size".to_string(),
+            param: crate::param::Param::String,
+            run: vec![
+                vec!["defaults".to_string(), "write".to_string(), "com.apple.dock".to_string(), "tilesize".to_string()],
+                vec!["killall".to_string(), "Dock".to_string()],
+            ],
+        };
+        assert_eq!(written_keys(&run), [key("com.apple.dock", "tilesize")]);
+    }
+}
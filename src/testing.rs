@@ -0,0 +1,107 @@
+//! Helpers for tests that drive the app against a [`ScriptedRunner`].
+
+use crate::app::App;
+use crate::config::Config;
+use crate::history::History;
+use crate::param::Param;
+use crate::runner::ScriptedRunner;
+use crate::snapshot::SnapshotStore;
+use crate::tweaks::{NodeId, NodeKind, Tweak, TweakAction, TweakKind, TweakTree};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// A new, empty directory of its own for each call.
+pub fn temp_dir() -> PathBuf {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let dir = std::env::temp_dir()
+        .join("macos-tweaks-tests")
+        .join(format!("{}-{}", std::process::id(), COUNT.fetch_add(1, Ordering::SeqCst)));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).expect("failed to create a temporary directory");
+    dir
+}
+
+/// A setting that runs `command` through the shell, with no flags set.
+pub fn shell_tweak(name: &str, command: &str) -> Tweak {
+    tweak(name, TweakAction::Shell { command: command.to_string() })
+}
+
+/// A setting that asks for a number from 16 to 128 and passes it to each
+/// of the `run` commands.
+pub fn value_tweak(name: &str, run: &[&[&str]]) -> Tweak {
+    tweak(
+        name,
+        TweakAction::PromptThenRun {
+            prompt: format!("{}?", name),
+            param: Param::Int { min: 16, max: 128, step: None },
+            run: run.iter().map(|argv| argv.iter().map(|arg| arg.to_string()).collect()).collect(),
+        },
+    )
+}
+
+pub fn tweak(name: &str, action: TweakAction) -> Tweak {
+    Tweak {
+        id: String::new(),
+        name: name.to_string(),
+        description: format!("{} for tests", name),
+        action,
+        disable_command: String::new(),
+        kind: TweakKind::Setting,
+        destructive: false,
+        requires_root: false,
+        interactive: false,
+        repeatable: false,
+        probe: None,
+        timeout_secs: None,
+    }
+}
+
+/// An app whose catalog is `tweaks` in a `test` category, running commands
+/// through `runner` and keeping its history and snapshots in a temporary
+/// directory. Tweak ids are `test/<slug of the name>`.
+pub fn app(runner: &Arc<ScriptedRunner>, tweaks: Vec<Tweak>) -> App {
+    let mut tree = TweakTree::default();
+    let category = tree.add(None, "test", "Test", NodeKind::Category);
+    add_tweaks(&mut tree, category, tweaks);
+    app_with_tree(runner, tree)
+}
+
+/// Like [`app`], with the tweaks in groups of the `test` category, named by
+/// the first of each pair. Tweak ids are `test/<group>/<slug of the name>`.
+pub fn grouped_app(runner: &Arc<ScriptedRunner>, groups: Vec<(&str, Vec<Tweak>)>) -> App {
+    let mut tree = TweakTree::default();
+    let category = tree.add(None, "test", "Test", NodeKind::Category);
+    for (name, tweaks) in groups {
+        let group = tree.add(Some(category), name, name, NodeKind::Group);
+        add_tweaks(&mut tree, group, tweaks);
+    }
+    app_with_tree(runner, tree)
+}
+
+fn add_tweaks(tree: &mut TweakTree, parent: NodeId, tweaks: Vec<Tweak>) {
+    for tweak in tweaks {
+        let slug = tweak.name.to_ascii_lowercase().replace(' ', "-");
+        let name = tweak.name.clone();
+        tree.add(Some(parent), &slug, &name, NodeKind::Tweak(Box::new(tweak)));
+    }
+}
+
+fn app_with_tree(runner: &Arc<ScriptedRunner>, tree: TweakTree) -> App {
+    let dir = temp_dir();
+    App::with_state(
+        runner.clone(),
+        Config::default(),
+        tree,
+        History::new(dir.join("history.jsonl")),
+        SnapshotStore::new(dir.join("snapshots.json")),
+    )
+}
+
+/// Highlights the tweak called `name` in the tree, as if navigated to.
+pub fn select(app: &mut App, name: &str) -> Tweak {
+    let tweak = app.find_tweak_by_name(name).expect("no such tweak");
+    let node = app.tree.find_by_id(&tweak.id).expect("tweak is not in the tree");
+    app.jump_to(node);
+    tweak
+}
@@ -1,28 +1,373 @@
+use crate::brew::{self, Package, PackageKind};
+use crate::param::Param;
+use crate::runner::CommandRequest;
+use anyhow::{bail, Result};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Tweak {
+    pub id: String,
     pub name: String,
     pub description: String,
-    pub enable_command: String,
+    pub action: TweakAction,
     pub disable_command: String,
-    pub is_enabled: bool,
+    pub kind: TweakKind,
+    /// Deletes data or resets state; needs explicit confirmation.
+    pub destructive: bool,
+    /// Runs commands through `sudo`.
+    pub requires_root: bool,
+    /// Needs the terminal for its own prompts or output.
+    pub interactive: bool,
+    /// Applying it again has a further effect, e.g. adds another Dock spacer.
+    pub repeatable: bool,
+    /// How to tell whether the setting is currently in effect.
+    pub probe: Option<Probe>,
+    /// Seconds each command may run before it is killed, overriding the
+    /// configured default; 0 for no limit.
+    pub timeout_secs: Option<u64>,
 }
 
 impl Tweak {
-    pub fn new(
-        name: &str,
-        description: &str,
-        enable_command: &str,
-        disable_command: &str,
-        is_enabled: bool,
-    ) -> Self {
-        Tweak {
+    /// Whether the command needs the terminal handed over to it.
+    pub fn runs_interactively(&self) -> bool {
+        self.requires_root || self.interactive
+    }
+
+    /// Whether a successful run should be remembered as applied.
+    pub fn tracks_applied(&self) -> bool {
+        self.kind != TweakKind::Query && !self.repeatable
+    }
+
+    /// Short comma-separated list of the safety-relevant flags, if any.
+    pub fn flags_label(&self) -> Option<String> {
+        join_flags(&[
+            (self.destructive, "destructive"),
+            (self.requires_root, "sudo"),
+            (self.interactive, "interactive"),
+        ])
+    }
+}
+
+/// Joins the labels of the flags that are set, or `None` if none are.
+pub fn join_flags(flags: &[(bool, &str)]) -> Option<String> {
+    let labels: Vec<&str> = flags.iter().filter(|(set, _)| *set).map(|(_, label)| *label).collect();
+    if labels.is_empty() {
+        None
+    } else {
+        Some(labels.join(", "))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TweakKind {
+    /// Changes a persistent setting.
+    Setting,
+    /// Performs a one-off operation.
+    Action,
+    /// Only reports information.
+    Query,
+}
+
+impl TweakKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            TweakKind::Setting => "setting",
+            TweakKind::Action => "action",
+            TweakKind::Query => "query",
+        }
+    }
+}
+
+/// A `defaults` key whose value shows whether a setting is in effect.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Probe {
+    pub domain: String,
+    pub key: String,
+    /// Value `defaults read` prints while the setting is in effect. Left out
+    /// for tweaks that take a value, where the current value is shown instead.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected: Option<String>,
+    /// Value macOS uses while the key is unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<String>,
+    /// Read with `defaults -currentHost`.
+    #[serde(default)]
+    pub current_host: bool,
+}
+
+/// What happens when a tweak is applied.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum TweakAction {
+    /// Run a shell command.
+    Shell { command: String },
+    /// Run something implemented by the app itself.
+    Builtin { builtin: BuiltinKind },
+    /// Ask for a value of type `param`, substitute it for `{}` in the
+    /// arguments of each `run` command and run them in order without a shell.
+    /// The TUI shows a picker for numbers, durations and enums.
+    PromptThenRun {
+        prompt: String,
+        param: Param,
+        run: Vec<Vec<String>>,
+    },
+    /// Show the packages listed by `source` and act on the chosen one.
+    OpenList { source: ListSource, on_select: ListSelectAction },
+}
+
+impl TweakAction {
+    /// The value the action asks for, if any.
+    pub fn param(&self) -> Option<&Param> {
+        match self {
+            TweakAction::PromptThenRun { param, .. } => Some(param),
+            _ => None,
+        }
+    }
+
+    /// Whether the action can run from the command line, as opposed to
+    /// needing the TUI for a game. Values are given with `--value`.
+    pub fn runs_in_cli(&self) -> bool {
+        match self {
+            TweakAction::Shell { .. } => true,
+            TweakAction::Builtin { builtin } => match builtin {
+                BuiltinKind::ShowVersion | BuiltinKind::CheckBrew => true,
+                BuiltinKind::Sokoban => false,
+            },
+            TweakAction::PromptThenRun { .. } => true,
+            TweakAction::OpenList { source, .. } => !source.searches(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BuiltinKind {
+    ShowVersion,
+    Sokoban,
+    CheckBrew,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+// The names are the catalog's values, e.g. `brew_search`
+#[allow(clippy::enum_variant_names)]
+pub enum ListSource {
+    BrewInstalled,
+    BrewOutdated,
+    /// Formulae and casks matching what the user types, found with
+    /// `brew search`.
+    BrewSearch,
+}
+
+impl ListSource {
+    /// The command listing the packages, or `None` for lists filled by
+    /// searching.
+    pub fn command(&self) -> Option<&'static str> {
+        match self {
+            ListSource::BrewInstalled => Some("brew info --json=v2 --installed"),
+            ListSource::BrewOutdated => Some("brew outdated --json=v2"),
+            ListSource::BrewSearch => None,
+        }
+    }
+
+    /// Whether the list starts empty and is filled by searching.
+    pub fn searches(&self) -> bool {
+        self.command().is_none()
+    }
+
+    /// Reads the packages out of what [`ListSource::command`] printed.
+    pub fn parse(&self, output: &str) -> Result<Vec<Package>> {
+        match self {
+            ListSource::BrewInstalled => brew::parse_installed(output),
+            ListSource::BrewOutdated => brew::parse_outdated(output),
+            ListSource::BrewSearch => bail!("search results are not listed by a command"),
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            ListSource::BrewInstalled => "Installed Packages",
+            ListSource::BrewOutdated => "Outdated Packages",
+            ListSource::BrewSearch => "Search Packages",
+        }
+    }
+
+    pub fn empty_message(&self) -> &'static str {
+        match self {
+            ListSource::BrewInstalled => "No installed Homebrew packages found.",
+            ListSource::BrewOutdated => "All Homebrew packages are up to date.",
+            ListSource::BrewSearch => "No formulae or casks found.",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[allow(clippy::enum_variant_names)]
+pub enum ListSelectAction {
+    BrewInfo,
+    BrewUpgrade,
+    /// Install the chosen packages; `u` uninstalls them instead.
+    BrewInstall,
+}
+
+impl ListSelectAction {
+    /// The command run for a package of `kind` called `name`.
+    pub fn command_for(&self, kind: PackageKind, name: &str) -> CommandRequest {
+        let verb = match self {
+            ListSelectAction::BrewInfo => "info",
+            ListSelectAction::BrewUpgrade => "upgrade",
+            ListSelectAction::BrewInstall => "install",
+        };
+        match kind {
+            PackageKind::Formula => CommandRequest::exec("brew", [verb, name]),
+            PackageKind::Cask => CommandRequest::exec("brew", [verb, "--cask", name]),
+        }
+    }
+
+    /// Keys the list responds to, shown below it.
+    pub fn hint(&self) -> &'static str {
+        match self {
+            ListSelectAction::BrewInfo => "↑↓ to select, Enter for info, Esc to close",
+            ListSelectAction::BrewUpgrade => {
+                "↑↓ to select, Space to mark, a to mark all, i to invert, Enter to upgrade the marked packages, Esc to close"
+            }
+            ListSelectAction::BrewInstall => {
+                "/ to search, ↑↓ to select, Space to mark, Enter to install, u to uninstall, Esc to close"
+            }
+        }
+    }
+}
+
+/// Index of a node inside a [`TweakTree`].
+pub type NodeId = usize;
+
+#[derive(Debug, Clone)]
+pub enum NodeKind {
+    Category,
+    Group,
+    Tweak(Box<Tweak>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Node {
+    /// Stable path-like identifier, e.g. `dock/dock-size/dock-size`.
+    pub id: String,
+    pub name: String,
+    pub kind: NodeKind,
+    pub parent: Option<NodeId>,
+    pub children: Vec<NodeId>,
+}
+
+impl Node {
+    pub fn tweak(&self) -> Option<&Tweak> {
+        match &self.kind {
+            NodeKind::Tweak(tweak) => Some(tweak),
+            _ => None,
+        }
+    }
+
+    pub fn is_container(&self) -> bool {
+        !matches!(self.kind, NodeKind::Tweak(_))
+    }
+}
+
+/// The tweak catalog as a tree of categories, groups of any depth and tweaks.
+#[derive(Debug, Clone, Default)]
+pub struct TweakTree {
+    nodes: Vec<Node>,
+    roots: Vec<NodeId>,
+}
+
+impl TweakTree {
+    /// Adds a node below `parent` (or as a root) and returns its index. The
+    /// node id is derived from the parent's id and `slug`.
+    pub fn add(&mut self, parent: Option<NodeId>, slug: &str, name: &str, kind: NodeKind) -> NodeId {
+        let id = match parent {
+            Some(parent) => format!("{}/{}", self.nodes[parent].id, slug),
+            None => slug.to_string(),
+        };
+        let kind = match kind {
+            NodeKind::Tweak(mut tweak) => {
+                tweak.id = id.clone();
+                NodeKind::Tweak(tweak)
+            }
+            other => other,
+        };
+        let index = self.nodes.len();
+        self.nodes.push(Node {
+            id,
             name: name.to_string(),
-            description: description.to_string(),
-            enable_command: enable_command.to_string(),
-            disable_command: disable_command.to_string(),
-            is_enabled,
+            kind,
+            parent,
+            children: Vec::new(),
+        });
+        match parent {
+            Some(parent) => self.nodes[parent].children.push(index),
+            None => self.roots.push(index),
+        }
+        index
+    }
+
+    pub fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id]
+    }
+
+    /// Children of `parent`, or the top-level categories for `None`.
+    pub fn children(&self, parent: Option<NodeId>) -> &[NodeId] {
+        match parent {
+            Some(parent) => &self.nodes[parent].children,
+            None => &self.roots,
+        }
+    }
+
+    /// Number of ancestors above `id`; categories are at depth 0.
+    pub fn depth(&self, id: NodeId) -> usize {
+        let mut depth = 0;
+        let mut current = self.nodes[id].parent;
+        while let Some(parent) = current {
+            depth += 1;
+            current = self.nodes[parent].parent;
+        }
+        depth
+    }
+
+    /// All nodes in depth-first display order.
+    pub fn walk(&self) -> Vec<NodeId> {
+        let mut order = Vec::with_capacity(self.nodes.len());
+        let mut stack: Vec<NodeId> = self.roots.iter().rev().copied().collect();
+        while let Some(id) = stack.pop() {
+            order.push(id);
+            stack.extend(self.nodes[id].children.iter().rev());
         }
+        order
+    }
+
+    /// All tweaks in depth-first display order.
+    pub fn tweaks(&self) -> impl Iterator<Item = &Tweak> + '_ {
+        self.walk().into_iter().filter_map(move |id| self.nodes[id].tweak())
     }
-} 
\ No newline at end of file
+
+    pub fn find_by_id(&self, id: &str) -> Option<NodeId> {
+        self.nodes.iter().position(|node| node.id == id)
+    }
+
+    /// Nodes from the top-level category down to `id`, inclusive.
+    pub fn ancestry(&self, id: NodeId) -> Vec<NodeId> {
+        let mut path = vec![id];
+        while let Some(parent) = self.nodes[path[path.len() - 1]].parent {
+            path.push(parent);
+        }
+        path.reverse();
+        path
+    }
+
+    /// Names from the top-level category down to `id`, e.g.
+    /// `Finder › Finder Appearance › Show Path Bar`.
+    pub fn breadcrumbs(&self, id: NodeId) -> String {
+        let names: Vec<&str> = self.ancestry(id).into_iter().map(|node| self.nodes[node].name.as_str()).collect();
+        names.join(" › ")
+    }
+}
@@ -1,19 +1,32 @@
-use crate::app::{App, Tile};
+use crate::app::{App, OutputOutcome, PackageOperation, PackageState, Tile};
+use crate::brew::{self, Package, PackageKind};
+use crate::config::ColorScheme;
+use crate::param::{self, Param};
+use crate::probe::ProbeState;
+use crate::runner::{self, CommandRequest};
+use crate::snapshot;
+use crate::tweaks::{NodeKind, Tweak, TweakAction, TweakKind};
+use chrono::Local;
 use ratatui::{
     layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Modifier, Style},
-    text::{Line, Span},
+    text::{Line, Span, Text},
     widgets::{
-        Block, Borders, List, ListItem, Paragraph, Wrap,
+        Block, Borders, List, ListItem, Paragraph, Row, Table, Wrap,
     },
     Frame,
 };
+use std::time::Duration;
 
 pub fn ui(f: &mut Frame, app: &mut App) {
     if app.sokoban_game.is_some() {
         render_sokoban_game(f, app);
         return;
     }
+    if app.history_view.is_some() {
+        render_history_view(f, app);
+        return;
+    }
     if app.fullscreen_list.is_some() {
         render_fullscreen_list(f, app);
         return;
@@ -31,8 +44,12 @@ pub fn ui(f: &mut Frame, app: &mut App) {
 
     app.update_status_timer();
 
-    let status_bar_height = if app.text_input_prompt.is_some() {
-        4
+    let status_bar_height = if app.output_view.is_some() {
+        2
+    } else if app.search.is_some() {
+        3
+    } else if app.text_input_prompt.is_some() {
+        4 + picker_param(app).is_some() as u16 + app.text_input_error.is_some() as u16
     } else if app.confirmation_message.is_some() {
         4
     } else if let Some(msg) = &app.status_message {
@@ -57,52 +74,363 @@ pub fn ui(f: &mut Frame, app: &mut App) {
     let header = create_header(app);
     f.render_widget(header, chunks[0]);
 
-    render_main_list(f, app, chunks[1]);
+    if app.output_view.is_some() {
+        render_output_view(f, app, chunks[1]);
+        let status = create_status_bar(app);
+        f.render_widget(status, chunks[2]);
+        return;
+    }
+    let (list_area, detail_area) = split_detail(chunks[1]);
+    if app.search.is_some() {
+        render_search_results(f, app, list_area);
+    } else {
+        render_main_list(f, app, list_area);
+    }
+    if let Some(area) = detail_area {
+        render_detail_pane(f, app, area);
+    }
 
     let status = create_status_bar(app);
     f.render_widget(status, chunks[2]);
 }
 
 fn render_fullscreen_list(f: &mut Frame, app: &mut App) {
-    let list_items_str = app.fullscreen_list.as_ref().unwrap();
-    let items: Vec<ListItem> = list_items_str
-        .iter()
-        .map(|item| {
-            ListItem::new(vec![Line::from(Span::raw(item))])
+    app.update_status_timer();
+    let color_scheme = app.config.get_color_scheme().clone();
+    let Some(list) = &app.fullscreen_list else { return };
+    let running = app.output_view.is_some();
+    // Details of the highlighted search result, when they fit below the table
+    let show_details = list.source.searches() && !list.packages.is_empty() && !running && f.size().height >= 20;
+    let mut constraints = Vec::new();
+    if list.query.is_some() {
+        constraints.push(Constraint::Length(3));
+    }
+    constraints.push(if running { Constraint::Percentage(50) } else { Constraint::Min(0) });
+    if show_details {
+        constraints.push(Constraint::Length(8));
+    }
+    if running {
+        constraints.push(Constraint::Percentage(50));
+    }
+    constraints.push(Constraint::Length(1));
+    let chunks = Layout::default().direction(Direction::Vertical).constraints(constraints).split(f.size());
+    let mut areas = chunks.iter().copied();
+    let query_area = list.query.as_ref().and_then(|_| areas.next());
+    let table_area = areas.next().unwrap_or_default();
+    let details_area = if show_details { areas.next() } else { None };
+    let output_area = if running { areas.next() } else { None };
+    let footer = areas.next().unwrap_or_default();
+    let dim = Style::default().fg(color_scheme.get_color("text_dim"));
+    let text = Style::default().fg(color_scheme.get_color("text"));
+    let primary = Style::default().fg(color_scheme.get_color("primary"));
+
+    if let (Some(query), Some(area)) = (&list.query, query_area) {
+        let (line, title) = if list.search.is_some() {
+            (query.clone(), "Searching…")
+        } else if list.editing_query {
+            (format!("{}█", query), "Search formulae and casks, Enter to search")
+        } else {
+            (query.clone(), "Search, / to change")
+        };
+        let style = if list.editing_query { primary.add_modifier(Modifier::BOLD) } else { text };
+        f.render_widget(Paragraph::new(line).style(style).block(Block::default().borders(Borders::ALL).title(title)), area);
+    }
+
+    let mut title = list.source.title().to_string();
+    if !list.marked.is_empty() {
+        title.push_str(&format!(" ({} selected)", list.marked.len()));
+    }
+    let block = Block::default().borders(Borders::ALL).title(title);
+
+    if let Some((operation, targets)) = &list.confirming {
+        let verb = operation.verb();
+        let verb = format!("{}{}", verb[..1].to_uppercase(), &verb[1..]);
+        let question = match targets.len() {
+            1 => format!("{} this package?", verb),
+            count if *operation == PackageOperation::Upgrade => format!("Upgrade these {} packages with one brew upgrade?", count),
+            count => format!("{} these {} packages?", verb, count),
+        };
+        let width = targets.iter().map(|p| p.name.chars().count()).max().unwrap_or(0);
+        let mut lines = vec![Line::styled(question, primary.add_modifier(Modifier::BOLD)), Line::default()];
+        lines.extend(targets.iter().map(|package| {
+            let mut spans = vec![Span::styled(format!("  {:<width$}  ", package.name), text)];
+            match operation {
+                PackageOperation::Upgrade => {
+                    spans.push(Span::styled(package.installed.join(", "), dim));
+                    spans.push(Span::raw(" → "));
+                    spans.push(Span::styled(
+                        package.latest.clone().unwrap_or_else(|| "latest".to_string()),
+                        Style::default().fg(color_scheme.get_color("success")),
+                    ));
+                }
+                PackageOperation::Install => {
+                    spans.push(Span::styled(format!("{} {}", package.kind.label(), package.latest.as_deref().unwrap_or_default()), dim));
+                }
+                PackageOperation::Uninstall => {
+                    spans.push(Span::styled(format!("{} {}", package.kind.label(), package.installed.join(", ")), dim));
+                }
+            }
+            Line::from(spans)
+        }));
+        f.render_widget(Paragraph::new(lines).block(block), table_area);
+        let hint = Paragraph::new(format!("Enter or y to {}, Esc to go back", operation.verb())).style(primary);
+        f.render_widget(hint, footer);
+        return;
+    }
+
+    if list.packages.is_empty() {
+        let message = match &list.query {
+            Some(_) if list.search.is_some() => "Searching…",
+            Some(query) if query.trim().is_empty() || list.editing_query => "Type a name or part of one and press Enter.",
+            _ => list.source.empty_message(),
+        };
+        f.render_widget(Paragraph::new(message).style(dim).block(block), table_area);
+    } else {
+        let multi_select = list.multi_select();
+        let progress = list.operation.filter(|_| !list.progress.is_empty());
+        let rows: Vec<Row> = list
+            .packages
+            .iter()
+            .map(|package| {
+                let mut cells = Vec::new();
+                if multi_select {
+                    let mark = if !list.markable(package) {
+                        " - "
+                    } else if list.marked.contains(&package.full_name) {
+                        "[x]"
+                    } else {
+                        "[ ]"
+                    };
+                    cells.push(mark.to_string());
+                }
+                cells.extend(package.columns());
+                let mut style = if package.pinned { Style::default().fg(color_scheme.get_color("warning")) } else { Style::default() };
+                if let Some(operation) = progress {
+                    let state = list.package_state(package);
+                    cells.push(state.map_or_else(String::new, |state| state.label(operation)));
+                    match state {
+                        Some(PackageState::Done) => style = Style::default().fg(color_scheme.get_color("success")),
+                        Some(PackageState::Failed) => style = Style::default().fg(color_scheme.get_color("error")),
+                        _ => {}
+                    }
+                }
+                Row::new(cells).style(style)
+            })
+            .collect();
+        // Dependencies take whatever width is left
+        let mut widths = vec![
+            Constraint::Length(24),
+            Constraint::Length(8),
+            Constraint::Length(16),
+            Constraint::Length(16),
+            Constraint::Length(6),
+            Constraint::Length(20),
+            Constraint::Min(10),
+        ];
+        let mut header: Vec<&str> = brew::COLUMNS.to_vec();
+        if multi_select {
+            widths.insert(0, Constraint::Length(3));
+            header.insert(0, "");
+        }
+        if progress.is_some() {
+            widths.push(Constraint::Length(14));
+            header.push("Status");
+        }
+
+        let table = Table::new(rows, widths)
+            .header(Row::new(header).style(dim.add_modifier(Modifier::BOLD)))
+            .block(block)
+            .highlight_style(primary.add_modifier(Modifier::BOLD))
+            .highlight_symbol("> ");
+        if let Some(list) = &mut app.fullscreen_list {
+            f.render_stateful_widget(table, table_area, &mut list.table_state);
+        }
+    }
+
+    let Some(list) = &app.fullscreen_list else { return };
+    if let Some(area) = details_area {
+        render_package_details(f, list.selected(), &color_scheme, area);
+    }
+    if let Some(area) = output_area {
+        render_output_view(f, app, area);
+        f.render_widget(create_status_bar(app), footer);
+    } else if let Some(search) = &list.search {
+        let elapsed = search.started.elapsed();
+        let line = format!(
+            "{} Searching for '{}' ({:.1}s), Esc to cancel",
+            spinner(elapsed),
+            search.query,
+            elapsed.as_secs_f32()
+        );
+        f.render_widget(Paragraph::new(line).style(primary), footer);
+    } else if let Some(message) = &app.status_message {
+        f.render_widget(Paragraph::new(message.as_str()).style(Style::default().fg(color_scheme.get_color("warning"))), footer);
+    } else {
+        f.render_widget(Paragraph::new(list.on_select.hint()).style(primary), footer);
+    }
+}
+
+/// Description, versions, tap and dependencies of the package highlighted
+/// in a search list.
+fn render_package_details(f: &mut Frame, package: Option<&Package>, color_scheme: &ColorScheme, area: Rect) {
+    let label = Style::default().fg(color_scheme.get_color("secondary")).add_modifier(Modifier::BOLD);
+    let text = Style::default().fg(color_scheme.get_color("text"));
+    let field = |name: &str, value: String| Line::from(vec![Span::styled(format!("{}: ", name), label), Span::styled(value, text)]);
+    let Some(package) = package else {
+        f.render_widget(Block::default().borders(Borders::ALL), area);
+        return;
+    };
+    let installed = match package.installed.as_slice() {
+        [] => "not installed".to_string(),
+        versions if package.outdated => format!("{} (outdated)", versions.join(", ")),
+        versions => versions.join(", "),
+    };
+    let lines = vec![
+        Line::styled(package.description.clone().unwrap_or_else(|| "No description.".to_string()), text),
+        field("Installed", installed),
+        field("Latest", package.latest.clone().unwrap_or_else(|| "unknown".to_string())),
+        field("Tap", package.tap.clone().unwrap_or_else(|| "-".to_string())),
+        field("Depends on", if package.dependencies.is_empty() { "nothing".to_string() } else { package.dependencies.join(", ") }),
+    ];
+    let title = format!("{} ({})", package.full_name, package.kind.label());
+    let details = Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title)).wrap(Wrap { trim: true });
+    f.render_widget(details, area);
+}
+
+fn render_history_view(f: &mut Frame, app: &mut App) {
+    let color_scheme = app.config.get_color_scheme().clone();
+    let Some(view) = &app.history_view else { return };
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(8)].as_ref())
+        .split(f.size());
+
+    let items: Vec<ListItem> = view
+        .visible()
+        .into_iter()
+        .map(|entry| {
+            let (mark, color) = if entry.succeeded() { ("✓", "success") } else { ("✗", "error") };
+            let mut spans = vec![
+                Span::styled(format!("{} ", mark), Style::default().fg(color_scheme.get_color(color))),
+                Span::styled(format!("{}  ", entry.local_time()), Style::default().fg(color_scheme.get_color("text_dim"))),
+                Span::styled(
+                    format!("{} > {}", app.category_name(entry.category()), entry.tweak_name),
+                    Style::default().fg(color_scheme.get_color("text")),
+                ),
+            ];
+            if entry.sudo {
+                spans.push(Span::styled(" (sudo)", Style::default().fg(color_scheme.get_color("warning"))));
+            }
+            ListItem::new(Line::from(spans))
         })
         .collect();
 
-    let list_widget = List::new(items)
-        .block(Block::default().borders(Borders::ALL).title(app.fullscreen_list_title.clone()))
-        .highlight_style(Style::default().fg(app.config.get_color_scheme().get_color("primary")).add_modifier(Modifier::BOLD))
+    let category = view.category.map_or("all", |index| app.category_name(&view.categories[index]));
+    let title = format!(
+        "History - category: {} (c), outcome: {} (o), Esc to close",
+        category,
+        view.outcome.label()
+    );
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .highlight_style(Style::default().fg(color_scheme.get_color("primary")).add_modifier(Modifier::BOLD))
         .highlight_symbol("> ");
 
-    f.render_stateful_widget(list_widget, f.size(), &mut app.fullscreen_list_state);
+    let details = match view.selected() {
+        Some(entry) => {
+            let mut text = format!("$ {}\n{}", entry.command, entry.outcome_label());
+            if !entry.stdout.is_empty() {
+                text.push_str(&format!("\nstdout: {}", entry.stdout));
+            }
+            if !entry.stderr.is_empty() {
+                text.push_str(&format!("\nstderr: {}", entry.stderr));
+            }
+            text
+        }
+        None => "No matching history.".to_string(),
+    };
+    let details = Paragraph::new(details)
+        .style(Style::default().fg(color_scheme.get_color("text")))
+        .block(Block::default().borders(Borders::ALL))
+        .wrap(Wrap { trim: true });
+
+    let mut list_state = view.list_state.clone();
+    f.render_stateful_widget(list, chunks[0], &mut list_state);
+    f.render_widget(details, chunks[1]);
+    if let Some(view) = &mut app.history_view {
+        view.list_state = list_state;
+    }
 }
 
-fn create_header(app: &App) -> Paragraph {
-    let header_text = format!("macOS-tweaks");
-    
-    Paragraph::new(header_text)
-        .style(Style::default().fg(app.config.get_color_scheme().get_color("primary")).add_modifier(Modifier::BOLD))
+fn create_header(app: &App) -> Paragraph<'_> {
+    let color_scheme = app.config.get_color_scheme();
+    let mut spans = vec![Span::styled(
+        "macOS-tweaks",
+        Style::default().fg(color_scheme.get_color("primary")).add_modifier(Modifier::BOLD),
+    )];
+    if app.dry_run {
+        spans.push(Span::styled(
+            " [DRY RUN]",
+            Style::default().fg(color_scheme.get_color("warning")).add_modifier(Modifier::BOLD),
+        ));
+    }
+    if let Some((name, _)) = &app.drift_profile {
+        let drifted = app.drifted_ids().len();
+        let color = if drifted > 0 { "warning" } else { "success" };
+        spans.push(Span::styled(
+            format!(" [vs {}: {} drifted]", name, drifted),
+            Style::default().fg(color_scheme.get_color(color)),
+        ));
+    }
+    if !app.marked_tweaks.is_empty() {
+        spans.push(Span::styled(
+            format!(" [{} selected]", app.marked_tweaks.len()),
+            Style::default().fg(color_scheme.get_color("accent")),
+        ));
+    }
+
+    Paragraph::new(Line::from(spans))
         .alignment(ratatui::layout::Alignment::Center)
 }
 
 fn render_main_list(f: &mut Frame, app: &mut App, area: Rect) {
     let color_scheme = app.config.get_color_scheme();
+    let drifted = app.drifted_ids();
     let list_items: Vec<ListItem> = app.get_current_list_items()
         .into_iter()
-        .map(|name| {
-            let style = if app.view_level == 1 && !name.starts_with("  ") { // Sub-category
+        .map(|id| {
+            let node = app.tree.node(id);
+            let mut style = if matches!(node.kind, NodeKind::Group) {
                 Style::default().fg(color_scheme.get_color("secondary")).add_modifier(Modifier::BOLD)
             } else { // Top-level category or tweak option
                 Style::default().fg(color_scheme.get_color("text_dim"))
             };
-            let owned_name = name.trim().to_string();
-            
-            let mut spans = vec![Span::styled(owned_name, style)];
-            
-            if app.applied_tweaks.contains(&name) {
+            // Drifted tweaks, and the categories and groups leading to them
+            let prefix = format!("{}/", node.id);
+            if drifted.iter().any(|d| *d == node.id || d.starts_with(&prefix)) {
+                style = style.fg(color_scheme.get_color("warning"));
+            }
+
+            let mut spans = Vec::new();
+            let marked = app.marked_tweaks.iter().enumerate().find(|(_, (id, _))| id == &node.id);
+            if let Some((position, _)) = marked {
+                spans.push(Span::styled(format!("{}. ", position + 1), Style::default().fg(color_scheme.get_color("accent"))));
+            }
+            spans.push(Span::styled(node.name.clone(), style));
+            if let Some((_, (_, Some(value)))) = marked {
+                spans.push(Span::styled(format!(" = {}", value), Style::default().fg(color_scheme.get_color("accent"))));
+            }
+
+            if let Some(state) = app.probe_states.get(&node.id) {
+                let color = match state {
+                    ProbeState::On => "success",
+                    ProbeState::Off => "text_dim",
+                    ProbeState::Value(_) => "accent",
+                    ProbeState::Unknown => "warning",
+                };
+                spans.push(Span::styled(format!(" [{}]", state.label()), Style::default().fg(color_scheme.get_color(color))));
+            } else if app.applied_tweaks.contains(&node.id) {
                 spans.push(Span::styled(" ✗", Style::default().fg(color_scheme.get_color("success"))));
             }
 
@@ -114,42 +442,265 @@ fn render_main_list(f: &mut Frame, app: &mut App, area: Rect) {
         .highlight_style(Style::default().fg(color_scheme.get_color("primary")).add_modifier(Modifier::BOLD))
         .highlight_symbol("> ");
 
-    let state = if app.view_level == 0 {
-        &mut app.category_list_state
+    f.render_stateful_widget(list, area, &mut app.list_state);
+}
+
+/// Search matches as a flat list with breadcrumbs, the matched characters
+/// of each name highlighted.
+fn render_search_results(f: &mut Frame, app: &mut App, area: Rect) {
+    let color_scheme = app.config.get_color_scheme().clone();
+    let Some(view) = &mut app.search else { return };
+
+    let items: Vec<ListItem> = view
+        .hits
+        .iter()
+        .map(|hit| {
+            let path = app.tree.ancestry(hit.node);
+            let mut spans: Vec<Span> = path[..path.len() - 1]
+                .iter()
+                .map(|&id| Span::styled(format!("{} › ", app.tree.node(id).name), Style::default().fg(color_scheme.get_color("text_dim"))))
+                .collect();
+            let name = &app.tree.node(hit.node).name;
+            for (index, c) in name.chars().enumerate() {
+                let style = if hit.name_matches.contains(&index) {
+                    Style::default().fg(color_scheme.get_color("accent")).add_modifier(Modifier::BOLD)
+                } else {
+                    Style::default().fg(color_scheme.get_color("text"))
+                };
+                spans.push(Span::styled(c.to_string(), style));
+            }
+            if app.applied_tweaks.contains(&app.tree.node(hit.node).id) {
+                spans.push(Span::styled(" ✗", Style::default().fg(color_scheme.get_color("success"))));
+            }
+            ListItem::new(Line::from(spans))
+        })
+        .collect();
+
+    let title = match view.hits.len() {
+        _ if view.query.trim().is_empty() => "Search tweak names and descriptions".to_string(),
+        0 => "No matches".to_string(),
+        1 => "1 match".to_string(),
+        count => format!("{} matches", count),
+    };
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .highlight_style(Style::default().fg(color_scheme.get_color("primary")).add_modifier(Modifier::BOLD))
+        .highlight_symbol("> ");
+
+    f.render_stateful_widget(list, area, &mut view.list_state);
+}
+
+/// The frame of a spinner for something that has been running for `elapsed`.
+fn spinner(elapsed: Duration) -> &'static str {
+    const SPINNER: [&str; 10] = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+    SPINNER[(elapsed.as_millis() / 100) as usize % SPINNER.len()]
+}
+
+/// Output of the commands running in the background, following the end
+/// unless scrolled back, with a spinner and the elapsed time in the title.
+fn render_output_view(f: &mut Frame, app: &App, area: Rect) {
+    let color_scheme = app.config.get_color_scheme();
+    let Some(view) = &app.output_view else { return };
+
+    let elapsed = view.elapsed();
+    let (title, color) = match &view.finished {
+        None => (format!("{} {} ({:.1}s)", spinner(elapsed), view.label, elapsed.as_secs_f32()), "primary"),
+        Some((OutputOutcome::Succeeded, _)) => (format!("✓ {} finished in {:.1}s", view.label, elapsed.as_secs_f32()), "success"),
+        Some((OutputOutcome::Failed(e), _)) => (format!("✗ {} failed after {:.1}s: {}", view.label, elapsed.as_secs_f32(), e), "error"),
+        Some((OutputOutcome::Canceled, _)) => (format!("✗ {} canceled after {:.1}s", view.label, elapsed.as_secs_f32()), "warning"),
+        Some((OutputOutcome::TimedOut(timeout), _)) => {
+            (format!("✗ {} timed out after {}", view.label, runner::format_timeout(*timeout)), "error")
+        }
+    };
+
+    let height = area.height.saturating_sub(3) as usize;
+    let end = view.lines.len().saturating_sub(view.scroll_back);
+    let start = end.saturating_sub(height);
+    let mut lines = vec![Line::styled(format!("$ {}", view.command), Style::default().fg(color_scheme.get_color("text_dim")))];
+    lines.extend(view.lines[start..end].iter().map(|(text, stderr)| {
+        let color = if *stderr { "warning" } else { "text" };
+        Line::styled(text.clone(), Style::default().fg(color_scheme.get_color(color)))
+    }));
+
+    let paragraph = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(Span::styled(title, Style::default().fg(color_scheme.get_color(color)).add_modifier(Modifier::BOLD))),
+    );
+    f.render_widget(paragraph, area);
+}
+
+/// Splits the list area to make room for the detail pane: to the right on
+/// wide terminals, below the list on tall ones, and not at all otherwise.
+fn split_detail(area: Rect) -> (Rect, Option<Rect>) {
+    let (direction, constraints) = if area.width >= 100 {
+        (Direction::Horizontal, [Constraint::Percentage(55), Constraint::Percentage(45)])
+    } else if area.height >= 24 {
+        (Direction::Vertical, [Constraint::Min(0), Constraint::Length(12)])
     } else {
-        &mut app.tweak_list_state
+        return (area, None);
     };
+    let chunks = Layout::default().direction(direction).constraints(constraints).split(area);
+    (chunks[0], Some(chunks[1]))
+}
 
-    f.render_stateful_widget(list, area, state);
+/// Describes the highlighted item: what a tweak does, the commands it runs
+/// and what is known about its state, or what a category contains.
+fn render_detail_pane(f: &mut Frame, app: &App, area: Rect) {
+    let color_scheme = app.config.get_color_scheme();
+    let label = Style::default().fg(color_scheme.get_color("secondary")).add_modifier(Modifier::BOLD);
+    let text = Style::default().fg(color_scheme.get_color("text"));
+    let dim = Style::default().fg(color_scheme.get_color("text_dim"));
+    let field = |name: &str, value: String, style: Style| {
+        Line::from(vec![Span::styled(format!("{}: ", name), label), Span::styled(value, style)])
+    };
+
+    let Some(id) = app.detail_node() else {
+        f.render_widget(Block::default().borders(Borders::ALL), area);
+        return;
+    };
+    let node = app.tree.node(id);
+    let mut lines = Vec::new();
+    match node.tweak() {
+        Some(tweak) => {
+            if !tweak.description.is_empty() {
+                lines.push(Line::styled(tweak.description.clone(), text));
+                lines.push(Line::default());
+            }
+            let mut kind = tweak.kind.label().to_string();
+            if let Some(flags) = tweak.flags_label() {
+                kind.push_str(&format!(" ({})", flags));
+            }
+            let kind_style = if tweak.destructive || tweak.requires_root {
+                Style::default().fg(color_scheme.get_color("warning"))
+            } else {
+                text
+            };
+            lines.push(field("Kind", kind, kind_style));
+
+            if let Some(probe) = &tweak.probe {
+                let state = match app.probe_states.get(&tweak.id) {
+                    Some(state) => state.label().to_string(),
+                    None => "not probed yet".to_string(),
+                };
+                let host = if probe.current_host { "-currentHost " } else { "" };
+                lines.push(field("State", state, text));
+                lines.push(Line::styled(format!("  read with: defaults {}read {} {}", host, probe.domain, probe.key), dim));
+            }
+            let last_applied = match app.last_applied.get(&tweak.id) {
+                Some(time) => time.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string(),
+                None => "never".to_string(),
+            };
+            lines.push(field("Last applied", last_applied, text));
+            let timeout = app.timeout_for(tweak).map_or_else(|| "none".to_string(), runner::format_timeout);
+            lines.push(field("Timeout", timeout, text));
+            if let Some(reason) = app.forbidden_reason(tweak) {
+                lines.push(Line::styled(reason, Style::default().fg(color_scheme.get_color("error"))));
+            }
+            lines.push(Line::styled("Apply:", label));
+            let commands = apply_commands(tweak);
+            if commands.is_empty() {
+                lines.push(Line::styled("  built into macos-tweaks", dim));
+            }
+            lines.extend(commands.into_iter().map(|command| Line::styled(format!("  $ {}", command), text)));
+            if tweak.kind != TweakKind::Query {
+                lines.push(Line::styled("Revert:", label));
+                let keys = snapshot::written_keys(&tweak.action);
+                if !keys.is_empty() {
+                    let keys: Vec<String> = keys.iter().map(|key| format!("{} {}", key.domain, key.key)).collect();
+                    lines.push(Line::styled(format!("  restores the saved value of {}", keys.join(", ")), dim));
+                    if !tweak.disable_command.is_empty() {
+                        lines.push(Line::styled("  or, without a saved value:", dim));
+                    }
+                }
+                if !tweak.disable_command.is_empty() {
+                    lines.push(Line::styled(format!("  $ {}", tweak.disable_command), text));
+                } else if keys.is_empty() {
+                    lines.push(Line::styled("  cannot be reverted", dim));
+                }
+            }
+        }
+        None => {
+            let children = app.tree.children(Some(id));
+            let count = match children.len() {
+                1 => "1 item".to_string(),
+                count => format!("{} items", count),
+            };
+            lines.push(field("Contains", count, text));
+            lines.extend(children.iter().map(|&child| Line::styled(format!("  {}", app.tree.node(child).name), dim)));
+        }
+    }
+
+    let paragraph = Paragraph::new(lines)
+        .block(Block::default().borders(Borders::ALL).title(node.name.clone()))
+        .wrap(Wrap { trim: false });
+    f.render_widget(paragraph, area);
+}
+
+/// The commands applying `tweak` runs, with placeholders for values the
+/// user provides. Empty for builtins.
+fn apply_commands(tweak: &Tweak) -> Vec<String> {
+    match &tweak.action {
+        TweakAction::Shell { command } => vec![command.clone()],
+        TweakAction::PromptThenRun { param, run, .. } => {
+            let hint = format!("<{}>", param.hint());
+            param::commands_for(run, &hint).iter().map(CommandRequest::display).collect()
+        }
+        TweakAction::Builtin { .. } => Vec::new(),
+        TweakAction::OpenList { source, on_select } => {
+            let listing = source.command().map_or_else(|| "brew search <query>".to_string(), str::to_string);
+            vec![listing, on_select.command_for(PackageKind::Formula, "<selected package>").display()]
+        }
+    }
 }
 
-fn create_status_bar(app: &App) -> Paragraph {
+fn create_status_bar(app: &App) -> Paragraph<'_> {
     let color_scheme = app.config.get_color_scheme();
-    let (status_text, style) = if let Some(prompt) = &app.text_input_prompt {
+    let (status_text, style): (Text, Style) = if let Some(view) = &app.output_view {
+        let hint = if view.is_running() {
+            "c to cancel, ↑↓ or PgUp/PgDn to scroll, End to follow the output"
+        } else {
+            "↑↓ or PgUp/PgDn to scroll, any other key to close"
+        };
+        (hint.into(), Style::default().fg(color_scheme.get_color("primary")))
+    } else if let Some(view) = &app.search {
+        (
+            Text::from(vec![
+                Line::from(format!("Search: {}", view.query)),
+                Line::from("↑↓ to select, Enter to apply, Tab to show in the list, Esc to close"),
+            ]),
+            Style::default().fg(color_scheme.get_color("primary")).add_modifier(Modifier::BOLD),
+        )
+    } else if let Some(prompt) = &app.text_input_prompt {
+        let mut lines = vec![Line::from(format!("{} (Enter to confirm, Esc to cancel)", prompt))];
+        if let Some(param) = picker_param(app) {
+            lines.push(Line::from(value_picker(param, &app.input_buffer)));
+        }
+        lines.push(Line::from(format!("Input: {}", app.input_buffer)));
+        if let Some(error) = &app.text_input_error {
+            lines.push(Line::styled(error.clone(), Style::default().fg(color_scheme.get_color("error"))));
+        }
         (
-            format!("{} (Enter to confirm, Esc to cancel)\nInput: {}", prompt, app.input_buffer),
+            Text::from(lines),
             Style::default().fg(color_scheme.get_color("primary")).add_modifier(Modifier::BOLD),
         )
     } else if let Some(confirmation) = &app.confirmation_message {
         (
-            format!("{}\nInput: {}", confirmation, app.input_buffer),
+            format!("{}\nInput: {}", confirmation, app.input_buffer).into(),
             Style::default().fg(color_scheme.get_color("error")).add_modifier(Modifier::BOLD),
         )
     } else if let Some(message) = &app.status_message {
-        (message.clone(), Style::default().fg(color_scheme.get_color("primary")))
+        (message.clone().into(), Style::default().fg(color_scheme.get_color("primary")))
     } else {
         (
-            match app.view_level {
-                0 => "Navigation: ↑↓ to select, → or Enter to view category, q to quit".to_string(),
-                1 => {
-                    if app.viewing_sub_category.is_some() {
-                        "Navigation: ↑↓ to select, Enter to apply, ← to go back, q to quit".to_string()
-                    } else {
-                        "Navigation: ↑↓ to select, → or Enter to view options, ← to go back, q to quit".to_string()
-                    }
-                },
-                _ => "".to_string(),
-            },
+            if app.current_parent.is_none() {
+                "Navigation: ↑↓ to select, → or Enter to view category, / to search, r to refresh, d for dry run, h for history, p to save a profile, c to compare with one, q to quit".to_string()
+            } else if app.get_selected_item().is_some_and(|id| app.tree.node(id).is_container()) {
+                "Navigation: ↑↓ to select, → or Enter to view options, ← to go back, q to quit".to_string()
+            } else {
+                "Navigation: ↑↓ to select, Enter to apply, u to undo, Space to select for a batch, a to apply the batch, ← to go back, q to quit".to_string()
+            }
+            .into(),
             Style::default().fg(color_scheme.get_color("primary")),
         )
     };
@@ -160,6 +711,27 @@ fn create_status_bar(app: &App) -> Paragraph {
         .wrap(Wrap { trim: true })
 }
 
+/// The param being entered, if it can be stepped through with ←/→.
+fn picker_param(app: &App) -> Option<&Param> {
+    app.text_input_tweak
+        .as_ref()
+        .and_then(|tweak| tweak.action.param())
+        .filter(|param| param.is_steppable())
+}
+
+/// A slider for numeric params, or the choice between arrows for enums.
+fn value_picker(param: &Param, value: &str) -> String {
+    const WIDTH: usize = 30;
+    match param.position(value) {
+        Some(position) => {
+            let filled = (position * WIDTH as f64).round() as usize;
+            format!("◀ {}●{} ▶", "━".repeat(filled), "─".repeat(WIDTH - filled))
+        }
+        None if value.is_empty() => "◀ ← → to choose ▶".to_string(),
+        None => format!("◀ {} ▶", value),
+    }
+}
+
 fn render_sokoban_game(f: &mut Frame, app: &mut App) {
     let game = app.sokoban_game.as_mut().unwrap();
     let color_scheme = app.config.get_color_scheme();